@@ -1,16 +1,35 @@
 use crate::rm::record_manager::RecordManager;
 use crate::logger::logger::RuaResult;
 use crate::sm::system_manager::SystemManager;
+use crate::sm::check;
+use crate::rm::record::Type;
 use crate::parser::ast::*;
 use crate::logger::logger::*;
 use crate::parser::sql;
+use crate::parser::tok;
 use std::cell::RefCell;
 use std::rc::Rc;
 use std::io;
+use std::collections::HashMap;
 
 pub struct Executor {
     sm: Rc<RefCell<SystemManager>>,
     rm: Rc<RefCell<RecordManager>>,
+    // the lines of a statement the REPL has not seen the end of yet
+    pending: RefCell<String>,
+}
+
+// a statement that is parsed and checked once, then executed with different values for its placeholders
+pub struct PreparedStmt {
+    stmt: Stmt,
+    param_count: usize,
+    param_types: HashMap<usize, Vec<(Type, bool)>>,
+}
+
+impl PreparedStmt {
+    pub fn param_count(&self) -> usize {
+        self.param_count
+    }
 }
 
 impl Executor {
@@ -20,6 +39,7 @@ impl Executor {
         Self {
             rm: rm,
             sm: sm,
+            pending: RefCell::new(String::new()),
         }
     }
 
@@ -39,19 +59,26 @@ impl Executor {
             Stmt::Table(ref s) => {
                 match s {
                     TableStmt::CreateTable { tb_name, field_list } => sm.create_table(&tb_name, &field_list),
+                    TableStmt::CreateTableAs { tb_name, query } => sm.create_table_as(&tb_name, &query),
+                    TableStmt::CreateView { view_name, query, definition } => sm.create_view(&view_name, &query, &definition),
+                    TableStmt::DropView { view_name } => sm.drop_view(&view_name),
+                    TableStmt::CreateSequence { seq_name, increment, start } => sm.create_sequence(&seq_name, &increment, &start),
+                    TableStmt::DropSequence { seq_name } => sm.drop_sequence(&seq_name),
                     TableStmt::DropTable { tb_name } => sm.drop_table(&tb_name),
                     TableStmt::Desc { tb_name } => sm.desc(&tb_name),
-                    TableStmt::Insert { tb_name, value_lists } => sm.insert(&tb_name, &value_lists),
-                    TableStmt::Select { table_list, selector, where_clause } => sm.select(&table_list, &selector, &where_clause),
+                    TableStmt::Insert { tb_name, column_list, value_lists } => sm.insert(&tb_name, &column_list, &value_lists),
+                    TableStmt::InsertSelect { tb_name, column_list, query } => sm.insert_select(&tb_name, &column_list, &query),
+                    TableStmt::Select { distinct, table_list, join_list, selector, where_clause, group_clause, having_clause, order_clause, limit_clause } => sm.select(&distinct, &table_list, &join_list, &selector, &where_clause, &group_clause, &having_clause, &order_clause, &limit_clause),
+                    TableStmt::Compound { op, all, left, right, order_clause, limit_clause } => sm.compound(&op, &all, &left, &right, &order_clause, &limit_clause),
                     TableStmt::Delete { tb_name, where_clause } => sm.delete(&tb_name, &where_clause),
                     TableStmt::Update { tb_name, set_clause, where_clause } => sm.update(&tb_name, &set_clause, &where_clause),
                 }
             },
             Stmt::Index(ref s) => {
                 match s {
-                    IndexStmt::CreateIndex { idx_name, tb_name, column_list } => sm.create_index(&idx_name, &tb_name, &column_list),
+                    IndexStmt::CreateIndex { idx_name, tb_name, column_list, unique } => sm.create_index(&idx_name, &tb_name, &column_list, *unique),
                     IndexStmt::DropIndex { idx_name, tb_name } => sm.drop_index(&idx_name, &tb_name),
-                    IndexStmt::AlterAddIndex { idx_name, tb_name, column_list } => sm.create_index(&idx_name, &tb_name, &column_list),
+                    IndexStmt::AlterAddIndex { idx_name, tb_name, column_list, unique } => sm.create_index(&idx_name, &tb_name, &column_list, *unique),
                     IndexStmt::AlterDropIndex { idx_name, tb_name } => sm.drop_index(&idx_name, &tb_name),
                 }
             },
@@ -62,11 +89,15 @@ impl Executor {
                     AlterStmt::ChangeColumn { tb_name, col_name, field } => sm.change_column(&tb_name, &col_name, &field),
                     AlterStmt::RenameTable { tb_name, new_name } => sm.rename_table(&tb_name, &new_name),
                     AlterStmt::AddPrimaryKey { tb_name, column_list } => sm.add_primary_key(&tb_name, &column_list),
+                    AlterStmt::AddUnique { tb_name, column_list } => sm.add_unique(&tb_name, &column_list),
                     AlterStmt::DropPrimaryKey { tb_name } => sm.drop_primary_key(&tb_name),
                     AlterStmt::AddConstraintPrimaryKey { tb_name, pk_name, column_list } => sm.add_constraint_primary_key(&tb_name, &pk_name, &column_list),
                     AlterStmt::DropConstraintPrimaryKey { tb_name, pk_name } => sm.drop_constraint_primary_key(&tb_name, &pk_name),
-                    AlterStmt::AddConstraintForeignKey { tb_name, fk_name, column_list, foreign_tb_name, foreign_column_list } => sm.add_constraint_foreign_key(&tb_name, &fk_name, &column_list, &foreign_tb_name, &foreign_column_list),
+                    AlterStmt::AddConstraintForeignKey { tb_name, fk_name, column_list, foreign_tb_name, foreign_column_list, on_delete, on_update } => sm.add_constraint_foreign_key(&tb_name, &fk_name, &column_list, &foreign_tb_name, &foreign_column_list, *on_delete, *on_update),
                     AlterStmt::DropConstraintForeignKey { tb_name, fk_name } => sm.drop_constraint_foreign_key(&tb_name, &fk_name),
+                    AlterStmt::AddConstraintCheck { tb_name, ck_name, check } => sm.add_constraint_check(&tb_name, &ck_name, &check),
+                    AlterStmt::DropConstraintCheck { tb_name, ck_name } => sm.drop_constraint_check(&tb_name, &ck_name),
+                    AlterStmt::DropConstraint { tb_name, name } => sm.drop_constraint(&tb_name, &name),
                 }
             },
             Stmt::Copy(ref s) => {
@@ -74,6 +105,7 @@ impl Executor {
                     CopyStmt { tb_name, path } => sm.copy(&tb_name, &path),
                 }
             },
+            Stmt::Explain(ExplainStmt { analyze, stmt }) => sm.explain(&analyze, &stmt),
             _ => unreachable!(),
         }
     }
@@ -86,21 +118,126 @@ impl Executor {
         self.process(stmt, true)
     }
 
+    // the trailing semicolon of the statement is optional
+    pub fn prepare(&self, input: &str) -> Result<PreparedStmt, RuaResult> {
+        let input = input.trim_end();
+        let input = if input.ends_with(';') {input.to_string()} else {format!("{};", input)};
+        let mut stmt_list = match sql::parse_sql(&input) {
+            Ok(sql) => sql.stmt_list,
+            Err(_) => return Err(RuaResult::err(String::from("Invalid syntax"))),
+        };
+        if stmt_list.len() != 1 {
+            return Err(RuaResult::err("only a single statement can be prepared".to_string()));
+        }
+        let mut stmt = stmt_list.pop().unwrap();
+        let param_count = match stmt.number_params() {
+            Some(count) => count,
+            None => return Err(RuaResult::err("`?` cannot be mixed with numbered placeholders".to_string())),
+        };
+        let query = match &stmt {
+            Stmt::Table(query) | Stmt::Explain(ExplainStmt { analyze: _, stmt: query }) => match query {
+                TableStmt::Select { .. } | TableStmt::Compound { .. } | TableStmt::Insert { .. } |
+                TableStmt::InsertSelect { .. } | TableStmt::Update { .. } | TableStmt::Delete { .. } => Some(query),
+                _ => None,
+            },
+            _ => None,
+        };
+        if query.is_none() && param_count > 0 {
+            return Err(RuaResult::err("placeholders are only allowed in select, insert, update and delete".to_string()));
+        }
+
+        self.sm.borrow_mut().set_prepare(true);
+        let res = self.check(&stmt);
+        self.sm.borrow_mut().set_prepare(false);
+        if res.is_err() {
+            return Err(res);
+        }
+        let param_types = match query {
+            Some(query) => check::param_types(query, &self.sm.borrow()),
+            None => HashMap::new(),
+        };
+        Ok(PreparedStmt {
+            stmt: stmt,
+            param_count: param_count,
+            param_types: param_types,
+        })
+    }
+
+    // gives the statement with the n-th value in place of placeholder n
+    pub fn bind(&self, prepared: &PreparedStmt, values: &Vec<Value>) -> Result<Stmt, RuaResult> {
+        if values.len() != prepared.param_count {
+            return Err(RuaResult::err(format!("expected {} values, got {}", prepared.param_count, values.len())));
+        }
+        for (i, value) in values.iter().enumerate() {
+            let fits = |(ty, can_be_null): &(Type, bool)| if value.is_null() {*can_be_null} else {ty.valid_value(value)};
+            let valid = match value {
+                Value::Param(_) => false,
+                _ => prepared.param_types.get(&(i + 1)).map_or(true, |types| types.iter().all(fits)),
+            };
+            if !valid {
+                return Err(RuaResult::err(format!("invalid value {} for placeholder {}", value.name(), i + 1)));
+            }
+        }
+        let mut stmt = prepared.stmt.clone();
+        for value in stmt.values_mut() {
+            if let Value::Param(n) = *value {
+                *value = values[n - 1].clone();
+            }
+        }
+        Ok(stmt)
+    }
+
+    // the checks of a write read the rows it touches, so they are repeated with the bound values
+    pub fn execute_prepared(&self, prepared: &PreparedStmt, values: &Vec<Value>) -> RuaResult {
+        let stmt = match self.bind(prepared, values) {
+            Ok(stmt) => stmt,
+            Err(res) => return res,
+        };
+        match &stmt {
+            Stmt::Table(TableStmt::Insert { .. }) | Stmt::Table(TableStmt::InsertSelect { .. }) |
+            Stmt::Table(TableStmt::Update { .. }) | Stmt::Table(TableStmt::Delete { .. }) => {
+                let res = self.check(&stmt);
+                if res.is_err() {
+                    return res;
+                }
+            },
+            _ => {},
+        }
+        self.execute(&stmt)
+    }
+
+    // every statement is parsed on its own, so a syntax error only loses the statement it is in
     pub fn process_string(&self, input: &String, logger: &RuaLogger) {
-        match sql::parse_sql(&input) {
-            Ok(sql) => {
-                for stmt in &sql.stmt_list {
-                    let res = self.check(stmt);
-                    if res.is_ok() {
-                        logger.log(&self.execute(stmt));
-                    }
-                    else {
-                        logger.log(&res);
+        let (stmts, rest) = tok::split_statements(input);
+        let mut start = 0;
+        for text in stmts.into_iter().chain(Some(rest)) {
+            let stmt = start..start + text.len();
+            start = stmt.end;
+            // the end of the input ends the last statement even without its semicolon
+            let terminated = format!("{}\n;", text);
+            let parsed = match sql::parse_sql(text) {
+                Err(_) if stmt.end == input.len() && sql::parse_sql(&terminated).is_ok() => sql::parse_sql(&terminated),
+                parsed => parsed,
+            };
+            match parsed {
+                Ok(mut sql) => {
+                    for stmt in &mut sql.stmt_list {
+                        if stmt.number_params() != Some(0) {
+                            logger.log(&RuaResult::err("placeholders need a prepared statement".to_string()));
+                            continue;
+                        }
+                        let res = self.check(stmt);
+                        if res.is_ok() {
+                            logger.log(&self.execute(stmt));
+                        }
+                        else {
+                            logger.log(&res);
+                        }
                     }
+                },
+                Err(e) => {
+                    logger.log(&RuaResult::err(sql::describe_error(input, stmt, &e)));
                 }
-            },
-            Err(e) => {
-                logger.log(&RuaResult::err(String::from("Invalid syntax")));
             }
         }
     }
@@ -111,15 +248,35 @@ impl Executor {
         }
     }
 
+    // runs the statements of `buffer` that are complete and leaves the rest in it
+    fn process_complete(&self, buffer: &mut String, logger: &RuaLogger) {
+        let (_, rest) = tok::split_statements(buffer);
+        let complete = buffer[..buffer.len() - rest.len()].to_string();
+        let rest = rest.trim_start().to_string();
+        self.process_string(&complete, logger);
+        *buffer = rest;
+    }
+
+    pub fn is_pending(&self) -> bool {
+        !self.pending.borrow().trim().is_empty()
+    }
+
     pub fn process_from_stdin(&self, logger: &RuaLogger) -> bool {
         let mut input = String::new();
-        io::stdin().read_line(&mut input).expect("Failed to read line.");
-        if input.trim() == "exit" {
+        let len = io::stdin().read_line(&mut input).expect("Failed to read line.");
+        if len == 0 || (input.trim() == "exit" && !self.is_pending()) {
+            // the end of the input also ends a statement that lacks its semicolon
+            let pending = self.pending.replace(String::new());
+            if !pending.trim().is_empty() {
+                self.process_string(&pending, logger);
+            }
             println!("bye!");
             true
         }
         else {
-            self.process_string(&input, logger);
+            let mut pending = self.pending.borrow_mut();
+            pending.push_str(&input);
+            self.process_complete(&mut pending, logger);
             false
         }
     }
@@ -127,20 +284,20 @@ impl Executor {
     pub fn process_from_file(&self, path: &str, logger: &RuaLogger) {
         use std::error::Error;
         use std::fs::File;
-        use std::io::{BufRead, BufReader};
+        use std::io::Read;
         use std::path::Path;
 
         let path = Path::new(path);
         let display = path.display();
-        let file = match File::open(&path) {
+        let mut file = match File::open(&path) {
             Err(why) => panic!("couldn't open {}: {}", display, why.description()),
             Ok(file) => file,
         };
-        let reader = BufReader::new(file);
-
-        for line in reader.lines() {
-            let line = line.unwrap();
-            self.process_string(&line, logger);
+        // the whole script is read at once so that errors point at its own lines
+        let mut script = String::new();
+        match file.read_to_string(&mut script) {
+            Err(why) => panic!("couldn't read {}: {}", display, why.description()),
+            Ok(_) => self.process_string(&script, logger),
         }
     }
 }
@@ -149,6 +306,38 @@ impl Executor {
 mod test {
     use super::Executor;
     use crate::logger;
+    use crate::logger::logger::RuaResult;
+    use crate::parser::sql;
+    use crate::parser::ast::Value;
+
+    fn run(executor: &Executor, input: &str) -> RuaResult {
+        let stmt = sql::parse_sql(input).unwrap().stmt_list.pop().unwrap();
+        let res = executor.check(&stmt);
+        if res.is_ok() {executor.execute(&stmt)} else {res}
+    }
+
+    // the rows of a select without the column names, nothing for an empty set
+    fn rows(executor: &Executor, input: &str) -> Vec<Vec<String>> {
+        let res = run(executor, input);
+        assert!(res.is_ok(), "{}: {:?}", input, res.error());
+        // a column is printed as a single cell, its values one per line
+        let cols: Vec<Vec<String>> = res.table().map_or(Vec::new(), |t| t[1].iter().map(|c| c.split('\n').map(String::from).collect()).collect());
+        (0..cols.first().map_or(0, |c| c.len())).map(|i| cols.iter().map(|c| c[i].clone()).collect()).collect()
+    }
+
+    // the rows of a select with their values joined by spaces
+    fn lines(executor: &Executor, input: &str) -> Vec<String> {
+        rows(executor, input).into_iter().map(|row| row.join(" ")).collect()
+    }
+
+    // a fresh database for the test, so that tests can run side by side
+    fn database(name: &str) -> Executor {
+        let executor = Executor::new();
+        run(&executor, &format!("drop database {};", name));
+        assert!(run(&executor, &format!("create database {};", name)).is_ok());
+        assert!(run(&executor, &format!("use {};", name)).is_ok());
+        executor
+    }
 
     #[test]
     pub fn sql_select() {
@@ -157,4 +346,601 @@ mod test {
         executor.process_from_file("sql/bug.rsql", &logger);
         executor.process_from_file("sql/small.rsql", &logger);
     }
-}
\ No newline at end of file
+
+    #[test]
+    pub fn not_exists() {
+        let executor = database("test_not_exists");
+        run(&executor, "create table a (id int, k int);");
+        run(&executor, "create table b (k int, v int);");
+        run(&executor, "insert into a values (1, 1), (2, 2), (3, null);");
+        run(&executor, "insert into b values (1, 10), (null, 20);");
+        let exists = rows(&executor, "select id from a where exists (select * from b where b.k = a.k) order by id;");
+        assert_eq!(exists, vec![vec!["1".to_string()]]);
+        // a NULL on either side never matches, so those rows have no match at all
+        let not_exists = rows(&executor, "select id from a where not exists (select * from b where b.k = a.k) order by id;");
+        assert_eq!(not_exists, vec![vec!["2".to_string()], vec!["3".to_string()]]);
+        let not_in = rows(&executor, "select id from a where not (exists (select * from b where b.k = a.k and b.v > 5)) order by id;");
+        assert_eq!(not_in, vec![vec!["2".to_string()], vec!["3".to_string()]]);
+    }
+
+    #[test]
+    pub fn views() {
+        let executor = database("test_views");
+        run(&executor, "create table t (a int not null, b varchar(5), primary key (a));");
+        run(&executor, "insert into t values (1, \"x\"), (2, \"y\"), (3, \"x\");");
+        assert!(run(&executor, "create view vx as select a, b from t where b = \"x\";").is_ok());
+        assert_eq!(rows(&executor, "select a from vx where a > 1;"), vec![vec!["3".to_string()]]);
+        assert_eq!(rows(&executor, "select count(*) from vx;"), vec![vec!["2".to_string()]]);
+        // the view is read again by every select, so it sees the rows inserted since
+        run(&executor, "insert into t values (4, \"x\");");
+        assert_eq!(rows(&executor, "select a from t where a in (select a from vx) order by a;"), vec![vec!["1".to_string()], vec!["3".to_string()], vec!["4".to_string()]]);
+        let mut tables = rows(&executor, "show tables;");
+        tables.sort();
+        assert_eq!(tables, vec![vec!["t".to_string(), "BASE TABLE".to_string()], vec!["vx".to_string(), "VIEW".to_string()]]);
+        assert!(run(&executor, "insert into vx values (5, \"x\");").is_err());
+        assert!(run(&executor, "update vx set a = 5;").is_err());
+        assert!(run(&executor, "delete from vx;").is_err());
+        assert!(run(&executor, "create view t as select * from t;").is_err());
+        assert!(run(&executor, "drop table vx;").is_err());
+        assert!(run(&executor, "drop view vx;").is_ok());
+        assert!(run(&executor, "select * from vx;").is_err());
+    }
+
+    #[test]
+    pub fn explain() {
+        let executor = database("test_explain");
+        run(&executor, "create table t (a int not null, b int, c varchar(5), primary key (a));");
+        run(&executor, "create index bc on t (b, c);");
+        run(&executor, "insert into t values (1, 10, \"x\"), (2, 20, \"y\"), (3, 10, \"y\");");
+        let plan = |input: &str| rows(&executor, input).into_iter().map(|row| row[0].clone()).collect::<Vec<String>>();
+        assert!(plan("explain select * from t where a > 1;").iter().any(|line| line.contains("index scan on primary key (a)")));
+        assert!(plan("explain select * from t where b = 10 and c = \"y\";").iter().any(|line| line.contains("index scan on index bc (b, c)")));
+        assert!(plan("explain update t set b = 1 where a = 2;").iter().any(|line| line.contains("table t")));
+        let analyzed = plan("explain analyze select * from t where b = 10;");
+        assert!(analyzed[0].contains("actual rows=2"));
+        // explain does not run an update, and explain analyze only runs selects
+        run(&executor, "explain delete from t;");
+        assert_eq!(rows(&executor, "select count(*) from t;"), vec![vec!["3".to_string()]]);
+        assert!(run(&executor, "explain analyze delete from t;").is_err());
+        assert!(run(&executor, "explain insert into t values (4, 1, \"z\");").is_err());
+    }
+
+    #[test]
+    pub fn bind_expr_comparison() {
+        let executor = database("test_bind_expr_comparison");
+        run(&executor, "create table t (c1 int, c4 int);");
+        run(&executor, "insert into t values (1, 1), (2, 5);");
+        // the placeholder is compared with `c4 + 1`, so it takes an int
+        let prepared = executor.prepare("select c1 from t where ? < c4 + 1").ok().unwrap();
+        assert!(executor.bind(&prepared, &vec![Value::Str("x".to_string())]).is_err());
+        let res = executor.execute_prepared(&prepared, &vec![Value::Int("3".to_string())]);
+        assert_eq!(res.table().unwrap()[1], vec!["2".to_string()]);
+        let prepared = executor.prepare("select c1 from t where c4 * 2 = ?").ok().unwrap();
+        assert!(executor.bind(&prepared, &vec![Value::Str("x".to_string())]).is_err());
+        assert!(executor.bind(&prepared, &vec![Value::Int("10".to_string())]).is_ok());
+    }
+
+    #[test]
+    pub fn unique() {
+        let executor = database("test_unique");
+        run(&executor, "create table t (a int not null, b int unique, c int, d int, primary key (a), unique (c, d));");
+        assert!(run(&executor, "insert into t values (1, 10, 1, 1), (2, 20, 1, 2);").is_ok());
+        assert!(run(&executor, "insert into t values (3, 10, 5, 5);").is_err());
+        assert!(run(&executor, "insert into t values (3, 30, 1, 1);").is_err());
+        // the rows of a single insert are checked against each other too
+        assert!(run(&executor, "insert into t values (3, 30, 7, 7), (4, 30, 8, 8);").is_err());
+        // NULLs never collide
+        assert!(run(&executor, "insert into t values (3, null, 9, null), (4, null, 9, null);").is_ok());
+        assert!(run(&executor, "update t set b = 20 where a = 1;").is_err());
+        assert!(run(&executor, "update t set b = a + 100 where b is null;").is_ok());
+        assert_eq!(rows(&executor, "select b from t order by a;"), vec![vec!["10".to_string()], vec!["20".to_string()], vec!["103".to_string()], vec!["104".to_string()]]);
+
+        run(&executor, "create table s (x int, y int);");
+        run(&executor, "insert into s values (1, 1), (2, 1);");
+        assert!(run(&executor, "create unique index iy on s (y);").is_err());
+        assert!(run(&executor, "create unique index ix on s (x);").is_ok());
+        assert!(run(&executor, "insert into s values (1, 7);").is_err());
+        // every row gets the default, which only one row can hold
+        assert!(run(&executor, "alter table s add w int default 3 unique;").is_err());
+        assert!(run(&executor, "alter table s add z int unique;").is_ok());
+
+        run(&executor, "create table one (x int);");
+        run(&executor, "insert into one values (1);");
+        assert!(run(&executor, "alter table one add w int default 3 unique;").is_ok());
+        assert_eq!(rows(&executor, "select w from one;"), vec![vec!["3".to_string()]]);
+        assert!(run(&executor, "insert into one (x) values (2);").is_err());
+    }
+
+    #[test]
+    pub fn check() {
+        let executor = database("test_check");
+        run(&executor, "create table p (id int not null, qty int check (qty >= 0), s date, e date, primary key (id), constraint dates check (e > s or e is null));");
+        assert!(run(&executor, "insert into p values (1, 5, '2020-01-01', '2020-02-01');").is_ok());
+        assert!(run(&executor, "insert into p values (2, -1, '2020-01-01', '2020-02-01');").is_err());
+        assert!(run(&executor, "insert into p values (2, 1, '2020-03-01', '2020-02-01');").is_err());
+        // a check that is unknown for NULL holds
+        assert!(run(&executor, "insert into p values (2, null, '2020-03-01', null);").is_ok());
+        assert!(run(&executor, "update p set qty = qty - 10 where id = 1;").is_err());
+        assert!(run(&executor, "update p set qty = qty + 10 where id = 1;").is_ok());
+
+        // existing rows are verified when a constraint is added
+        assert!(run(&executor, "alter table p add constraint big check (qty > 20);").is_err());
+        assert!(run(&executor, "alter table p add constraint small check (qty < 100);").is_ok());
+        assert!(run(&executor, "insert into p values (3, 200, null, null);").is_err());
+        assert!(run(&executor, "alter table p drop check small;").is_ok());
+        assert!(run(&executor, "insert into p values (3, 200, null, null);").is_ok());
+        assert!(run(&executor, "alter table p drop qty;").is_err());
+
+        // the rows already there take the default of an added column
+        assert!(run(&executor, "alter table p add c int default -1 check (c >= 0);").is_err());
+        assert!(run(&executor, "alter table p add w int check (w is not null);").is_err());
+        assert!(run(&executor, "alter table p add w int default 1 check (w is not null);").is_ok());
+        assert!(run(&executor, "insert into p values (4, 1, null, null, null);").is_err());
+        assert_eq!(rows(&executor, "select w from p where id = 3;"), vec![vec!["1".to_string()]]);
+    }
+
+    #[test]
+    pub fn auto_increment() {
+        let executor = database("test_auto_increment");
+        run(&executor, "create table t (id int not null auto_increment, name varchar(10), primary key (id));");
+        run(&executor, "insert into t (name) values (\"a\"), (\"b\");");
+        // an update past the counter moves it on, as an insert would
+        assert!(run(&executor, "update t set id = 10 where id = 2;").is_ok());
+        assert!(run(&executor, "insert into t (name) values (\"c\");").is_ok());
+        assert_eq!(rows(&executor, "select id from t order by id;"), vec![vec!["1".to_string()], vec!["10".to_string()], vec!["11".to_string()]]);
+        assert!(run(&executor, "insert into t values (9223372036854775807, \"max\");").is_ok());
+        assert!(run(&executor, "insert into t (name) values (\"over\");").is_err());
+
+        run(&executor, "create table u (k int auto_increment, v int);");
+        assert!(run(&executor, "alter table u change k k2 int auto_increment default 5;").is_err());
+        assert!(run(&executor, "alter table u change k k2 int auto_increment;").is_ok());
+    }
+
+    #[test]
+    pub fn referential_actions() {
+        let executor = database("test_referential_actions");
+        let ids = |input: &str| rows(&executor, input).into_iter().map(|row| row.join(" ")).collect::<Vec<String>>();
+        run(&executor, "create table p (id int not null, primary key (id));");
+        run(&executor, "create table c (cid int not null, pid int, primary key (cid), foreign key (pid) references p (id) on delete cascade on update cascade);");
+        run(&executor, "create table g (gid int not null, cref int, primary key (gid), foreign key (cref) references c (cid) on delete set null);");
+        run(&executor, "insert into p values (1), (2), (3);");
+        run(&executor, "insert into c values (10, 2), (11, 2), (12, 3);");
+        run(&executor, "insert into g values (100, 10), (101, 12);");
+
+        // a cascade goes on to the rows referencing the rows it deletes
+        assert!(run(&executor, "delete from p where id = 2;").is_ok());
+        assert_eq!(ids("select cid, pid from c order by cid;"), vec!["12 3"]);
+        assert_eq!(ids("select gid, cref from g order by gid;"), vec!["100 NULL", "101 12"]);
+        assert!(run(&executor, "update p set id = 30 where id = 3;").is_ok());
+        assert_eq!(ids("select cid, pid from c order by cid;"), vec!["12 30"]);
+
+        // SET DEFAULT needs a row holding the default key
+        run(&executor, "create table d (did int not null, pid int default 9, primary key (did), foreign key (pid) references p (id) on delete set default);");
+        run(&executor, "insert into d values (1, 1);");
+        assert!(run(&executor, "delete from p where id = 1;").is_err());
+        run(&executor, "insert into p values (9);");
+        assert!(run(&executor, "delete from p where id = 1;").is_ok());
+        assert_eq!(ids("select did, pid from d;"), vec!["1 9"]);
+
+        // a self-referencing cascade deletes the whole chain below the row
+        run(&executor, "create table e (eid int not null, boss int, primary key (eid));");
+        run(&executor, "alter table e add constraint fb foreign key (boss) references e (eid) on delete cascade on update cascade;");
+        for input in &["insert into e values (1, null);", "insert into e values (2, 1);", "insert into e values (3, 2);", "insert into e values (4, null);"] {
+            assert!(run(&executor, input).is_ok());
+        }
+        assert!(run(&executor, "update e set eid = 10 where eid = 1;").is_ok());
+        assert_eq!(ids("select eid, boss from e order by eid;"), vec!["2 10", "3 2", "4 NULL", "10 NULL"]);
+        assert!(run(&executor, "delete from e where eid = 10;").is_ok());
+        assert_eq!(ids("select eid from e;"), vec!["4"]);
+
+        // NO ACTION only looks at the rows once the statement is done, RESTRICT at every row it touches
+        run(&executor, "create table n (nid int not null, boss int, primary key (nid));");
+        run(&executor, "alter table n add constraint fn foreign key (boss) references n (nid);");
+        run(&executor, "create table r (rid int not null, boss int, primary key (rid));");
+        run(&executor, "alter table r add constraint fr foreign key (boss) references r (rid) on delete restrict;");
+        for tb_name in &["n", "r"] {
+            assert!(run(&executor, &format!("insert into {} values (1, null);", tb_name)).is_ok());
+            assert!(run(&executor, &format!("insert into {} values (2, 1);", tb_name)).is_ok());
+            assert!(run(&executor, &format!("delete from {} where boss is null;", tb_name)).is_err());
+        }
+        assert!(run(&executor, "delete from n;").is_ok());
+        assert!(run(&executor, "delete from r;").is_err());
+        assert_eq!(ids("select rid from r order by rid;"), vec!["1", "2"]);
+    }
+
+    #[test]
+    pub fn order_by() {
+        let executor = database("test_order_by");
+        run(&executor, "create table t (a int not null, b int, c varchar(5), primary key (a));");
+        run(&executor, "insert into t values (3, 20, \"x\"), (1, null, \"y\"), (2, 10, \"x\"), (5, 20, null), (4, 10, \"z\");");
+        // the primary key already has the order, so its btree is walked backwards instead of sorting
+        assert!(lines(&executor, "explain select a from t order by a desc;").iter().any(|line| line.contains("ordered scan on primary key (a) desc")));
+        assert_eq!(lines(&executor, "select a from t order by a desc;"), vec!["5", "4", "3", "2", "1"]);
+        assert_eq!(lines(&executor, "select a from t order by a;"), vec!["1", "2", "3", "4", "5"]);
+        // NULL sorts first, and the later keys break the ties of the earlier ones
+        assert_eq!(lines(&executor, "select a, b from t order by b, a desc;"), vec!["1 NULL", "4 10", "2 10", "5 20", "3 20"]);
+        assert_eq!(lines(&executor, "select c, a from t order by c desc, a;"), vec!["z 4", "y 1", "x 2", "x 3", "NULL 5"]);
+    }
+
+    #[test]
+    pub fn group_by() {
+        let executor = database("test_group_by");
+        run(&executor, "create table t (a int not null, b int, c varchar(5), f float, n numeric(5,2), primary key (a));");
+        run(&executor, "insert into t values (3, 20, \"x\", 1.5, 1.25), (1, null, \"y\", 2.5, 2.50), (2, 10, \"x\", null, 0.10), (5, 20, null, 1.5, null), (4, 10, \"z\", 0.5, 3.33);");
+        // NULLs form a group of their own and are skipped by the aggregates, a Numeric keeps its scale
+        assert_eq!(lines(&executor, "select b, count(*), sum(a), avg(a), min(c), max(f), sum(n), avg(n) from t group by b order by b;"),
+            vec!["NULL 1 1 1 y 2.5 2.50 2.50", "10 2 6 3 x 0.5 3.43 1.72", "20 2 8 4 x 1.5 1.25 1.25"]);
+        assert_eq!(lines(&executor, "select c, count(b) from t group by c having count(b) > 1 order by c;"), vec!["x 2"]);
+        assert_eq!(lines(&executor, "select sum(f), avg(f), min(n), max(n) from t;"), vec!["6 1.5 0.10 3.33"]);
+        // without GROUP BY an empty input still gives one row
+        assert_eq!(lines(&executor, "select count(*), sum(a) from t where a > 100;"), vec!["0 NULL"]);
+        assert!(lines(&executor, "explain select count(*) from t;")[0].contains("counted from the born btree"));
+        assert_eq!(lines(&executor, "select count(*) from t;"), vec!["5"]);
+        assert!(run(&executor, "select b, c from t group by b;").is_err());
+        assert!(run(&executor, "select sum(c) from t;").is_err());
+    }
+
+    #[test]
+    pub fn boolean_predicates() {
+        let executor = database("test_boolean_predicates");
+        run(&executor, "create table t (a int not null, b int, c varchar(5), primary key (a));");
+        run(&executor, "create index ib on t (b);");
+        run(&executor, "insert into t values (3, 20, \"x\"), (1, null, \"y\"), (2, 10, \"x\"), (5, 20, null), (4, 10, \"z\");");
+        // every branch has an index, so their scans are put together
+        assert!(lines(&executor, "explain select a from t where a = 1 or b = 20;")[3].contains("union of index scans"));
+        assert_eq!(lines(&executor, "select a from t where a = 1 or b = 20 order by a;"), vec!["1", "3", "5"]);
+        assert_eq!(lines(&executor, "select a from t where a = 1 or c = \"x\" order by a;"), vec!["1", "2", "3"]);
+        assert_eq!(lines(&executor, "select a from t where not (a > 2) order by a;"), vec!["1", "2"]);
+        assert_eq!(lines(&executor, "select a from t where (a = 1 or a = 2) and not (b is null) order by a;"), vec!["2"]);
+        // NOT of an unknown is still unknown
+        assert_eq!(lines(&executor, "select a from t where not (b = 10 or c = \"x\") order by a;"), Vec::<String>::new());
+        assert_eq!(lines(&executor, "select a from t where not (b = 10) order by a;"), vec!["3", "5"]);
+    }
+
+    #[test]
+    pub fn arithmetic() {
+        let executor = database("test_arithmetic");
+        run(&executor, "create table t (a int not null, b int, f float, n numeric(5,2), primary key (a));");
+        run(&executor, "insert into t values (1, null, 2.5, 2.50), (2, 10, null, 0.10), (4, 10, 0.5, 3.33);");
+        assert_eq!(lines(&executor, "select a * 2 + 1, f * 2, n * 2, n / 3, -a, b - a from t order by a;"),
+            vec!["3 5 5.00 0.83 -1 NULL", "5 NULL 0.20 0.03 -2 8", "9 1 6.66 1.11 -4 6"]);
+        // division by zero gives NULL, an Int divides as an Int
+        assert_eq!(lines(&executor, "select a / 0, 7 / 2, 7.0 / 2 from t where a = 1;"), vec!["NULL 3 3.5"]);
+        assert!(run(&executor, "update t set b = b - 1, n = n * 2 where a > 1;").is_ok());
+        assert_eq!(lines(&executor, "select a, b, n from t order by a;"), vec!["1 NULL 2.50", "2 9 0.20", "4 9 6.66"]);
+        assert_eq!(lines(&executor, "select a from t where a + 1 > b / 3 order by a;"), vec!["4"]);
+        assert!(run(&executor, "update t set b = f;").is_err());
+    }
+
+    #[test]
+    pub fn limit() {
+        let executor = database("test_limit");
+        run(&executor, "create table t (a int not null, b int, primary key (a));");
+        run(&executor, "insert into t values (3, 1), (1, 2), (2, 3), (5, 4), (4, 5);");
+        assert_eq!(lines(&executor, "select a from t order by a limit 2;"), vec!["1", "2"]);
+        assert_eq!(lines(&executor, "select a from t order by a desc limit 2 offset 1;"), vec!["4", "3"]);
+        assert_eq!(lines(&executor, "select a from t order by b limit 3 offset 3;"), vec!["5", "4"]);
+        assert_eq!(lines(&executor, "select a from t order by a limit 0;"), Vec::<String>::new());
+        assert_eq!(lines(&executor, "select count(*) from t limit 1;"), vec!["5"]);
+    }
+
+    #[test]
+    pub fn in_between() {
+        let executor = database("test_in_between");
+        run(&executor, "create table t (a int not null, b int, primary key (a));");
+        run(&executor, "insert into t values (3, 20), (1, null), (2, 10), (5, 20), (4, 10);");
+        // an IN list on an indexed column is a lookup per value
+        assert!(lines(&executor, "explain select a from t where a in (1, 4, 9);")[3].contains("primary key (a) for t.a = 9"));
+        assert_eq!(lines(&executor, "select a from t where a in (1, 4, 9) order by a;"), vec!["1", "4"]);
+        assert_eq!(lines(&executor, "select a from t where a between 2 and 4 order by a;"), vec!["2", "3", "4"]);
+        assert_eq!(lines(&executor, "select a from t where a between 4 and 2;"), Vec::<String>::new());
+        assert_eq!(lines(&executor, "select a from t where b not in (10) order by a;"), vec!["3", "5"]);
+        // a NULL in the list can only make a match unknown
+        assert_eq!(lines(&executor, "select a from t where b in (10, null) order by a;"), vec!["2", "4"]);
+        assert_eq!(lines(&executor, "select a from t where b not in (20, null) order by a;"), Vec::<String>::new());
+        assert_eq!(lines(&executor, "select a from t where b not between 11 and 30 order by a;"), vec!["2", "4"]);
+    }
+
+    #[test]
+    pub fn insert_columns() {
+        let executor = database("test_insert_columns");
+        run(&executor, "create table t (a int not null, b int default 7, c varchar(5) default \"d\", n numeric(5,2), primary key (a));");
+        // the columns left out take their defaults, or NULL without one
+        assert!(run(&executor, "insert into t (a) values (1);").is_ok());
+        assert!(run(&executor, "insert into t (c, a) values (\"q\", 2);").is_ok());
+        assert!(run(&executor, "insert into t (a, b) values (3, null);").is_ok());
+        assert_eq!(lines(&executor, "select * from t order by a;"), vec!["1 7 d NULL", "2 7 q NULL", "3 NULL d NULL"]);
+        assert!(run(&executor, "insert into t (a, b) values (4);").is_err());
+        assert!(run(&executor, "insert into t (a, b) values (4, 1, 2);").is_err());
+        assert!(run(&executor, "insert into t (a, zz) values (4, 1);").is_err());
+        assert!(run(&executor, "insert into t (a, a) values (4, 4);").is_err());
+        assert!(run(&executor, "insert into t values (4, 1);").is_err());
+        // a column that can't be NULL has to be given
+        assert!(run(&executor, "insert into t (b) values (5);").is_err());
+
+        assert!(run(&executor, "create table s as select a, b * 2 as bb, c, n, a + 0.5 as h from t where a < 3;").is_ok());
+        let types: Vec<String> = rows(&executor, "desc s;").into_iter().map(|row| format!("{} {}", row[0], row[1])).collect();
+        assert_eq!(types, vec!["a INT", "bb INT", "c VARCHAR", "n NUMERIC", "h NUMERIC"]);
+        assert_eq!(lines(&executor, "select * from s order by a;"), vec!["1 14 d NULL 1.5", "2 14 q NULL 2.5"]);
+        assert!(run(&executor, "create table g as select b, count(*) as k from t group by b;").is_ok());
+        assert_eq!(lines(&executor, "select b, k from g order by b;"), vec!["NULL 1", "7 2"]);
+        assert!(run(&executor, "create table s as select a from t;").is_err());
+
+        assert!(run(&executor, "insert into s (a, c) select a + 20, c from t where a = 2;").is_ok());
+        assert_eq!(lines(&executor, "select * from s where a > 20;"), vec!["22 NULL q NULL NULL"]);
+        assert!(run(&executor, "insert into s (a, c) select a from t;").is_err());
+    }
+
+    #[test]
+    pub fn joins() {
+        let executor = database("test_joins");
+        run(&executor, "create table p (id int not null, name varchar(5), primary key (id));");
+        run(&executor, "create table q (pid int, v int);");
+        run(&executor, "insert into p values (1, \"a\"), (2, \"b\"), (3, \"c\");");
+        run(&executor, "insert into q values (1, 10), (1, 11), (3, 30), (4, 40), (null, 50);");
+        assert_eq!(lines(&executor, "select p.id, q.v from p join q on p.id = q.pid order by q.v;"), vec!["1 10", "1 11", "3 30"]);
+        assert_eq!(lines(&executor, "select id, v from p, q where id = pid order by v;"), vec!["1 10", "1 11", "3 30"]);
+        // the side an outer join keeps is padded with NULL where nothing matches
+        assert_eq!(lines(&executor, "select p.id, q.v from p left join q on p.id = q.pid order by p.id, q.v;"), vec!["1 10", "1 11", "2 NULL", "3 30"]);
+        assert_eq!(lines(&executor, "select p.id, q.v from p right join q on p.id = q.pid order by q.v;"), vec!["1 10", "1 11", "3 30", "NULL 40", "NULL 50"]);
+        assert_eq!(lines(&executor, "select p.id from p left outer join q on p.id = q.pid where q.v is null;"), vec!["2"]);
+        assert_eq!(lines(&executor, "select count(*) from p cross join q;"), vec!["15"]);
+
+        run(&executor, "create table r (id int, w int);");
+        run(&executor, "insert into r values (1, 100), (2, 200);");
+        assert_eq!(lines(&executor, "select name, w from p natural join r order by w;"), vec!["a 100", "b 200"]);
+        assert_eq!(lines(&executor, "select w from p left join r using (id) order by w;"), vec!["NULL", "100", "200"]);
+        // a column name two tables share has to be qualified
+        assert!(run(&executor, "select id from p, r;").is_err());
+        assert!(run(&executor, "select id from p join r on p.id = r.id;").is_err());
+
+        // a table can be joined with itself once it is given two names
+        assert!(run(&executor, "select id from p, p;").is_err());
+        assert!(run(&executor, "select id from p as x, p as y;").is_err());
+        assert!(run(&executor, "select x.id from p as x where p.id = 1;").is_err());
+        assert_eq!(lines(&executor, "select x.id, y.id from p as x, p as y where x.id < y.id order by x.id, y.id;"), vec!["1 2", "1 3", "2 3"]);
+        let res = run(&executor, "select x.id as later, y.name as before from p as x join p as y on x.id = y.id + 1 order by x.id;");
+        assert_eq!(res.table().unwrap()[0], vec!["later".to_string(), "before".to_string()]);
+        assert_eq!(lines(&executor, "select x.id as later, y.name as before from p as x join p as y on x.id = y.id + 1 order by x.id;"), vec!["2 a", "3 b"]);
+    }
+
+    #[test]
+    pub fn set_operations() {
+        let executor = database("test_set_operations");
+        run(&executor, "create table x (k int, f float);");
+        run(&executor, "create table y (k int, f float);");
+        run(&executor, "insert into x values (1, 0.5), (1, 0.5), (2, null), (null, 1.25), (null, 1.25), (3, 0.1);");
+        run(&executor, "insert into y values (1, 0.5), (null, 1.25), (4, 2.0), (2, null), (3, 0.30000000000000004);");
+        // NULLs are equal to each other here, and the duplicates go unless ALL keeps them
+        assert_eq!(lines(&executor, "select k, f from x union select k, f from y order by k, f;"),
+            vec!["NULL 1.25", "1 0.5", "2 NULL", "3 0.1", "3 0.30000000000000004", "4 2"]);
+        assert_eq!(lines(&executor, "select k from x union all select k from y order by k;"),
+            vec!["NULL", "NULL", "NULL", "1", "1", "1", "2", "2", "3", "3", "4"]);
+        assert_eq!(lines(&executor, "select k, f from x intersect select k, f from y order by k;"), vec!["NULL 1.25", "1 0.5", "2 NULL"]);
+        assert_eq!(lines(&executor, "select k, f from x except select k, f from y order by k;"), vec!["3 0.1"]);
+        assert_eq!(lines(&executor, "select distinct k, f from x order by k;"), vec!["NULL 1.25", "1 0.5", "2 NULL", "3 0.1"]);
+        assert!(run(&executor, "select k from x union select k, f from y;").is_err());
+        assert!(run(&executor, "select k from x union select f from y;").is_err());
+
+        // floats are the same key when they are equal, 0.0 and -0.0 included
+        assert_eq!(lines(&executor, "select f * 3 from x where k = 3 union select f from y where k = 3;"), vec!["0.30000000000000004"]);
+        run(&executor, "create table z (f float);");
+        run(&executor, "insert into z values (0.0), (1.0);");
+        assert_eq!(lines(&executor, "select f from z union select -f from z order by f;"), vec!["-1", "0", "1"]);
+        assert_eq!(lines(&executor, "select f from z intersect select -f from z;"), vec!["0"]);
+    }
+
+    #[test]
+    pub fn limit_scan() {
+        let executor = database("test_limit_scan");
+        run(&executor, "create table t (a int not null, b int, primary key (a));");
+        run(&executor, "insert into t values (3, 20), (1, null), (2, 10), (5, 20), (4, 10), (6, 30), (7, 30);");
+        // the index scan stops once the rows the limit needs are found
+        let plan = lines(&executor, "explain analyze select a from t where a > 1 limit 2;");
+        assert!(plan[4].contains("index scan on primary key (a)") && plan[4].contains("stop after 2 rows, actual rows=2"));
+        assert_eq!(lines(&executor, "select a from t where a > 1 limit 2;"), vec!["2", "3"]);
+        let plan = lines(&executor, "explain analyze select a from t order by a desc limit 2 offset 1;");
+        assert!(plan[4].contains("stop after 3 rows, actual rows=3"));
+        assert_eq!(lines(&executor, "select a from t order by a desc limit 2 offset 1;"), vec!["6", "5"]);
+        // an offset past the end gives nothing
+        assert_eq!(lines(&executor, "select a from t where a > 1 order by a limit 1 offset 10;"), Vec::<String>::new());
+        assert_eq!(lines(&executor, "select a from t limit 5 offset 7;"), Vec::<String>::new());
+        assert_eq!(lines(&executor, "select a from t order by a limit 5 offset 6;"), vec!["7"]);
+
+        assert_eq!(lines(&executor, "select distinct b from t order by b desc;"), vec!["30", "20", "10", "NULL"]);
+        // the duplicates are removed before the limit counts the rows
+        assert_eq!(lines(&executor, "select distinct b from t where b < 100 order by b limit 2 offset 1;"), vec!["20", "30"]);
+        assert_eq!(lines(&executor, "select count(*) from t where b in (select distinct b from t);"), vec!["6"]);
+    }
+
+    #[test]
+    pub fn index_paths() {
+        let executor = database("test_index_paths");
+        run(&executor, "create table t (a int not null, b int, primary key (a));");
+        run(&executor, "create index ib on t (b);");
+        run(&executor, "insert into t values (3, 20), (1, null), (2, 10), (5, 20), (4, 10), (8, 0), (6, 5000000000000000000), (7, 5000000000000000000);");
+        assert!(lines(&executor, "explain select a from t where a < 6 order by a desc;")[3].contains("ordered scan on primary key (a) desc"));
+        assert_eq!(lines(&executor, "select a from t where a < 6 order by a desc;"), vec!["5", "4", "3", "2", "1"]);
+        assert_eq!(lines(&executor, "select b from t where a < 6 order by b desc;"), vec!["20", "20", "10", "10", "NULL"]);
+
+        // the NULLs are a group, and a sum that does not fit an int is an error instead of a wrong number
+        assert_eq!(lines(&executor, "select b, count(*), sum(a) from t where a < 6 group by b order by b desc;"), vec!["20 2 8", "10 2 6", "NULL 1 1"]);
+        assert_eq!(lines(&executor, "select b, count(*) from t group by b order by b desc;"), vec!["5000000000000000000 2", "20 2", "10 2", "0 1", "NULL 1"]);
+        assert_eq!(run(&executor, "select b, sum(b) from t group by b;").error(), Some(&"sum(b) is out of range".to_string()));
+        assert!(run(&executor, "select avg(b) from t;").is_err());
+        assert!(run(&executor, "create table s as select sum(b) from t;").is_err());
+        assert!(run(&executor, "select b from t group by b order by a;").is_err());
+
+        // a NULL is kept in the btree as a zero, which the union of the scans must not take for it
+        let plan = lines(&executor, "explain select a from t where b = 10 or a = 1 or b is null;");
+        assert!(plan[3].contains("union of index scans on index ib (b) for t.b = 10; primary key (a) for t.a = 1"));
+        assert_eq!(lines(&executor, "select a from t where b = 10 or a = 1 or b is null order by a desc;"), vec!["4", "2", "1"]);
+        assert_eq!(lines(&executor, "select a from t where b = 10 or a = 1 or a > 6 order by a desc;"), vec!["8", "7", "4", "2", "1"]);
+    }
+
+    #[test]
+    pub fn bind_null() {
+        let executor = database("test_bind_null");
+        run(&executor, "create table t (a int not null, b int, c varchar(5), primary key (a));");
+        run(&executor, "create index ib on t (b);");
+        run(&executor, "insert into t values (1, 10, \"x\"), (2, null, \"y\");");
+        // a comparison with NULL is unknown, so no row matches it, not even one holding NULL
+        for input in &["select a from t where a = ?", "select a from t where b > ?", "select a from t where c <> ?", "select a from t where ? = b",
+                       "select a from t where a = ? and b = 10", "select a from t where a between ? and 3", "select a from t where b = ? or a = 5"] {
+            let prepared = executor.prepare(input).ok().unwrap();
+            let res = executor.execute_prepared(&prepared, &vec![Value::Null]);
+            assert!(res.is_ok() && res.table().is_none(), "{}", input);
+        }
+        let prepared = executor.prepare("select a from t where b = ? or a = 2").ok().unwrap();
+        assert_eq!(executor.execute_prepared(&prepared, &vec![Value::Null]).table().unwrap()[1], vec!["2".to_string()]);
+        let prepared = executor.prepare("update t set c = \"z\" where b = ?").ok().unwrap();
+        assert!(executor.execute_prepared(&prepared, &vec![Value::Null]).is_ok());
+        assert_eq!(lines(&executor, "select c from t order by a;"), vec!["x", "y"]);
+        assert_eq!(lines(&executor, "select a from t where b = null;"), Vec::<String>::new());
+    }
+
+    #[test]
+    pub fn mixed_placeholders() {
+        let executor = database("test_mixed_placeholders");
+        run(&executor, "create table t (a int, b varchar(5));");
+        run(&executor, "insert into t values (1, \"x\"), (2, \"y\");");
+        // a bare `?` and `$1` would otherwise both be the first parameter
+        let res = executor.prepare("select a from t where a = ? and b = $1").err().unwrap();
+        assert!(res.error().unwrap().contains("cannot be mixed"));
+        assert!(executor.prepare("select a from t where a = ?2 or b = ?").is_err());
+        let prepared = executor.prepare("select a from t where b = $2 or a = $1").ok().unwrap();
+        let res = executor.execute_prepared(&prepared, &vec![Value::Int("1".to_string()), Value::Str("y".to_string())]);
+        assert_eq!(res.table().unwrap()[1], vec!["1\n2".to_string()]);
+        let prepared = executor.prepare("select a from t where b = ? or a = ?").ok().unwrap();
+        let res = executor.execute_prepared(&prepared, &vec![Value::Str("y".to_string()), Value::Int("1".to_string())]);
+        assert_eq!(res.table().unwrap()[1], vec!["1\n2".to_string()]);
+    }
+
+    #[test]
+    pub fn multi_line_script() {
+        let executor = database("test_multi_line_script");
+        let logger = logger::logger::RuaLogger::new();
+        let script = "-- a table; with a comment\ncreate table t (\n    a int, /* the key; */\n    b varchar(10)\n);\n\
+                      insert into t values (1, \"a;b\"),\n    (2, \"--\"); insert into t\n  values (3, \"/*\"); -- trailing; comment\n\
+                      /* insert into t values (4, \"x\"); */\ninsert into t values (5, \"*/\")";
+        executor.process_string(&script.to_string(), &logger);
+        // a statement without its semicolon at the end of the script still runs
+        assert_eq!(lines(&executor, "select a, b from t order by a;"), vec!["1 a;b", "2 --", "3 /*", "5 */"]);
+        // a syntax error only loses its own statement
+        executor.process_string(&"delete from t where a = 1;\nselect * frm t;\ndelete from t where a = 2;".to_string(), &logger);
+        assert_eq!(lines(&executor, "select a from t order by a;"), vec!["3", "5"]);
+    }
+
+    #[test]
+    pub fn pending_input() {
+        let executor = database("test_pending_input");
+        let logger = logger::logger::RuaLogger::new();
+        let mut buffer = String::new();
+        // the REPL keeps what has no semicolon yet and runs it once a line ends it
+        for line in &["create table t (a int,\n", "  b varchar(5));  insert into t\n", "values (1, \"x;\"),\n", "(2, \"z\");\n"] {
+            buffer.push_str(line);
+            executor.process_complete(&mut buffer, &logger);
+            if line.starts_with("  b") {
+                assert_eq!(buffer, "insert into t\n");
+                assert_eq!(lines(&executor, "select a from t;"), Vec::<String>::new());
+            }
+        }
+        assert_eq!(buffer, "");
+        assert_eq!(lines(&executor, "select a, b from t order by a;"), vec!["1 x;", "2 z"]);
+    }
+
+    #[test]
+    pub fn case_cast() {
+        let executor = database("test_case_cast");
+        run(&executor, "create table t (a int, b varchar(10), c float, d numeric(6, 2), e date);");
+        run(&executor, "insert into t values (1, \"12\", 2.5, 3.45, '2020-01-31'), (-5, \"x\", -0.5, -1.5, null), (3, null, null, null, '2021-12-01');");
+        // a searched CASE without ELSE gives NULL, a simple CASE compares with `=`
+        assert_eq!(lines(&executor, "select a, case when a > 2 then \"big\" when a > 0 then \"small\" end, case b when \"x\" then 1 when \"12\" then 2 else 0 end from t order by a;"),
+                   vec!["-5 NULL 1", "1 small 2", "3 big 0"]);
+        assert_eq!(lines(&executor, "select case when c is null then d else c end, case a when null then 1 else 2 end from t order by a;"), vec!["-0.5 2", "2.5 2", "NULL 2"]);
+        assert_eq!(lines(&executor, "select a from t where case when a < 0 then -a else a end > 2 order by a;"), vec!["-5", "3"]);
+        // a string is cut to the declared length
+        assert_eq!(lines(&executor, "select cast(-5 as varchar(1)), cast(a as varchar(1)), cast(e as varchar(4)), cast(a as varchar(10)) from t order by a;"),
+                   vec!["- - NULL -5", "- 1 2020 1", "- 3 2021 3"]);
+        assert_eq!(lines(&executor, "select a from t where cast(a as varchar(1)) = \"-\";"), vec!["-5"]);
+        // what does not convert is NULL, narrowing rounds half away from zero
+        assert_eq!(lines(&executor, "select cast(b as int), cast(b as numeric(4, 1)), cast(c as int), cast(d as int), cast(c as numeric(5, 2)), cast(d as float) from t order by a;"),
+                   vec!["NULL NULL -1 -2 -0.50 -1.5", "12 12.0 3 3 2.50 3.45", "NULL NULL NULL NULL NULL NULL"]);
+    }
+
+    #[test]
+    pub fn add_unique() {
+        let executor = database("test_add_unique");
+        run(&executor, "create table t (a int, b int, c int);");
+        run(&executor, "insert into t values (1, 1, 1), (2, 1, 2), (3, null, 2), (4, null, 3);");
+        // the rows already there are checked, NULLs never clash
+        assert!(run(&executor, "alter table t add unique (b);").is_err());
+        assert!(run(&executor, "alter table t add unique (b, c);").is_ok());
+        assert!(run(&executor, "alter table t add unique (b, c);").is_err());
+        assert!(run(&executor, "alter table t add unique (d);").is_err());
+        assert!(run(&executor, "insert into t values (5, 1, 3);").is_ok());
+        assert!(run(&executor, "insert into t values (6, 1, 2);").is_err());
+        assert!(run(&executor, "alter table t change c c int unique;").is_err());
+        assert!(run(&executor, "alter table t change a a int unique;").is_ok());
+        assert!(run(&executor, "insert into t values (1, 9, 9);").is_err());
+        assert!(run(&executor, "update t set a = 2 where a = 1;").is_err());
+        assert!(run(&executor, "alter table t add d int;").is_ok());
+        assert!(run(&executor, "update t set d = a * 10;").is_ok());
+        assert!(run(&executor, "alter table t change d e int unique;").is_ok());
+        assert!(run(&executor, "insert into t values (6, 9, 9, 40);").is_err());
+        assert_eq!(lines(&executor, "select a, e from t where e > 30 order by a;"), vec!["4 40", "5 50"]);
+    }
+
+    #[test]
+    pub fn drop_constraint() {
+        let executor = database("test_drop_constraint");
+        run(&executor, "create table p (id int not null, primary key (id));");
+        run(&executor, "create table t (a int check (a > 0), b int, constraint small check (b < 10));");
+        run(&executor, "insert into p values (1), (20);");
+        assert!(run(&executor, "alter table t add constraint fk foreign key (b) references p (id);").is_ok());
+        // the checks are listed after the columns
+        let desc = rows(&executor, "desc t;");
+        assert_eq!(desc.iter().map(|row| format!("{} {}", row[0], row[1])).collect::<Vec<String>>(), vec!["a INT", "b INT", "t_chk_1 CHECK (a > 0)", "small CHECK (b < 10)"]);
+        assert!(run(&executor, "insert into t values (1, 20);").is_err());
+        assert!(run(&executor, "alter table t drop constraint small;").is_ok());
+        assert!(run(&executor, "insert into t values (1, 20);").is_ok());
+        assert!(run(&executor, "insert into t values (-1, 1);").is_err());
+        assert!(run(&executor, "alter table t drop constraint t_chk_1;").is_ok());
+        assert!(run(&executor, "insert into t values (-1, 1);").is_ok());
+        assert_eq!(rows(&executor, "desc t;").len(), 2);
+        // a foreign key goes the same way, a name the table does not have is an error
+        assert!(run(&executor, "insert into t values (2, 5);").is_err());
+        assert!(run(&executor, "alter table t drop constraint fk;").is_ok());
+        assert!(run(&executor, "insert into t values (2, 5);").is_ok());
+        assert!(run(&executor, "alter table t drop constraint fk;").is_err());
+        assert!(run(&executor, "alter table nothing drop constraint fk;").is_err());
+        assert_eq!(lines(&executor, "select a, b from t order by a;"), vec!["-1 1", "1 20", "2 5"]);
+    }
+
+    #[test]
+    pub fn sequence_functions() {
+        let executor = database("test_sequence_functions");
+        run(&executor, "create table t (id int, v int);");
+        run(&executor, "create table u (id int, v int check (v > 0));");
+        run(&executor, "insert into t values (0, 1), (0, 2), (0, 3);");
+        assert!(run(&executor, "create sequence s;").is_ok());
+        // currval has nothing to give before the first nextval
+        assert!(run(&executor, "select currval('s') from t;").is_err());
+        assert!(run(&executor, "insert into u values (nextval('s'), 1);").is_ok());
+        // nextval moves on once for every row and currval follows it
+        assert_eq!(lines(&executor, "select v, nextval('s'), currval('s') from t order by v;"), vec!["1 2 2", "2 3 3", "3 4 4"]);
+        assert_eq!(lines(&executor, "select nextval('s') + 1, currval(\"s\") from t where v = 1;"), vec!["6 5"]);
+        assert!(run(&executor, "insert into u select nextval('s'), v from t;").is_ok());
+        assert!(run(&executor, "update t set id = nextval('s') * 10;").is_ok());
+        assert_eq!(lines(&executor, "select id, v from u order by id;"), vec!["1 1", "6 1", "7 2", "8 3"]);
+        assert_eq!(lines(&executor, "select id, v from t order by v;"), vec!["90 1", "100 2", "110 3"]);
+        // a statement that fails its check leaves the sequence alone
+        assert!(run(&executor, "insert into u select nextval('s'), v - 2 from t;").is_err());
+        assert!(run(&executor, "update u set v = nextval('s') - 13;").is_err());
+        assert_eq!(lines(&executor, "select nextval('s') from t where v = 1;"), vec!["12"]);
+        // the sequence must exist and be named by a literal
+        assert!(run(&executor, "select nextval('nothing') from t;").is_err());
+        assert!(run(&executor, "update t set id = nextval('nothing');").is_err());
+        assert!(run(&executor, "select nextval(id) from t;").is_err());
+    }
+}
@@ -4,6 +4,7 @@ use std::cmp::min;
 use crate::rm::record::*;
 use crate::rm::table_handler::TableHandler;
 use crate::utils::convert;
+use crate::parser::ast::ReferentialAction;
 
 #[derive(Clone, Debug)]
 pub struct Index<'a> {
@@ -161,10 +162,11 @@ pub struct BTree<'a> {
     // default_name: 
     // "" : born btree,
     // "[pk_name_name]": primary btree,
-    // "foreign_constraint_name tb_name": foreign index
+    // "foreign_constraint_name tb_name on_delete on_update": foreign index
+    // "unique col_name,..": unique constraint, a unique index keeps its own name
     pub index_name: String, 
-    // born, primary, index, foreign
-    // 0, 1, 2, 3
+    // born, primary, index, foreign, unique
+    // 0, 1, 2, 3, 4
     pub ty: u8, 
 }
 
@@ -182,10 +184,12 @@ impl<'a> BTree<'a> {
     pub fn is_primary(&self) -> bool { self.ty == Self::primary_ty() } 
     pub fn is_foreign(&self) -> bool { self.ty == Self::foreign_ty() }
     pub fn is_index(&self) -> bool { self.ty == Self::index_ty() }
+    pub fn is_unique(&self) -> bool { self.ty == Self::unique_ty() }
     pub fn born_ty() -> u8 {0}
     pub fn primary_ty() -> u8 {1}
     pub fn index_ty() -> u8 {2}
     pub fn foreign_ty() -> u8 {3}
+    pub fn unique_ty() -> u8 {4}
 
     pub fn get_foreign_constraint_name(&self) -> &str {
         if !self.is_foreign() {
@@ -202,9 +206,22 @@ impl<'a> BTree<'a> {
         iter.next().unwrap()
     }
 
+    // a foreign index made before the actions were kept has NO ACTION for both, and so has a name that is not an action
+    pub fn get_foreign_actions(&self) -> (ReferentialAction, ReferentialAction) {
+        assert!(self.is_foreign());
+
+        let mut iter = self.index_name.split_whitespace().skip(2).map(|name| ReferentialAction::from_name(name).unwrap_or(ReferentialAction::NoAction));
+        (iter.next().unwrap_or(ReferentialAction::NoAction), iter.next().unwrap_or(ReferentialAction::NoAction))
+    }
+
+    pub fn foreign_index_name(constraint: &str, table: &str, on_delete: ReferentialAction, on_update: ReferentialAction) -> String {
+        format!("{} {} {} {}", constraint, table, on_delete.name(), on_update.name())
+    }
+
     pub fn set_foreign_index_name(&mut self, constraint: &str, table: &str) {
         assert!(self.is_foreign());
-        self.index_name = format!("{} {}", constraint, table);
+        let (on_delete, on_update) = self.get_foreign_actions();
+        self.index_name = Self::foreign_index_name(constraint, table, on_delete, on_update);
     }
 
     // offset
@@ -768,7 +785,10 @@ impl BTreeNode {
         match self.ty {
             BTreeNodeType::Leaf => {
                 let i = self.lower_bound(th, key, len);
-                if i == len {
+                if len == 0 { // empty tree
+                    None
+                }
+                else if i == len {
                     let bucket = th.get_bucket_(self.bucket[i - 1]); // bucket < key
                     if is_less { // less
                         Some(bucket)
@@ -837,7 +857,7 @@ impl BTreeNode {
                 }
             }
             BTreeNodeType::Internal => {
-                th.get_btree_node_(self.son[self.get_len()]).first_bucket(th)
+                th.get_btree_node_(self.son[self.get_len()]).last_bucket(th)
             }
         }
     }
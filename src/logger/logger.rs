@@ -29,6 +29,15 @@ impl RuaResult {
     pub fn is_err(&self) -> bool {
         self.res.is_err()
     }
+
+    // the printed table, its first row holds the column names
+    pub fn table(&self) -> Option<&Vec<Vec<String>>> {
+        self.res.as_ref().ok().and_then(|(t, _)| t.as_ref())
+    }
+
+    pub fn error(&self) -> Option<&String> {
+        self.res.as_ref().err()
+    }
 }
 
 impl std::ops::BitAnd for RuaResult {
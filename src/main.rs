@@ -26,8 +26,9 @@ fn initalize() {
     println!("{:?}", settings);
 }
 
-fn print_prompt() {
-    print!("rua > ");
+// a statement that goes on over several lines continues with the second prompt
+fn print_prompt(pending: bool) {
+    print!("{}", if pending {"    > "} else {"rua > "});
     io::stdout().flush().ok().expect("Could not flush stdout.");
 }
 
@@ -39,7 +40,7 @@ fn main() {
     let logger = logger::logger::RuaLogger::new();
     let executor = executor::executor::Executor::new();
     loop {
-        print_prompt();
+        print_prompt(executor.is_pending());
         if executor.process_from_stdin(&logger) {
             break;
         }
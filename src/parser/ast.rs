@@ -13,6 +13,41 @@ pub enum Stmt {
     Index(IndexStmt),
     Alter(AlterStmt),
     Copy(CopyStmt),
+    Explain(ExplainStmt),
+}
+
+impl Stmt {
+    // the values in the order they are written
+    pub fn values_mut(&mut self) -> Vec<&mut Value> {
+        match self {
+            Stmt::Table(stmt) => stmt.values_mut(),
+            Stmt::Alter(AlterStmt::AddColumn { tb_name: _, field }) |
+            Stmt::Alter(AlterStmt::ChangeColumn { tb_name: _, col_name: _, field }) => field.values_mut(),
+            Stmt::Explain(explain) => explain.stmt.values_mut(),
+            _ => vec![],
+        }
+    }
+
+    // numbers the bare `?` in order, gives the number of placeholders
+    // or None when bare and numbered placeholders are mixed
+    pub fn number_params(&mut self) -> Option<usize> {
+        let (mut bare, mut numbered) = (0, 0);
+        for value in self.values_mut() {
+            if let Value::Param(n) = value {
+                if *n == 0 {
+                    bare += 1;
+                    *n = bare;
+                }
+                else {
+                    numbered = numbered.max(*n);
+                }
+            }
+        }
+        match (bare, numbered) {
+            (0, count) | (count, 0) => Some(count),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -21,6 +56,13 @@ pub struct CopyStmt {
     pub path: Name,
 }
 
+// ANALYZE runs the statement and reports what every node of the plan produced
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExplainStmt {
+    pub analyze: bool,
+    pub stmt: TableStmt,
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum SystemStmt {
     ShowDatabases,
@@ -52,10 +94,38 @@ pub enum TableStmt {
     Desc {
         tb_name: Name,
     },
+    CreateTableAs {
+        tb_name: Name,
+        query: Box<TableStmt>,
+    },
+    // `definition` is the text of the select, which is what gets stored
+    CreateView {
+        view_name: Name,
+        query: Box<TableStmt>,
+        definition: String,
+    },
+    DropView {
+        view_name: Name,
+    },
+    CreateSequence {
+        seq_name: Name,
+        increment: Option<i64>,
+        start: Option<i64>,
+    },
+    DropSequence {
+        seq_name: Name,
+    },
+    // without a column list the values are given for every column in order
     Insert {
         tb_name: Name,
+        column_list: Option<Vec<Name>>,
         value_lists: Vec<Vec<Value>>,
     },
+    InsertSelect {
+        tb_name: Name,
+        column_list: Option<Vec<Name>>,
+        query: Box<TableStmt>,
+    },
     Delete {
         tb_name: Name,
         where_clause: Option<Vec<WhereClause>>,
@@ -66,18 +136,69 @@ pub enum TableStmt {
         where_clause: Option<Vec<WhereClause>>,
     },
     Select {
-        table_list: Vec<Name>,
+        distinct: bool,
+        table_list: Vec<TableRef>,
+        join_list: Vec<Join>,
         selector: Selector,
         where_clause: Option<Vec<WhereClause>>,
+        group_clause: Option<Vec<Column>>,
+        having_clause: Option<Vec<HavingClause>>,
+        order_clause: Option<Vec<OrderClause>>,
+        limit_clause: Option<LimitClause>,
+    },
+    // ORDER BY and LIMIT after the last select apply to the combined rows
+    Compound {
+        op: SetOp,
+        all: bool,
+        left: Box<TableStmt>,
+        right: Box<TableStmt>,
+        order_clause: Option<Vec<OrderClause>>,
+        limit_clause: Option<LimitClause>,
     },
 }
 
+impl TableStmt {
+    pub fn values_mut(&mut self) -> Vec<&mut Value> {
+        match self {
+            TableStmt::CreateTable { tb_name: _, field_list } => field_list.iter_mut().flat_map(|field| field.values_mut()).collect(),
+            TableStmt::CreateTableAs { tb_name: _, query } |
+            TableStmt::CreateView { view_name: _, query, definition: _ } |
+            TableStmt::InsertSelect { tb_name: _, column_list: _, query } => query.values_mut(),
+            TableStmt::Insert { tb_name: _, column_list: _, value_lists } => value_lists.iter_mut().flatten().collect(),
+            TableStmt::Delete { tb_name: _, where_clause } => where_clause.iter_mut().flatten().flat_map(|clause| clause.values_mut()).collect(),
+            TableStmt::Update { tb_name: _, set_clause, where_clause } => {
+                let mut values: Vec<&mut Value> = set_clause.iter_mut().flat_map(|set| set.expr.values_mut()).collect();
+                values.extend(where_clause.iter_mut().flatten().flat_map(|clause| clause.values_mut()));
+                values
+            },
+            TableStmt::Select { distinct: _, table_list: _, join_list, selector, where_clause, group_clause: _, having_clause, order_clause: _, limit_clause: _ } => {
+                let mut values = selector.values_mut();
+                values.extend(join_list.iter_mut().flat_map(|join| join.values_mut()));
+                values.extend(where_clause.iter_mut().flatten().flat_map(|clause| clause.values_mut()));
+                for having in having_clause.iter_mut().flatten() {
+                    values.extend(having.item.values_mut());
+                    values.extend(having.expr.values_mut());
+                }
+                values
+            },
+            TableStmt::Compound { op: _, all: _, left, right, order_clause: _, limit_clause: _ } => {
+                let mut values = left.values_mut();
+                values.extend(right.values_mut());
+                values
+            },
+            TableStmt::DropTable { .. } | TableStmt::Desc { .. } | TableStmt::DropView { .. } |
+            TableStmt::CreateSequence { .. } | TableStmt::DropSequence { .. } => vec![],
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum IndexStmt {
     CreateIndex {
         idx_name: Name,
         tb_name: Name,
         column_list: Vec<Name>, 
+        unique: bool,
     }, 
     DropIndex {
         idx_name: Name,
@@ -87,6 +208,7 @@ pub enum IndexStmt {
         idx_name: Name,
         tb_name: Name,
         column_list: Vec<Name>, 
+        unique: bool,
     }, 
     AlterDropIndex {
         idx_name: Name,
@@ -117,6 +239,10 @@ pub enum AlterStmt {
         tb_name: Name,
         column_list: Vec<Name>,
     },
+    AddUnique {
+        tb_name: Name,
+        column_list: Vec<Name>,
+    },
     DropPrimaryKey {
         tb_name: Name,
     },
@@ -135,11 +261,26 @@ pub enum AlterStmt {
         column_list: Vec<Name>,
         foreign_tb_name: Name,
         foreign_column_list: Vec<Name>,
+        on_delete: ReferentialAction,
+        on_update: ReferentialAction,
     },
     DropConstraintForeignKey {
         tb_name: Name,
         fk_name: Name,
     },
+    AddConstraintCheck {
+        tb_name: Name,
+        ck_name: Name,
+        check: Check,
+    },
+    DropConstraintCheck {
+        tb_name: Name,
+        ck_name: Name,
+    },
+    DropConstraint {
+        tb_name: Name,
+        name: Name,
+    },
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -148,20 +289,84 @@ pub enum Field {
         col_name: Name,
         ty: Type,
         not_null: bool,
+        auto_increment: bool,
         default_value: Option<Value>,
+        unique: bool,
+        check: Option<Check>,
     },
 
     PrimaryKeyField {
         column_list: Vec<Name>,
     },
 
+    UniqueField {
+        column_list: Vec<Name>,
+    },
+
     ForeignKeyField {
         column_list: Vec<Name>,
         foreign_tb_name: Name, 
         foreign_column_list: Vec<Name>,
+        on_delete: ReferentialAction,
+        on_update: ReferentialAction,
+    },
+
+    CheckField {
+        ck_name: Option<Name>,
+        check: Check,
     },
 }
 
+// the condition of a CHECK constraint, `definition` is its text which is what the table keeps
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Check {
+    pub clause: Vec<WhereClause>,
+    pub definition: String,
+}
+
+// what happens to the referencing rows when the row they reference is deleted or gets a new key
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReferentialAction {
+    Cascade,
+    SetNull,
+    SetDefault,
+    Restrict,
+    NoAction,
+}
+
+impl ReferentialAction {
+    // a single word, foreign key btrees keep it in their name
+    pub fn name(&self) -> &'static str {
+        match self {
+            ReferentialAction::Cascade => "cascade",
+            ReferentialAction::SetNull => "set_null",
+            ReferentialAction::SetDefault => "set_default",
+            ReferentialAction::Restrict => "restrict",
+            ReferentialAction::NoAction => "no_action",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "cascade" => Some(ReferentialAction::Cascade),
+            "set_null" => Some(ReferentialAction::SetNull),
+            "set_default" => Some(ReferentialAction::SetDefault),
+            "restrict" => Some(ReferentialAction::Restrict),
+            "no_action" => Some(ReferentialAction::NoAction),
+            _ => None,
+        }
+    }
+}
+
+impl Field {
+    pub fn values_mut(&mut self) -> Vec<&mut Value> {
+        match self {
+            Field::ColumnField { default_value: Some(value), .. } => vec![value],
+            _ => vec![],
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Type {
     Int(i64), 
@@ -172,8 +377,16 @@ pub enum Type {
 }
 
 impl Type {
+    // the coercion lattice: Int, Numeric and Float meet each other and a string meets a date
     pub fn comparable(&self, other: &Self) -> bool {
-        self.same(other)
+        let numeric = |ty: &Type| match ty {
+            Type::Int(_) | Type::Numeric(_, _) | Type::Float => true,
+            _ => false,
+        };
+        match (self, other) {
+            (Type::Varchar(_), Type::Date) | (Type::Date, Type::Varchar(_)) => true,
+            (l, r) => l.same(r) || (numeric(l) && numeric(r)),
+        }
     }
 
     pub fn same(&self, other: &Self) -> bool {
@@ -186,6 +399,16 @@ impl Type {
             (_, _) => false,
         }
     }
+
+    pub fn name(&self) -> String {
+        match self {
+            Type::Int(_) => "int".to_owned(),
+            Type::Varchar(n) => format!("varchar({})", n),
+            Type::Date => "date".to_owned(),
+            Type::Float => "float".to_owned(),
+            Type::Numeric(p, s) => format!("numeric({}, {})", p, s),
+        }
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -195,6 +418,11 @@ pub enum Value {
     Float(String),
     Date(String),
     Null,
+    // a placeholder of a prepared statement, numbered from 1
+    Param(usize),
+    // `nextval('s')` and `currval('s')` in the values of an insert, replaced by numbers before it is checked
+    NextVal(Name),
+    CurrVal(Name),
 }
 
 
@@ -205,7 +433,7 @@ impl Value {
 
     pub fn of_type(&self, ty: &Type) -> bool {
         match (self, ty) {
-            (Value::Null, _) => true,
+            (Value::Null, _) | (Value::Param(_), _) => true,
             (Value::Int(_), Type::Int(_)) | 
             (Value::Str(_), Type::Varchar(_)) | 
             (Value::Float(_), Type::Float) | 
@@ -213,6 +441,18 @@ impl Value {
             (_, _) => false, 
         }
     }
+
+    pub fn name(&self) -> String {
+        match self {
+            Value::Int(s) | Value::Float(s) => s.clone(),
+            Value::Str(s) => format!("\"{}\"", s),
+            Value::Date(s) => format!("'{}'", s),
+            Value::Null => "null".to_owned(),
+            Value::Param(n) => format!("${}", n),
+            Value::NextVal(seq_name) => format!("nextval('{}')", seq_name),
+            Value::CurrVal(seq_name) => format!("currval('{}')", seq_name),
+        }
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -226,6 +466,129 @@ pub enum WhereClause {
         op: Op,
         expr: Expr,
     },
+    ExprComparison {
+        left: Expr,
+        op: Op,
+        right: Expr,
+    },
+    Like {
+        col: Column,
+        pattern: String,
+        escape: Option<String>,
+    },
+    In {
+        col: Column,
+        values: Vec<Value>,
+    },
+    InSubquery {
+        col: Column,
+        query: Box<TableStmt>,
+    },
+    Exists(Box<TableStmt>),
+    Between {
+        col: Column,
+        low: Value,
+        high: Value,
+    },
+    And(Vec<WhereClause>),
+    Or(Vec<WhereClause>),
+    Not(Box<WhereClause>),
+}
+
+impl WhereClause {
+    // a column on the left is kept apart so that it can become a range condition
+    pub fn compare(left: Expr, op: Op, right: Expr) -> Self {
+        match left {
+            Expr::Column(col) => WhereClause::Comparison { col: col, op: op, expr: right },
+            left => WhereClause::ExprComparison { left: left, op: op, right: right },
+        }
+    }
+
+    pub fn and(clauses: Vec<WhereClause>) -> Self {
+        let mut clauses = clauses;
+        if clauses.len() == 1 {
+            clauses.pop().unwrap()
+        }
+        else {
+            WhereClause::And(clauses)
+        }
+    }
+
+    pub fn columns(&self) -> Vec<&Column> {
+        match self {
+            WhereClause::IsAssert { col, null: _ } |
+            WhereClause::Like { col, pattern: _, escape: _ } |
+            WhereClause::In { col, values: _ } |
+            WhereClause::InSubquery { col, query: _ } |
+            WhereClause::Between { col, low: _, high: _ } => vec![col],
+            WhereClause::Comparison { col, op: _, expr } => {
+                let mut cols = vec![col];
+                cols.extend(expr.columns());
+                cols
+            },
+            WhereClause::ExprComparison { left, op: _, right } => left.columns().into_iter().chain(right.columns()).collect(),
+            WhereClause::And(clauses) | WhereClause::Or(clauses) => clauses.iter().flat_map(|clause| clause.columns()).collect(),
+            WhereClause::Not(clause) => clause.columns(),
+            WhereClause::Exists(_) => vec![],
+        }
+    }
+
+    // nested selects that are not inside another nested select
+    pub fn subqueries(&self) -> Vec<&TableStmt> {
+        match self {
+            WhereClause::Comparison { col: _, op: _, expr } => expr.subqueries(),
+            WhereClause::ExprComparison { left, op: _, right } => left.subqueries().into_iter().chain(right.subqueries()).collect(),
+            WhereClause::InSubquery { col: _, query } | WhereClause::Exists(query) => vec![query],
+            WhereClause::And(clauses) | WhereClause::Or(clauses) => clauses.iter().flat_map(|clause| clause.subqueries()).collect(),
+            WhereClause::Not(clause) => clause.subqueries(),
+            _ => vec![],
+        }
+    }
+
+    pub fn has_subquery(&self) -> bool {
+        self.subqueries().len() > 0
+    }
+
+    pub fn values_mut(&mut self) -> Vec<&mut Value> {
+        match self {
+            WhereClause::IsAssert { .. } | WhereClause::Like { .. } => vec![],
+            WhereClause::Comparison { col: _, op: _, expr } => expr.values_mut(),
+            WhereClause::ExprComparison { left, op: _, right } => {
+                let mut values = left.values_mut();
+                values.extend(right.values_mut());
+                values
+            },
+            WhereClause::In { col: _, values } => values.iter_mut().collect(),
+            WhereClause::InSubquery { col: _, query } | WhereClause::Exists(query) => query.values_mut(),
+            WhereClause::Between { col: _, low, high } => vec![low, high],
+            WhereClause::And(clauses) | WhereClause::Or(clauses) => clauses.iter_mut().flat_map(|clause| clause.values_mut()).collect(),
+            WhereClause::Not(clause) => clause.values_mut(),
+        }
+    }
+
+    pub fn name(&self) -> String {
+        let values = |values: &Vec<Value>| values.iter().map(|value| value.name()).collect::<Vec<String>>().join(", ");
+        match self {
+            WhereClause::IsAssert { col, null: true } => format!("{} is null", col.name()),
+            WhereClause::IsAssert { col, null: false } => format!("{} is not null", col.name()),
+            WhereClause::Comparison { col, op, expr } => format!("{} {} {}", col.name(), op.symbol(), expr.name()),
+            WhereClause::ExprComparison { left, op, right } => format!("{} {} {}", left.name(), op.symbol(), right.name()),
+            WhereClause::Like { col, pattern, escape: None } => format!("{} like \"{}\"", col.name(), pattern),
+            WhereClause::Like { col, pattern, escape: Some(escape) } => format!("{} like \"{}\" escape \"{}\"", col.name(), pattern, escape),
+            WhereClause::In { col, values: list } => format!("{} in ({})", col.name(), values(list)),
+            WhereClause::InSubquery { col, query: _ } => format!("{} in (subquery)", col.name()),
+            WhereClause::Exists(_) => "exists (subquery)".to_owned(),
+            WhereClause::Between { col, low, high } => format!("{} between {} and {}", col.name(), low.name(), high.name()),
+            WhereClause::And(clauses) if clauses.len() == 0 => "true".to_owned(),
+            WhereClause::Or(clauses) if clauses.len() == 0 => "false".to_owned(),
+            WhereClause::And(clauses) => clauses.iter().map(|clause| match clause {
+                WhereClause::Or(_) => format!("({})", clause.name()),
+                _ => clause.name(),
+            }).collect::<Vec<String>>().join(" and "),
+            WhereClause::Or(clauses) => clauses.iter().map(|clause| clause.name()).collect::<Vec<String>>().join(" or "),
+            WhereClause::Not(clause) => format!("not ({})", clause.name()),
+        }
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -234,10 +597,274 @@ pub struct Column {
     pub col_name: Name,
 }
 
+impl Column {
+    pub fn name(&self) -> String {
+        match &self.tb_name {
+            Some(tb_name) => format!("{}.{}", tb_name, self.col_name),
+            None => self.col_name.clone(),
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Expr {
     Value(Value),
     Column(Column),
+    Binary {
+        op: BinOp,
+        left: Box<Expr>,
+        right: Box<Expr>,
+    },
+    Neg(Box<Expr>),
+    Subquery(Box<TableStmt>),
+    Func {
+        func: Func,
+        args: Vec<Expr>,
+    },
+    // the simple form `CASE x WHEN v THEN ..` is read as `CASE WHEN x = v THEN ..`
+    Case {
+        whens: Vec<(WhereClause, Expr)>,
+        else_: Option<Box<Expr>>,
+    },
+    Cast {
+        expr: Box<Expr>,
+        ty: Type,
+    },
+}
+
+impl Expr {
+    pub fn binary(op: BinOp, left: Expr, right: Expr) -> Self {
+        Expr::Binary {
+            op: op,
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+
+    // a sequence is named by a string, which may also be written in single quotes like a date
+    pub fn call(func: Func, args: Vec<Expr>) -> Self {
+        let args = match func {
+            Func::NextVal | Func::CurrVal => args.into_iter().map(|arg| match arg {
+                Expr::Value(Value::Date(seq_name)) => Expr::Value(Value::Str(seq_name)),
+                arg => arg,
+            }).collect(),
+            _ => args,
+        };
+        Expr::Func { func: func, args: args }
+    }
+
+    // folds the sign into numeric literals so that `-1` stays a plain value
+    pub fn neg(self) -> Self {
+        match self {
+            Expr::Value(Value::Int(s)) => match s.starts_with('-') {
+                true => Expr::Value(Value::Int(s[1..].to_owned())),
+                false => Expr::Value(Value::Int(format!("-{}", s))),
+            },
+            Expr::Value(Value::Float(s)) => match s.starts_with('-') {
+                true => Expr::Value(Value::Float(s[1..].to_owned())),
+                false => Expr::Value(Value::Float(format!("-{}", s))),
+            },
+            expr => Expr::Neg(Box::new(expr)),
+        }
+    }
+
+    pub fn columns(&self) -> Vec<&Column> {
+        match self {
+            Expr::Value(_) => vec![],
+            Expr::Column(col) => vec![col],
+            Expr::Binary { op: _, left, right } => left.columns().into_iter().chain(right.columns()).collect(),
+            Expr::Neg(expr) => expr.columns(),
+            Expr::Subquery(_) => vec![],
+            Expr::Func { func: _, args } => args.iter().flat_map(|arg| arg.columns()).collect(),
+            Expr::Case { whens, else_ } => {
+                let mut cols: Vec<&Column> = whens.iter().flat_map(|(cond, expr)| cond.columns().into_iter().chain(expr.columns())).collect();
+                cols.extend(else_.iter().flat_map(|expr| expr.columns()));
+                cols
+            },
+            Expr::Cast { expr, ty: _ } => expr.columns(),
+        }
+    }
+
+    pub fn subqueries(&self) -> Vec<&TableStmt> {
+        match self {
+            Expr::Binary { op: _, left, right } => left.subqueries().into_iter().chain(right.subqueries()).collect(),
+            Expr::Neg(expr) => expr.subqueries(),
+            Expr::Subquery(query) => vec![query],
+            Expr::Func { func: _, args } => args.iter().flat_map(|arg| arg.subqueries()).collect(),
+            // the conditions of a CASE may not hold subqueries
+            Expr::Case { whens, else_ } => whens.iter().map(|(_, expr)| expr).chain(else_.iter().map(|expr| &**expr)).flat_map(|expr| expr.subqueries()).collect(),
+            Expr::Cast { expr, ty: _ } => expr.subqueries(),
+            _ => vec![],
+        }
+    }
+
+    pub fn has_subquery(&self) -> bool {
+        self.subqueries().len() > 0
+    }
+
+    // nextval and currval are replaced by their numbers before the expression is evaluated
+    pub fn has_sequence(&self) -> bool {
+        match self {
+            Expr::Func { func: Func::NextVal, .. } | Expr::Func { func: Func::CurrVal, .. } => true,
+            Expr::Binary { op: _, left, right } => left.has_sequence() || right.has_sequence(),
+            Expr::Neg(expr) => expr.has_sequence(),
+            Expr::Func { func: _, args } => args.iter().any(|arg| arg.has_sequence()),
+            Expr::Case { whens, else_ } => whens.iter().map(|(_, expr)| expr).chain(else_.iter().map(|expr| &**expr)).any(|expr| expr.has_sequence()),
+            Expr::Cast { expr, ty: _ } => expr.has_sequence(),
+            _ => false,
+        }
+    }
+
+    pub fn values_mut(&mut self) -> Vec<&mut Value> {
+        match self {
+            Expr::Value(value) => vec![value],
+            Expr::Column(_) => vec![],
+            Expr::Binary { op: _, left, right } => {
+                let mut values = left.values_mut();
+                values.extend(right.values_mut());
+                values
+            },
+            Expr::Neg(expr) => expr.values_mut(),
+            Expr::Subquery(query) => query.values_mut(),
+            Expr::Func { func: _, args } => args.iter_mut().flat_map(|arg| arg.values_mut()).collect(),
+            Expr::Case { whens, else_ } => {
+                let mut values: Vec<&mut Value> = whens.iter_mut().flat_map(|(cond, expr)| {
+                    let mut values = cond.values_mut();
+                    values.extend(expr.values_mut());
+                    values
+                }).collect();
+                values.extend(else_.iter_mut().flat_map(|expr| expr.values_mut()));
+                values
+            },
+            Expr::Cast { expr, ty: _ } => expr.values_mut(),
+        }
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            Expr::Value(value) => value.name(),
+            Expr::Column(col) => col.name(),
+            Expr::Binary { op, left, right } => {
+                let left = match left.precedence() < op.precedence() {
+                    true => format!("({})", left.name()),
+                    false => left.name(),
+                };
+                let right = match right.precedence() <= op.precedence() {
+                    true => format!("({})", right.name()),
+                    false => right.name(),
+                };
+                format!("{}{}{}", left, op.symbol(), right)
+            },
+            Expr::Neg(expr) => match expr.precedence() < 3 {
+                true => format!("-({})", expr.name()),
+                false => format!("-{}", expr.name()),
+            },
+            Expr::Subquery(_) => "(subquery)".to_owned(),
+            Expr::Func { func, args } => {
+                let args: Vec<String> = args.iter().map(|arg| arg.name()).collect();
+                format!("{}({})", func.name(), args.join(", "))
+            },
+            Expr::Case { whens, else_ } => {
+                let mut parts: Vec<String> = whens.iter().map(|(cond, expr)| format!("when {} then {}", cond.name(), expr.name())).collect();
+                parts.extend(else_.iter().map(|expr| format!("else {}", expr.name())));
+                format!("case {} end", parts.join(" "))
+            },
+            Expr::Cast { expr, ty } => format!("cast({} as {})", expr.name(), ty.name()),
+        }
+    }
+
+    fn precedence(&self) -> u8 {
+        match self {
+            Expr::Binary { op, left: _, right: _ } => op.precedence(),
+            _ => 3,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Func {
+    Upper,
+    Lower,
+    Length,
+    Substr,
+    Trim,
+    Concat,
+    Abs,
+    Round,
+    Floor,
+    Ceil,
+    Coalesce,
+    IfNull,
+    Now,
+    CurrentDate,
+    Year,
+    Month,
+    Day,
+    NextVal,
+    CurrVal,
+}
+
+// the names the scalar functions are called by, the first one of a function is the one it is shown with
+const FUNCS: &[(&str, Func)] = &[
+    ("upper", Func::Upper),
+    ("ucase", Func::Upper),
+    ("lower", Func::Lower),
+    ("lcase", Func::Lower),
+    ("length", Func::Length),
+    ("substr", Func::Substr),
+    ("substring", Func::Substr),
+    ("trim", Func::Trim),
+    ("concat", Func::Concat),
+    ("abs", Func::Abs),
+    ("round", Func::Round),
+    ("floor", Func::Floor),
+    ("ceil", Func::Ceil),
+    ("ceiling", Func::Ceil),
+    ("coalesce", Func::Coalesce),
+    ("ifnull", Func::IfNull),
+    ("now", Func::Now),
+    ("current_date", Func::CurrentDate),
+    ("year", Func::Year),
+    ("month", Func::Month),
+    ("day", Func::Day),
+    ("nextval", Func::NextVal),
+    ("currval", Func::CurrVal),
+];
+
+impl Func {
+    pub fn from_name(name: &str) -> Option<Self> {
+        FUNCS.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)).map(|(_, func)| func.clone())
+    }
+
+    pub fn name(&self) -> &'static str {
+        FUNCS.iter().find(|(_, func)| func == self).unwrap().0
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+impl BinOp {
+    fn precedence(&self) -> u8 {
+        match self {
+            BinOp::Add | BinOp::Sub => 1,
+            BinOp::Mul | BinOp::Div => 2,
+        }
+    }
+
+    fn symbol(&self) -> &'static str {
+        match self {
+            BinOp::Add => "+",
+            BinOp::Sub => "-",
+            BinOp::Mul => "*",
+            BinOp::Div => "/",
+        }
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -250,15 +877,192 @@ pub enum Op {
     Greater,
 }
 
+impl Op {
+    // the operator that gives the same result with both sides swapped
+    pub fn flip(&self) -> Self {
+        match self {
+            Op::LessEqual => Op::GreaterEqual,
+            Op::GreaterEqual => Op::LessEqual,
+            Op::Less => Op::Greater,
+            Op::Greater => Op::Less,
+            op => op.clone(),
+        }
+    }
+
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Op::Equal => "=",
+            Op::NotEqual => "<>",
+            Op::LessEqual => "<=",
+            Op::GreaterEqual => ">=",
+            Op::Less => "<",
+            Op::Greater => ">",
+        }
+    }
+}
+
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct SetClause {
     pub col_name: Name,
-    pub value: Value,
+    pub expr: Expr,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OrderClause {
+    pub col: Column,
+    pub asc: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LimitClause {
+    pub limit: usize,
+    pub offset: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SetOp {
+    Union,
+    Intersect,
+    Except,
 }
 
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum JoinKind {
+    Inner,
+    Left,
+    Right,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum JoinCondition {
+    On(Vec<WhereClause>),
+    Using(Vec<Name>),
+    Natural,
+}
+
+// a table of the from list, its columns are qualified by the alias if there is one
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TableRef {
+    pub tb_name: Name,
+    pub alias: Option<Name>,
+}
+
+impl TableRef {
+    pub fn name(&self) -> &Name {
+        self.alias.as_ref().unwrap_or(&self.tb_name)
+    }
+}
+
+// joins `tb_name`, the name or alias of a table, to the tables before it in the same from item,
+// a cross join has no condition
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Join {
+    pub kind: JoinKind,
+    pub tb_name: Name,
+    pub cond: Option<JoinCondition>,
+}
+
+impl Join {
+    pub fn values_mut(&mut self) -> Vec<&mut Value> {
+        match &mut self.cond {
+            Some(JoinCondition::On(clauses)) => clauses.iter_mut().flat_map(|clause| clause.values_mut()).collect(),
+            _ => vec![],
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HavingClause {
+    pub item: SelectItem,
+    pub op: Op,
+    pub expr: Expr,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AggrFunc {
+    Count,
+    Sum,
+    Avg,
+    Min,
+    Max,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Aggregate {
+    pub func: AggrFunc,
+    pub col: Option<Column>, // None for count(*)
+}
+
+impl Aggregate {
+    pub fn name(&self) -> String {
+        let func = match self.func {
+            AggrFunc::Count => "count",
+            AggrFunc::Sum => "sum",
+            AggrFunc::Avg => "avg",
+            AggrFunc::Min => "min",
+            AggrFunc::Max => "max",
+        };
+        match &self.col {
+            Some(col) => format!("{}({})", func, col.name()),
+            None => format!("{}(*)", func),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SelectItem {
+    Column(Column),
+    Aggregate(Aggregate),
+    Expr(Expr),
+}
+
+impl SelectItem {
+    pub fn from_expr(expr: Expr) -> Self {
+        match expr {
+            Expr::Column(col) => SelectItem::Column(col),
+            expr => SelectItem::Expr(expr),
+        }
+    }
+
+    pub fn values_mut(&mut self) -> Vec<&mut Value> {
+        match self {
+            SelectItem::Expr(expr) => expr.values_mut(),
+            _ => vec![],
+        }
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            SelectItem::Column(col) => col.name(),
+            SelectItem::Aggregate(aggr) => aggr.name(),
+            SelectItem::Expr(expr) => expr.name(),
+        }
+    }
+}
+
+// the second list holds the alias of every item
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Selector {
     All,
-    Columns(Vec<Column>),
+    Columns(Vec<SelectItem>, Vec<Option<Name>>),
+}
+
+impl Selector {
+    pub fn values_mut(&mut self) -> Vec<&mut Value> {
+        match self {
+            Selector::All => vec![],
+            Selector::Columns(items, _) => items.iter_mut().flat_map(|item| item.values_mut()).collect(),
+        }
+    }
+
+    pub fn has_aggregate(&self) -> bool {
+        match self {
+            Selector::All => false,
+            Selector::Columns(items, _) => items.iter().any(|item| match item {
+                SelectItem::Aggregate(_) => true,
+                _ => false,
+            }),
+        }
+    }
 }
\ No newline at end of file
@@ -4,12 +4,66 @@ use super::lrsql;
 
 use crate::utils;
 
+use std::ops::Range;
+
 pub type ParseError<'input> = lalrpop_util::ParseError<usize, tok::Tok<'input>, tok::Error>;
 
 pub fn parse_sql<'input>(input: &'input str) -> Result<ast::Sql, ParseError<'input>> {
     let tokenizer = tok::Tokenizer::new(input, 0);
-    let sql = lrsql::SqlParser::new().parse(input, tokenizer)?;
-    Ok(sql)
+    lrsql::SqlParser::new().parse(input, tokenizer)
+}
+
+// the error with its line and column and the lines of the statement with a caret under the bad token,
+// `stmt` is where the statement that failed to parse lies in `input`
+pub fn describe_error(input: &str, stmt: Range<usize>, err: &ParseError) -> String {
+    use lalrpop_util::ParseError::*;
+
+    let text = &input[stmt.clone()];
+    let end = text.trim_end().len();
+    let (l, r, what, expected) = match err {
+        InvalidToken { location } => (*location, *location + 1, "invalid token".to_string(), None),
+        UnrecognizedEOF { location: _, expected } => (end, end + 1, "unexpected end of input".to_string(), Some(expected)),
+        UnrecognizedToken { token: (l, _, r), expected } => (*l, *r, format!("unexpected `{}`", &text[*l..*r]), Some(expected)),
+        ExtraToken { token: (l, _, r) } => (*l, *r, format!("unexpected `{}`", &text[*l..*r]), None),
+        User { error } => (error.location, error.location + 1, error.code.message().to_string(), None),
+    };
+    let (l, r) = (stmt.start + l, stmt.start + r);
+
+    let line_start = input[..l].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = input[l..].find('\n').map_or(input.len(), |i| l + i);
+    let line = input[..l].matches('\n').count() + 1;
+    let column = input[line_start..l].chars().count() + 1;
+    let mut message = format!("syntax error at line {}, column {}: {}", line, column, what);
+    if let Some(expected) = expected {
+        // a misplaced keyword can be followed by most of the grammar
+        let shown: Vec<&str> = expected.iter().take(12).map(|e| e.as_str()).collect();
+        message += &format!(", expected {}{}", shown.join(", "), if expected.len() > shown.len() {", ..."} else {""});
+    }
+
+    let first = input[stmt.start..l].find(|c: char| !c.is_whitespace()).map_or(l, |i| stmt.start + i);
+    let shown_start = input[..first].rfind('\n').map_or(0, |i| i + 1);
+    let caret: String = input[line_start..l].chars().map(|c| if c == '\t' {'\t'} else {' '}).collect();
+    let width = std::cmp::max(1, input[l..std::cmp::min(r, line_end)].chars().count());
+    let after = if line_end < stmt.end {input[line_end..stmt.end].trim_end()} else {""};
+    format!("{}\n{}\n{}{}{}", message, &input[shown_start..line_end], caret, "^".repeat(width), after)
+}
+
+// reads back the select of a stored view
+pub fn parse_select(definition: &str) -> Option<ast::TableStmt> {
+    let input = format!("{};", definition);
+    match parse_sql(&input).ok()?.stmt_list.pop() {
+        Some(ast::Stmt::Table(query @ ast::TableStmt::Select { .. })) |
+        Some(ast::Stmt::Table(query @ ast::TableStmt::Compound { .. })) => Some(query),
+        _ => None,
+    }
+}
+
+// reads back the condition of a stored CHECK
+pub fn parse_condition(definition: &str) -> Option<Vec<ast::WhereClause>> {
+    match parse_select(&format!("select * from t where {}", definition))? {
+        ast::TableStmt::Select { where_clause, .. } => where_clause,
+        _ => None,
+    }
 }
 
 // pub fn parse(input: &str) -> Option<ast::Sql> {
@@ -43,10 +97,59 @@ mod tests {
             "delete from tb;",
             "delete from tb where id = number;",
             "delete from tb where id = number and id = 1;",
+            "delete from tb where id = 1 or id = 2;",
+            "select * from tb where not (c1 = 1 or c2 is null) and (c3 > 1 or not c4 <> tb.c5);",
             "update tb set id = 1;",
             "update tb set id = 1 where id = number;",
             "select * from tb;",
             "select tb.c1 from tb, tb2 where tb1.id = \"1\";",
+            "select * from tb order by c1;",
+            "select * from tb where c1 > 1 order by tb.c1 desc, c2 asc;",
+            "select count(*) from tb;",
+            "select c1, count(c2), sum(tb.c3), avg(c3), min(c4), max(c4) from tb where c1 > 1 group by c1;",
+            "select c1, c2, count(*) from tb group by c1, c2 having count(*) > 1 and max(c3) <= c2 order by c1;",
+            "select c1 + c2 * 2, -(c3 - 1.5) / tb.c4, c1 from tb where c1 * 2 > c2 - -1 and 3 <= c3;",
+            "select c1, sum(c2) from tb group by c1 having sum(c2) > c1 * 2;",
+            "update tb set c1 = c1 + 1, c2 = -c2, c3 = \"s\" where (c1 + 1) * 2 >= 10;",
+            "select * from tb limit 10;",
+            "select c1 from tb where c1 > 1 order by c1 desc limit 10 offset 20;",
+            "select * from tb where c1 like \"ab%\" and c2 not like 'x!_%' escape '!' or tb.c3 like \"%\";",
+            "select * from tb where c1 in (1, -2, 3) and c2 not in (\"a\") or c3 between 1 and 10 and c4 not between 'x' and 'y';",
+            "select * from tb where c1 in (select c2 from tb2 where c3 > 1) and not exists (select * from tb2 where tb2.c2 = tb.c1);",
+            "select * from tb where c1 > (select max(c2) from tb2) + 1 and c2 not in (select c3 from tb3 limit 5);",
+            "select * from tb join tb2 on tb.c1 = tb2.c1 left outer join tb3 using (c2) where tb3.c2 is null;",
+            "select * from tb right join tb2 on c1 = c2 and c3 > 1, tb3 cross join tb4 natural join tb5 inner join tb6 using (c1, c2);",
+            "select a.c1 as x, count(*) as n from tb as a join tb as b on a.c1 = b.c2 group by a.c1;",
+            "select c1 + 1 as c2 from tb as t1, tb2 as t2 where t1.c1 = t2.c1;",
+            "select distinct c1, c2 from tb where c1 > 1 order by c1 limit 3;",
+            "insert into tb (c1, c3) values (1, \"a\"), (2, null);",
+            "insert into tb (c1) select c2 from tb2 where c2 > 1 union select c3 from tb3;",
+            "create table tb as select c1, count(*) as n from tb2 group by c1;",
+            "create view v as select c1 from tb where c2 > 1 union select c1 from tb2; drop view v;",
+            "select c1 from tb union all select c2 from tb2 except select c3 from tb3 intersect select c4 from tb4 order by c1 desc;",
+            "explain select * from tb where c1 > 1; explain query plan delete from tb where c1 = 2;",
+            "explain analyze select c1 from tb union select c2 from tb2;",
+            "select * from tb where c1 = ? and c2 in (?, $3) and ? < c4 + 1;",
+            "insert into tb values (?, ?2, null); update tb set c1 = $1 where c2 = $2;",
+            "select c1 -- the key; ignored\n from tb /* where c1 = 1; */ where c2 = \"a;b\";",
+            "create table `order` (`select` int, \"Key\" varchar(10)); select `order`.`select` from `order` where `Key` = \"Key\";",
+            "insert into \"order\" (\"select\", `a``b`) values (1, 2); update `order` set \"Key\" = \"x\";",
+            "select upper(c1), substr(c2, 1, 3), round(c3 * 2, -1), coalesce(c4, now()) from tb where year(c5) = 2020 and length(trim(c2)) > current_date;",
+            "update tb set c1 = concat(lower(c1), \"!\"), c2 = abs(c2) where ifnull(c3, 0) < ceil(c4);",
+            "select case when c1 > 1 and c2 is null then \"a\" when c1 in (1, 2) then \"b\" else \"c\" end from tb;",
+            "select cast(c1 as numeric(10, 2)), case c2 when 1 then cast(c3 as varchar(5)) end from tb where cast(c4 as int) > 3;",
+            "create table tb (c1 int unique, c2 int default 1 unique, c3 int, unique (c1, c3));",
+            "create unique index idx on tb (c1, c2);",
+            "create table tb (c1 int check (c1 >= 0), c2 date, c3 date, constraint ck check (c3 > c2 or c3 is null));",
+            "alter table tb add constraint ck check (c1 between 1 and 9);",
+            "alter table tb drop check ck;",
+            "alter table tb drop constraint ck;",
+            "create table tb (c1 int not null auto_increment, c2 int, primary key (c1));",
+            "create sequence seq increment by -2 start with 10;",
+            "insert into tb values (nextval('seq'), currval('seq')), (null, 1);",
+            "select nextval('seq'), currval(\"seq\") + 1 from tb; update tb set c1 = nextval('seq') where c2 = 1;",
+            "create table tb (c1 int, c2 int, foreign key (c2) references tb2 (c1) on delete cascade on update set null);",
+            "alter table tb add constraint fk foreign key (c1) references ftb (c4) on update no action;",
             "create index idx on tb (c1, c2);",
             "drop index idx on tb;",
             "alter table tb add index idx (c1, c2);",
@@ -57,6 +160,7 @@ mod tests {
             "alter table tb2 rename to tb4;",
             "alter table pk add primary key (c1, c2);",
             "alter table pk drop primary key;",
+            "alter table tb add unique (c1, c2);",
             "alter table tb add constraint pk primary key (c1, c2);",
             "alter table tb drop primary key pk;",
             "alter table tb add constraint fk foreign key (c1, c2) references ftb (c4, c5);",
@@ -64,6 +168,52 @@ mod tests {
         ];
         let incorrect = vec![
             "1;",
+            "select c1 + from tb;",
+            "update tb set c1 = c1 * * 2;",
+            "select * from tb offset 1;",
+            "select * from tb where c1 like c2;",
+            "select * from tb where c1 in ();",
+            "select * from tb where c1 between 1;",
+            "select * from tb limit -1;",
+            "select * from tb limit 99999999999999999999999;",
+            "select * from tb limit 1 offset 99999999999999999999999;",
+            "select * from tb where exists select * from tb2;",
+            "select * from tb where c1 in (select * from tb2;",
+            "select * from tb join tb2;",
+            "select * from tb left join tb2 using c1;",
+            "select c1 as from tb;",
+            "select * from tb as;",
+            "select c1 from tb order by c1 union select c2 from tb2;",
+            "select c1 from tb union distinct select c2 from tb2;",
+            "insert into tb () values (1);",
+            "insert into tb (c1) values select c2 from tb2;",
+            "create table tb as (c1 int);",
+            "create view v as insert into tb values (1);",
+            "explain;",
+            "explain analyze query plan select * from tb;",
+            "select * from tb where c1 = $x;",
+            "select * from tb where c1 = ?0;",
+            "select * from tb /* unterminated;",
+            "select * from tb where c1 = 1 and;",
+            "create table `a/b` (c1 int);",
+            "drop database `.`;",
+            "drop database `..`;",
+            "create table `.hidden` (c1 int);",
+            "create table `a b` (c1 int);",
+            "create index `i\tx` on tb (c1);",
+            "select * from tb where `c1 = 1;",
+            "select explode(c1) from tb;",
+            "select upper(c1 from tb;",
+            "select case c1 end from tb;",
+            "select cast(c1 int) from tb;",
+            "create index unique idx on tb (c1);",
+            "create table tb (c1 int, check c1 > 0);",
+            "alter table tb add check (c1 > 0);",
+            "alter table tb add unique ();",
+            "alter table tb add unique c1;",
+            "create sequence seq start with 1 increment by 2;",
+            "create table tb (c1 int, foreign key (c1) references tb2 (c1) on update cascade on delete restrict);",
+            "alter table tb add constraint fk foreign key (c1) references ftb (c4) on delete set;",
         ];
 
         for s in &correct {
@@ -74,6 +224,55 @@ mod tests {
             assert!(parse_sql(s).is_err());
         }
     }
+
+    #[test]
+    fn split_test() {
+        use crate::parser::tok::split_statements;
+        assert_eq!(split_statements("select 1; select 2;"), (vec!["select 1;", " select 2;"], ""));
+        // a `;` inside a literal, a quoted name or a comment does not end the statement
+        assert_eq!(split_statements("insert into t values (\"a;b\", 'c;d');"), (vec!["insert into t values (\"a;b\", 'c;d');"], ""));
+        assert_eq!(split_statements("select `a;b`, [c;d] from t; -- e;f\nselect"), (vec!["select `a;b`, [c;d] from t;"], " -- e;f\nselect"));
+        assert_eq!(split_statements("select /* a; */ 1; /* b;"), (vec!["select /* a; */ 1;"], " /* b;"));
+        assert_eq!(split_statements("insert into t values (\"a\"\"b;"), (vec![], "insert into t values (\"a\"\"b;"));
+        assert_eq!(split_statements("insert into t values (\"a\"\"b;\");"), (vec!["insert into t values (\"a\"\"b;\");"], ""));
+    }
+
+    #[test]
+    fn unquote_test() {
+        use crate::parser::tok::{unquote, ErrorCode};
+        assert_eq!(unquote("order", '`', 0, "`order`").ok().unwrap(), "order");
+        assert_eq!(unquote("a``b.c", '`', 0, "`a``b.c`").ok().unwrap(), "a`b.c");
+        assert_eq!(unquote("Key\"\"", '"', 0, "\"Key\"\"\"").ok().unwrap(), "Key\"");
+        // names become file names and the parts of stored index names
+        for name in &["", ".", "..", ".hidden", "a/b", "a\\b", "a\0b", "a b", "a\tb", "a\n", "\u{3000}"] {
+            let err = unquote(name, '`', 3, "select 1").err().unwrap();
+            assert_eq!((err.location, err.line), (3, 1));
+            assert!(err.code == ErrorCode::BadQuotedIdentifier, "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn describe_error_test() {
+        use crate::parser::sql::{parse_sql, describe_error};
+        let input = "select 1;\nselect *\n  frm t;\nselect 2;";
+        let stmt = 9..input.len() - 9;
+        let err = parse_sql(&input[stmt.clone()]).err().unwrap();
+        let message = describe_error(input, stmt, &err);
+        let lines: Vec<&str> = message.lines().collect();
+        assert!(lines[0].starts_with("syntax error at line 3, column 3: unexpected `frm`, expected "));
+        assert_eq!(&lines[1..], &["select *", "  frm t;", "  ^^^"]);
+
+        let input = "select * from t where a = \"x;";
+        let err = parse_sql(input).err().unwrap();
+        assert_eq!(describe_error(input, 0..input.len(), &err), "syntax error at line 1, column 27: unterminated quote\nselect * from t where a = \"x;\n                          ^");
+        let input = "select * from\n\tt where;";
+        let err = parse_sql(input).err().unwrap();
+        // the caret keeps the tabs of the line so that it lines up
+        let message = describe_error(input, 0..input.len(), &err);
+        let lines: Vec<&str> = message.lines().collect();
+        assert!(lines[0].starts_with("syntax error at line 2, column 9: unexpected `;`, expected "));
+        assert_eq!(&lines[1..], &["select * from", "\tt where;", "\t       ^"]);
+    }
 }
 
 // pub fn parse(sql: &String) {
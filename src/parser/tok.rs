@@ -26,6 +26,26 @@ pub enum ErrorCode {
     ExpectedEqualsSign,
     MalformedBlobLiteral,
     MalformedHexInteger,
+    BadQuotedIdentifier,
+    UnknownFunction,
+}
+
+impl ErrorCode {
+    pub fn message(&self) -> &'static str {
+        match self {
+            UnrecognizedToken => "unrecognized token",
+            UnterminatedLiteral => "unterminated quote",
+            UnterminatedBracket => "unterminated bracket",
+            UnterminatedBlockComment => "unterminated block comment",
+            BadVariableName => "bad placeholder name",
+            BadNumber => "bad number",
+            ExpectedEqualsSign => "expected `=`",
+            MalformedBlobLiteral => "malformed blob literal",
+            MalformedHexInteger => "malformed hex integer",
+            BadQuotedIdentifier => "bad quoted identifier",
+            UnknownFunction => "unknown function",
+        }
+    }
 }
 
 fn error<T>(c: ErrorCode, l: usize, t: &str) -> Result<T, Error> {
@@ -61,6 +81,7 @@ pub enum Tok<'input> {
     As,
     Asc,
     Attach,
+    Avg,
     Autoincr,
     Before,
     Begin,
@@ -79,6 +100,7 @@ pub enum Tok<'input> {
     Conflict,
     Constraint,
     Copy,
+    Count,
     Create,
     Cross,
     CurrentDate,
@@ -118,6 +140,7 @@ pub enum Tok<'input> {
     Ignore,
     Immediate,
     In,
+    Increment,
     Index,
     Indexed,
     Initially,
@@ -137,6 +160,8 @@ pub enum Tok<'input> {
     Like,
     Limit,
     Match,
+    Max,
+    Min,
     Natural,
     No,
     Not,
@@ -167,8 +192,11 @@ pub enum Tok<'input> {
     Row,
     Savepoint,
     Select,
+    Sequence,
     Set,
     Show,
+    Start,
+    Sum,
     Table,
     Tables,
     Temp,
@@ -195,6 +223,7 @@ pub enum Tok<'input> {
     StringLiteralDoubleQuote(&'input str),
     StringLiteralSingleQuote(&'input str),
     Id(&'input str),
+    QuotedId(&'input str),
     Variable(&'input str),
 
     // Values:
@@ -240,7 +269,9 @@ const KEYWORDS: &'static [(&'static str, Tok<'static>)] = &[
     ("AS", As),
     ("ASC", Asc),
     ("ATTACH", Attach),
+    ("AVG", Avg),
     ("AUTOINCREMENT", Autoincr),
+    ("AUTO_INCREMENT", Autoincr),
     ("BEFORE", Before),
     ("BEGIN", Begin),
     ("BETWEEN", Between),
@@ -258,6 +289,7 @@ const KEYWORDS: &'static [(&'static str, Tok<'static>)] = &[
     ("CONFLICT", Conflict),
     ("CONSTRAINT", Constraint),
     ("COPY", Copy),
+    ("COUNT", Count),
     ("CREATE", Create),
     ("CROSS", Cross),
     ("CURRENT_DATE", CurrentDate),
@@ -296,6 +328,7 @@ const KEYWORDS: &'static [(&'static str, Tok<'static>)] = &[
     ("IGNORE", Ignore),
     ("IMMEDIATE", Immediate),
     ("IN", In),
+    ("INCREMENT", Increment),
     ("INDEX", Index),
     ("INDEXED", Indexed),
     ("INITIALLY", Initially),
@@ -314,6 +347,8 @@ const KEYWORDS: &'static [(&'static str, Tok<'static>)] = &[
     ("LIKE", Like),
     ("LIMIT", Limit),
     ("MATCH", Match),
+    ("MAX", Max),
+    ("MIN", Min),
     ("NATURAL", Natural),
     ("NO", No),
     ("NOT", Not),
@@ -344,8 +379,11 @@ const KEYWORDS: &'static [(&'static str, Tok<'static>)] = &[
     ("ROW", Row),
     ("SAVEPOINT", Savepoint),
     ("SELECT", Select),
+    ("SEQUENCE", Sequence),
     ("SET", Set),
     ("SHOW", Show),
+    ("START", Start),
+    ("SUM", Sum),
     ("TABLE", Table),
     ("TABLES", Tables),
     ("TEMP", Temp),
@@ -489,8 +527,7 @@ impl<'input> Tokenizer<'input> {
                     self.bump();
                     Some(Ok((idx0, BitNot, idx0 + 1)))
                 }
-                // Some((idx0, c)) if c == '`' || c == '\'' || c == '"' => Some(self.literal(idx0, c)),
-                Some((idx0, c)) if c == '\'' || c == '"' => Some(self.literal(idx0, c)),
+                Some((idx0, c)) if c == '`' || c == '\'' || c == '"' => Some(self.literal(idx0, c)),
 
                 Some((idx0, '.')) => match self.bump() {
                     Some((_, c)) if c.is_digit(10) => Some(self.fractional_part(idx0)),
@@ -568,6 +605,7 @@ impl<'input> Tokenizer<'input> {
                 let tok = match delim {
                     '\'' => StringLiteralSingleQuote(text),
                     '\"' => StringLiteralDoubleQuote(text),
+                    '`' => QuotedId(text),
                     _ => unreachable!(),
                 };
                 // let tok = if delim == '\'' {
@@ -834,3 +872,53 @@ fn is_identifier_continue(c: char) -> bool {
         || (c >= 'a' && c <= 'z')
         || c > '\x7F'
 }
+
+// the name a quoted identifier stands for, a doubled quote inside is the quote itself;
+// names end up in file names, so they can neither be empty, hold a path separator nor start with a dot,
+// and whitespace separates the parts of the stored index names
+pub fn unquote(text: &str, quote: char, location: usize, input: &str) -> Result<String, Error> {
+    let name = text.replace(&format!("{}{}", quote, quote), &quote.to_string());
+    if name.is_empty() || name.starts_with('.') || name.contains(|c: char| c == '/' || c == '\\' || c == '\0' || c.is_whitespace()) {
+        error(BadQuotedIdentifier, location, input)
+    } else {
+        Ok(name)
+    }
+}
+
+// splits off the statements of `text` that end with a `;` outside literals and comments,
+// the rest still waits for its end
+pub fn split_statements(text: &str) -> (Vec<&str>, &str) {
+    let mut stmts = vec![];
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((idx, c)) = chars.next() {
+        let terminated = match c {
+            ';' => {
+                stmts.push(&text[start..idx + 1]);
+                start = idx + 1;
+                true
+            }
+            // a doubled delimiter inside a literal reads as two literals, which splits the same
+            '\'' | '"' | '`' => chars.by_ref().any(|(_, nc)| nc == c),
+            '[' => chars.by_ref().any(|(_, nc)| nc == ']'),
+            '-' if chars.peek().map(|&(_, nc)| nc) == Some('-') => {
+                chars.by_ref().any(|(_, nc)| nc == '\n');
+                true
+            }
+            '/' if chars.peek().map(|&(_, nc)| nc) == Some('*') => {
+                chars.next();
+                let mut pc = '\0';
+                chars.by_ref().any(|(_, nc)| {
+                    let end = pc == '*' && nc == '/';
+                    pc = nc;
+                    end
+                })
+            }
+            _ => true,
+        };
+        if !terminated {
+            break;
+        }
+    }
+    (stmts, &text[start..])
+}
@@ -35,6 +35,8 @@ impl FileHandler {
             header.btrees_ptr = 0;
             header.column_types_ptr = 0;
             header.btree = 0;
+            header.checks_ptr = 0;
+            header.auto_increment = 0;
         }
         s
     }
@@ -214,7 +216,10 @@ impl FileHandler {
         let data = data.encode::<Size>().unwrap();
         let mut d_offset = 0; 
         let mut t = ptr;
-        while d_offset < data.len() {
+        // the first slot is written even for empty data, so that nothing of the old value is left
+        let mut first = true;
+        while first || d_offset < data.len() {
+            first = false;
             let sp = unsafe{self.sp_mut(t.page)};
             let ss = &mut sp.strs[t.offset as usize];
             let len = min(data.len() - d_offset, length);
@@ -292,4 +297,24 @@ impl FileHandler {
         let header = unsafe { self.header_mut() };
         header.btree = ptr;
     }
+
+    pub fn get_checks_ptr(&self) -> u64 {
+        let header = unsafe { self.header_mut() };
+        header.checks_ptr
+    }
+
+    pub fn set_checks_ptr(&self, ptr: u64) {
+        let header = unsafe { self.header_mut() };
+        header.checks_ptr = ptr;
+    }
+
+    pub fn get_auto_increment(&self) -> u64 {
+        let header = unsafe { self.header_mut() };
+        header.auto_increment
+    }
+
+    pub fn set_auto_increment(&self, value: u64) {
+        let header = unsafe { self.header_mut() };
+        header.auto_increment = value;
+    }
 }
\ No newline at end of file
@@ -0,0 +1,200 @@
+use crate::parser::ast::{self, Func};
+use crate::utils::convert;
+use super::record::*;
+
+fn is_str(ty: &Type) -> bool {
+    match ty {
+        Type::Str(_) => true,
+        _ => false,
+    }
+}
+
+fn is_int(ty: &Type) -> bool {
+    match ty {
+        Type::Int(_) => true,
+        _ => false,
+    }
+}
+
+fn is_date(ty: &Type) -> bool {
+    match ty {
+        Type::Date(_) => true,
+        _ => false,
+    }
+}
+
+// NULL and placeholders have no type of their own as arguments
+pub fn known_type(arg: &ast::Expr, ty: &Type) -> Option<Type> {
+    match arg {
+        ast::Expr::Value(ast::Value::Null) | ast::Expr::Value(ast::Value::Param(_)) => None,
+        _ => Some(ty.without_default()),
+    }
+}
+
+// the type of a call, None when the arguments don't fit the function; an argument of unknown type fits anywhere
+pub fn result_type(func: &Func, args: &Vec<Option<Type>>) -> Option<Type> {
+    let is = |i: usize, pred: fn(&Type) -> bool| args[i].as_ref().map_or(true, pred);
+    let first = || args[0].clone().unwrap_or(Type::Int(None));
+    match (func, args.len()) {
+        (Func::Upper, 1) | (Func::Lower, 1) | (Func::Trim, 1) if is(0, is_str) => Some(Type::Str(None)),
+        (Func::Length, 1) if is(0, is_str) => Some(Type::Int(None)),
+        (Func::Substr, 2) | (Func::Substr, 3) if is(0, is_str) && (1..args.len()).all(|i| is(i, is_int)) => Some(Type::Str(None)),
+        (Func::Concat, n) if n > 0 => Some(Type::Str(None)),
+        (Func::Abs, 1) | (Func::Round, 1) if is(0, Type::is_numeric) => Some(first()),
+        (Func::Round, 2) if is(0, Type::is_numeric) && is(1, is_int) => Some(first()),
+        (Func::Floor, 1) | (Func::Ceil, 1) if is(0, Type::is_numeric) => Some(Type::Int(None)),
+        (Func::Coalesce, n) | (Func::IfNull, n) if n > 0 && (func == &Func::Coalesce || n == 2) => common_type(args),
+        (Func::Now, 0) | (Func::CurrentDate, 0) => Some(Type::Date(None)),
+        (Func::Year, 1) | (Func::Month, 1) | (Func::Day, 1) if is(0, is_date) => Some(Type::Int(None)),
+        (Func::NextVal, 1) | (Func::CurrVal, 1) if is(0, is_str) => Some(Type::Int(None)),
+        (_, _) => None,
+    }
+}
+
+// the type all the values meet in, e.g. the arguments of COALESCE or the results of CASE
+pub fn common_type(types: &Vec<Option<Type>>) -> Option<Type> {
+    let mut ty: Option<Type> = None;
+    for other in types.iter().flatten() {
+        ty = Some(match ty {
+            Some(ty) => ty.unify(other)?,
+            None => other.clone(),
+        });
+    }
+    Some(ty.unwrap_or(Type::Int(None)))
+}
+
+// calls `func` on the arguments with their types, `ct` is the type of the call; a NULL argument gives NULL
+// except for COALESCE and IFNULL, and so do overflow and arguments of another type
+pub fn call(func: &Func, args: Vec<(Option<Data>, ColumnType)>, ct: &ColumnType) -> Option<Data> {
+    match func {
+        Func::Coalesce | Func::IfNull => {
+            let (data, from) = args.into_iter().find(|(data, _)| data.is_some())?;
+            return data?.cast(&from, ct);
+        },
+        Func::Now | Func::CurrentDate => return Some(Data::Date(convert::today())),
+        // the numbers of a sequence are put in place before the expression is evaluated
+        Func::NextVal | Func::CurrVal => return None,
+        _ => {},
+    }
+    let mut data = vec![];
+    for (d, from) in args {
+        data.push((d?, from));
+    }
+    match (func, &data[..]) {
+        (Func::Upper, [(Data::Str(s), _)]) => Some(Data::Str(s.to_uppercase())),
+        (Func::Lower, [(Data::Str(s), _)]) => Some(Data::Str(s.to_lowercase())),
+        (Func::Trim, [(Data::Str(s), _)]) => Some(Data::Str(s.trim_matches(' ').to_string())),
+        (Func::Length, [(Data::Str(s), _)]) => Some(Data::Int(s.chars().count() as i64)),
+        (Func::Substr, [(Data::Str(s), _), (Data::Int(start), _)]) => Some(Data::Str(substr(s, *start, i64::max_value()))),
+        (Func::Substr, [(Data::Str(s), _), (Data::Int(start), _), (Data::Int(len), _)]) => Some(Data::Str(substr(s, *start, *len))),
+        (Func::Concat, data) => Some(Data::Str(data.iter().map(|(d, from)| d.to_string(from)).collect())),
+        (Func::Abs, [(Data::Int(d), _)]) => d.checked_abs().map(Data::Int),
+        (Func::Abs, [(Data::Float(d), _)]) => Some(Data::Float(d.abs())),
+        (Func::Abs, [(Data::Numeric(d), from)]) => d.checked_abs().and_then(|d| Data::Numeric(d).cast(from, ct)),
+        (Func::Round, [(d, from)]) => round(d, from, 0)?.cast(from, ct),
+        (Func::Round, [(d, from), (Data::Int(digits), _)]) => round(d, from, *digits)?.cast(from, ct),
+        (Func::Floor, [(d, from)]) => floor(d, from, false).map(Data::Int),
+        (Func::Ceil, [(d, from)]) => floor(d, from, true).map(Data::Int),
+        (Func::Year, [(Data::Date(d), _)]) => Some(Data::Int((d >> 9) as i64)),
+        (Func::Month, [(Data::Date(d), _)]) => Some(Data::Int((d >> 5 & 0b1111) as i64)),
+        (Func::Day, [(Data::Date(d), _)]) => Some(Data::Int((d & 0b11111) as i64)),
+        (_, _) => None,
+    }
+}
+
+// positions count from 1, a negative start counts from the end
+fn substr(s: &str, start: i64, len: i64) -> String {
+    let chars: Vec<char> = s.chars().collect();
+    let n = chars.len() as i64;
+    let from = if start > 0 {start - 1} else if start < 0 {n + start} else {n};
+    if from < 0 || len <= 0 {
+        return String::new();
+    }
+    chars.into_iter().skip(from as usize).take(std::cmp::min(len, n) as usize).collect()
+}
+
+// rounds half away from zero to `digits` decimals, which may be negative to round to tens, hundreds, ..
+fn round(d: &Data, from: &ColumnType, digits: i64) -> Option<Data> {
+    let to_multiple = |d: i128, scale: i64| -> Option<i64> {
+        if digits >= scale {
+            return to_i64(d);
+        }
+        match scale.checked_sub(digits) {
+            Some(n) if n <= 38 => {
+                let factor = pow10(n as u8);
+                to_i64(div_round(d, factor) * factor)
+            },
+            _ => Some(0),
+        }
+    };
+    match d {
+        Data::Int(d) => to_multiple(*d as i128, 0).map(Data::Int),
+        Data::Numeric(d) => to_multiple(*d as i128, from.numeric_precision.saturating_sub(1) as i64).map(Data::Numeric),
+        Data::Float(d) => {
+            let factor = 10f64.powi(std::cmp::max(-308, std::cmp::min(308, digits)) as i32);
+            // a negative number rounded to zero gives zero rather than -0
+            Some(Data::Float((d * factor).round() / factor + 0.0))
+        },
+        _ => None,
+    }
+}
+
+// the integer below a number, or above it for `ceil`
+fn floor(d: &Data, from: &ColumnType, ceil: bool) -> Option<i64> {
+    match d {
+        Data::Int(d) => Some(*d),
+        Data::Numeric(d) => {
+            let factor = pow10(from.numeric_precision.saturating_sub(1));
+            let d = *d as i128;
+            to_i64(if ceil {-(-d).div_euclid(factor)} else {d.div_euclid(factor)})
+        },
+        Data::Float(d) => {
+            let d = if ceil {d.ceil()} else {d.floor()};
+            if d >= i64::min_value() as f64 && d < i64::max_value() as f64 {Some(d as i64)} else {None}
+        },
+        _ => None,
+    }
+}
+
+#[test]
+fn function_test() {
+    let int = ColumnType { data_type: Type::Int(None), ..Default::default() };
+    let str_ = ColumnType { data_type: Type::Str(None), ..Default::default() };
+    let date = ColumnType { data_type: Type::Date(None), ..Default::default() };
+    let s = |s: &str| (Some(Data::Str(s.to_string())), str_.clone());
+    let i = |i: i64| (Some(Data::Int(i)), int.clone());
+
+    assert_eq!(substr("hello", 2, 3), "ell");
+    assert_eq!(substr("hello", -3, 2), "ll");
+    assert_eq!(substr("hello", 0, 2), "");
+    assert_eq!(substr("hello", 2, 0), "");
+    assert_eq!(substr("hello", 2, -1), "");
+    assert_eq!(substr("hello", -9, 2), "");
+    assert_eq!(substr("hello", 9, 2), "");
+    assert_eq!(substr("hello", i64::min_value(), i64::max_value()), "");
+    assert_eq!(call(&Func::Substr, vec![s("hello"), i(4)], &str_), Some(Data::Str("lo".to_string())));
+    assert_eq!(call(&Func::Substr, vec![s("hello"), (None, int.clone())], &str_), None);
+
+    assert_eq!(call(&Func::Round, vec![i(1250), i(-2)], &int), Some(Data::Int(1300)));
+    assert_eq!(call(&Func::Round, vec![i(-1250), i(-2)], &int), Some(Data::Int(-1300)));
+    assert_eq!(call(&Func::Round, vec![i(1250), i(3)], &int), Some(Data::Int(1250)));
+    assert_eq!(call(&Func::Round, vec![i(1250), i(-38)], &int), Some(Data::Int(0)));
+    assert_eq!(call(&Func::Round, vec![i(1250), i(i64::min_value())], &int), Some(Data::Int(0)));
+    assert_eq!(call(&Func::Round, vec![i(1250), i(i64::max_value())], &int), Some(Data::Int(1250)));
+    assert_eq!(call(&Func::Round, vec![i(i64::max_value()), i(-1)], &int), None);
+    let float = ColumnType { data_type: Type::Float(None), ..Default::default() };
+    let f = |f: f64| (Some(Data::Float(f)), float.clone());
+    assert_eq!(call(&Func::Round, vec![f(-2.56), i(1)], &float), Some(Data::Float(-2.6)));
+    for (d, digits) in &[(-2.5, -1), (-0.4, 0), (-0.0, 0), (-4.0, -3)] {
+        match call(&Func::Round, vec![f(*d), i(*digits)], &float) {
+            Some(Data::Float(r)) => assert!(r == 0.0 && r.is_sign_positive(), "round({}, {}) = {}", d, digits, r),
+            r => panic!("{:?}", r),
+        }
+    }
+
+    // 2000 is a leap year and 1900 is not, the date keeps what it was given
+    let d = |s: &str| vec![(Some(Data::Date(convert::str_to_date(s))), date.clone())];
+    assert_eq!(call(&Func::Day, d("2000-02-29"), &int), Some(Data::Int(29)));
+    assert_eq!(call(&Func::Month, d("2000-02-29"), &int), Some(Data::Int(2)));
+    assert_eq!(call(&Func::Year, d("1900-02-28"), &int), Some(Data::Int(1900)));
+}
@@ -27,11 +27,17 @@ bytevec_decl! {
         pub numeric_precision: u8,
         /*
             flags [0 .. 8]
-            [can_be_null, has_index, has_default, is_primary, is_foreign, default_null, 0, 0]
+            [can_be_null, has_index, has_default, is_primary, is_foreign, default_null, auto_increment, 0]
         */
         pub flags: u8
     }
 
+    #[derive(PartialEq, Eq, Debug)]
+    pub struct CheckInFile {
+        pub name: u64, // StrPointer
+        pub definition: u64 // StrPointer
+    }
+
     #[derive(PartialEq, Eq, Debug)]
     pub struct RecordInFile {
         pub record: String
@@ -250,7 +256,8 @@ impl ColumnTypeInFile {
                    (ct.has_default as u8) << 2 |
                    (ct.is_primary as u8) << 3 |
                    (ct.is_foreign as u8) << 4 |
-                   (ct.default_null as u8) << 5,
+                   (ct.default_null as u8) << 5 |
+                   (ct.auto_increment as u8) << 6,
         }
     }
 
@@ -278,6 +285,24 @@ impl ColumnTypeInFile {
             is_primary: self.flags & 8 > 0,
             is_foreign: self.flags & 16 > 0,
             default_null: self.flags & 32 > 0,
+            auto_increment: self.flags & 64 > 0,
         }
     }
-}
\ No newline at end of file
+}
+impl CheckInFile {
+    // CheckConstraint to CheckInFile
+    pub fn from(th: &TableHandler, check: &CheckConstraint) -> Self {
+        Self {
+            name: th.insert_string(&check.name).to_u64(),
+            definition: th.insert_string(&check.definition).to_u64(),
+        }
+    }
+
+    // CheckInFile to CheckConstraint
+    pub fn to_check(&self, th: &TableHandler) -> CheckConstraint {
+        CheckConstraint {
+            name: th.get_string(&StrPointer::new(self.name)),
+            definition: th.get_string(&StrPointer::new(self.definition)),
+        }
+    }
+}
@@ -1,5 +1,6 @@
 pub mod record_manager;
 pub mod record;
+pub mod function;
 pub mod filesystem;
 pub mod file_handler;
 pub mod table_handler;
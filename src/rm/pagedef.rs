@@ -82,6 +82,9 @@ pub struct FileHeader {
     pub column_types_ptr: u64,
     pub btree: u64,
     pub btrees_ptr: u64,
+    pub checks_ptr: u64,
+    // the last value given to the auto-increment column
+    pub auto_increment: u64,
 }
 
 
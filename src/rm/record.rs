@@ -1,11 +1,15 @@
 use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
 use crate::parser::ast;
+use crate::parser::sql;
 use crate::utils::convert;
+use crate::utils::string;
 use super::pagedef::StrPointer;
+use super::function;
 
 use crate::defer;
 
-#[derive(Clone, PartialEq, PartialOrd, Debug)]
+#[derive(Clone, PartialOrd, Debug)]
 pub enum Data {
     Str(String),
     Int(i64),
@@ -14,6 +18,36 @@ pub enum Data {
     Numeric(i64),
 } 
 
+// NaN equals itself so that Eq holds and duplicates can be removed by hashing
+impl PartialEq for Data {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Data::Str(l), Data::Str(r)) => l == r,
+            (Data::Int(l), Data::Int(r)) => l == r,
+            (Data::Float(l), Data::Float(r)) => l == r || (l.is_nan() && r.is_nan()),
+            (Data::Date(l), Data::Date(r)) => l == r,
+            (Data::Numeric(l), Data::Numeric(r)) => l == r,
+            (_, _) => false,
+        }
+    }
+}
+
+impl Eq for Data {}
+
+// Float is hashed by its bits, with 0.0 and -0.0 folded together since they compare equal, and all NaNs alike
+impl Hash for Data {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            Data::Str(d) => d.hash(state),
+            Data::Int(d) => d.hash(state),
+            Data::Float(d) => (if *d == 0.0 {0.0f64} else if d.is_nan() {f64::NAN} else {*d}).to_bits().hash(state),
+            Data::Date(d) => d.hash(state),
+            Data::Numeric(d) => d.hash(state),
+        }
+    }
+}
+
 impl Data {
     pub fn from_value(value: &ast::Value, ct: &ColumnType) -> Option<Self> {
         use std::str::FromStr;
@@ -26,16 +60,105 @@ impl Data {
             }
         }
         else {
-            match value {
-                ast::Value::Int(s) => Some(Self::Int(i64::from_str(s).unwrap())),
-                ast::Value::Str(s) => Some(Self::Str(s.to_string())),
-                ast::Value::Date(s) => Some(Self::Date(convert::str_to_date(s))),
-                ast::Value::Float(s) => Some(Self::Float(f64::from_str(s).unwrap())),
-                ast::Value::Null => None,
+            // an Int literal widens into a Float column and a string is read as a date in a Date column
+            match (value, &ct.data_type) {
+                (ast::Value::Int(s), Type::Float(_)) => Some(Self::Float(f64::from_str(s).unwrap())),
+                (ast::Value::Str(s), Type::Date(_)) => Some(Self::Date(convert::str_to_date(s))),
+                (ast::Value::Int(s), _) => Some(Self::Int(i64::from_str(s).unwrap())),
+                (ast::Value::Str(s), _) => Some(Self::Str(s.to_string())),
+                (ast::Value::Date(s), _) => Some(Self::Date(convert::str_to_date(s))),
+                (ast::Value::Float(s), _) => Some(Self::Float(f64::from_str(s).unwrap())),
+                (ast::Value::Null, _) => None,
+                // placeholders are bound and sequences are read before any record is made
+                (ast::Value::Param(_), _) | (ast::Value::NextVal(_), _) | (ast::Value::CurrVal(_), _) => unreachable!(),
             }
         }
     }
 
+    // the literal that `from_value` reads back into the same data, floats use the exponent form to stay floats
+    pub fn to_value(data: &Option<Self>, ct: &ColumnType) -> ast::Value {
+        match data {
+            Some(Data::Str(d)) => ast::Value::Str(d.clone()),
+            Some(Data::Int(d)) => ast::Value::Int(d.to_string()),
+            Some(Data::Float(d)) => ast::Value::Float(format!("{:e}", d)),
+            Some(Data::Date(d)) => ast::Value::Date(convert::date_to_str(*d)),
+            // trailing zeros are dropped so that the literal also fits columns with fewer decimals
+            Some(Data::Numeric(d)) => match convert::numeric_to_str(*d, ct.numeric_precision) {
+                s if s.contains('.') => match s.trim_end_matches('0').trim_end_matches('.') {
+                    s if s.contains('.') => ast::Value::Float(s.to_string()),
+                    s => ast::Value::Int(s.to_string()),
+                },
+                s => ast::Value::Int(s),
+            },
+            None => ast::Value::Null,
+        }
+    }
+
+    // widens along `Type::unify`, Numeric keeps `numeric_precision - 1` decimal digits and a string that is no date gives NULL
+    pub fn cast(self, from: &ColumnType, to: &ColumnType) -> Option<Self> {
+        let scale = |ct: &ColumnType| if ct.numeric_precision > 0 { ct.numeric_precision - 1 } else { 0 };
+        match (self, &to.data_type) {
+            (Data::Int(d), Type::Int(_)) => Some(Data::Int(d)),
+            (Data::Int(d), Type::Float(_)) => Some(Data::Float(d as f64)),
+            (Data::Int(d), Type::Numeric(_)) => to_i64(rescale(d as i128, 0, scale(to))).map(Data::Numeric),
+            (Data::Numeric(d), Type::Float(_)) => Some(Data::Float(d as f64 / pow10(scale(from)) as f64)),
+            (Data::Numeric(d), Type::Numeric(_)) => to_i64(rescale(d as i128, scale(from), scale(to))).map(Data::Numeric),
+            (Data::Float(d), Type::Float(_)) => Some(Data::Float(d)),
+            (Data::Str(d), Type::Str(_)) => Some(Data::Str(d)),
+            (Data::Date(d), Type::Date(_)) => Some(Data::Date(d)),
+            (Data::Str(d), Type::Date(_)) => Some(convert::str_to_date(&d)).filter(|d| *d != 0).map(Data::Date),
+            (_, _) => None,
+        }
+    }
+
+    // the conversions of CAST, which may also narrow or go through a string, NULL if the value does not convert
+    pub fn convert(self, from: &ColumnType, to: &ColumnType) -> Option<Self> {
+        let scale = |ct: &ColumnType| if ct.numeric_precision > 0 { ct.numeric_precision - 1 } else { 0 };
+        let from_f64 = |d: f64, scale: u8| Some(d * pow10(scale) as f64).filter(|d| d.is_finite() && d.abs() < 9.2e18).map(|d| d.round() as i64);
+        match (self, &to.data_type) {
+            (d, Type::Str(_)) => Some(Data::Str(d.to_string(from))),
+            (Data::Float(d), Type::Int(_)) => from_f64(d, 0).map(Data::Int),
+            (Data::Float(d), Type::Numeric(_)) => from_f64(d, scale(to)).map(Data::Numeric),
+            (Data::Numeric(d), Type::Int(_)) => to_i64(div_round(d as i128, pow10(scale(from)))).map(Data::Int),
+            (Data::Str(d), Type::Int(_)) => d.trim().parse().ok().map(Data::Int),
+            (Data::Str(d), Type::Float(_)) => d.trim().parse().ok().map(Data::Float),
+            (Data::Str(d), Type::Numeric(_)) => match d.trim().parse::<i64>() {
+                Ok(d) => Data::Int(d).cast(&Default::default(), to),
+                Err(_) => d.trim().parse().ok().and_then(|d| from_f64(d, scale(to))).map(Data::Numeric),
+            },
+            (d, _) => d.cast(from, to),
+        }
+    }
+
+    // both operands are already cast to `ct`, division by zero and overflow give NULL
+    pub fn arith(op: &ast::BinOp, l: Self, r: Self, ct: &ColumnType) -> Option<Self> {
+        match (l, r) {
+            (Data::Int(l), Data::Int(r)) => match op {
+                ast::BinOp::Add => l.checked_add(r),
+                ast::BinOp::Sub => l.checked_sub(r),
+                ast::BinOp::Mul => l.checked_mul(r),
+                ast::BinOp::Div => l.checked_div(r),
+            }.map(Data::Int),
+            (Data::Float(l), Data::Float(r)) => match op {
+                ast::BinOp::Add => Some(l + r),
+                ast::BinOp::Sub => Some(l - r),
+                ast::BinOp::Mul => Some(l * r),
+                ast::BinOp::Div => if r == 0.0 { None } else { Some(l / r) },
+            }.map(Data::Float),
+            (Data::Numeric(l), Data::Numeric(r)) => {
+                let (l, r) = (l as i128, r as i128);
+                let exp10 = pow10(if ct.numeric_precision > 0 { ct.numeric_precision - 1 } else { 0 });
+                match op {
+                    ast::BinOp::Add => Some(l + r),
+                    ast::BinOp::Sub => Some(l - r),
+                    ast::BinOp::Mul => Some(div_round(l * r, exp10)),
+                    ast::BinOp::Div => if r == 0 { None } else { Some(div_round(l * exp10, r)) },
+                }.and_then(to_i64).map(Data::Numeric)
+            },
+            (_, _) => None,
+        }
+    }
+
     pub fn to_string(&self, ct: &ColumnType) -> String {
         match self {
             Data::Str(d) => d.clone(),
@@ -49,6 +172,66 @@ impl Data {
     }
 }
 
+pub fn pow10(n: u8) -> i128 {
+    10i128.pow(n as u32)
+}
+
+// rounds half away from zero
+pub fn div_round(a: i128, b: i128) -> i128 {
+    let (q, r) = (a / b, a % b);
+    if 2 * r.abs() >= b.abs() {
+        if (a < 0) == (b < 0) { q + 1 } else { q - 1 }
+    }
+    else {
+        q
+    }
+}
+
+fn rescale(d: i128, from: u8, to: u8) -> i128 {
+    if to >= from {
+        d * pow10(to - from)
+    }
+    else {
+        div_round(d, pow10(from - to))
+    }
+}
+
+pub fn to_i64(d: i128) -> Option<i64> {
+    use std::convert::TryFrom;
+    i64::try_from(d).ok()
+}
+
+pub fn compare_data(op: &ast::Op, l_data: &Data, r_data: &Data) -> bool {
+    match op {
+        ast::Op::Equal => l_data == r_data,
+        ast::Op::NotEqual => l_data != r_data,
+        ast::Op::LessEqual => l_data <= r_data,
+        ast::Op::GreaterEqual => l_data >= r_data,
+        ast::Op::Less => l_data < r_data,
+        ast::Op::Greater => l_data > r_data,
+    }
+}
+
+// widens both sides to a common type first, None if they have none
+pub fn compare_typed(op: &ast::Op, l_data: Data, l_ct: &ColumnType, r_data: Data, r_ct: &ColumnType) -> Option<bool> {
+    let ct = ColumnType::unify(l_ct, r_ct);
+    match (l_data.cast(l_ct, &ct), r_data.cast(r_ct, &ct)) {
+        (Some(l_data), Some(r_data)) => Some(compare_data(op, &l_data, &r_data)),
+        _ => None,
+    }
+}
+
+// a literal the column cannot hold exactly, e.g. 2.5 against an Int column, is compared in the type both widen to
+pub fn compare_value(op: &ast::Op, data: Data, ct: &ColumnType, value: &ast::Value) -> Option<bool> {
+    if ct.fits_value(value) {
+        Data::from_value(value, ct).map(|r_data| compare_data(op, &data, &r_data))
+    }
+    else {
+        let r_ct = ColumnType::from_expr(&ast::Expr::Value(value.clone()), &Vec::new());
+        Data::from_value(value, &r_ct).and_then(|r_data| compare_typed(op, data, ct, r_data, &r_ct))
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, PartialOrd)]
 pub enum Type {
     Str(Option<String>),
@@ -69,30 +252,73 @@ impl Type {
             (_, _) => false, 
         }
     }
+
+    // the same storage type, e.g. for keys that reference each other
+    pub fn same(&self, other: &Self) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+    }
+
     pub fn comparable(&self, other: &Self) -> bool {
-        match (self, other) {
-            (Type::Int(_), Type::Int(_)) | 
-            (Type::Str(_), Type::Str(_)) | 
-            (Type::Float(_), Type::Float(_)) | 
-            (Type::Date(_), Type::Date(_)) |
-            (Type::Numeric(_), Type::Numeric(_)) => true,
-            (_, _) => false, 
-        }
+        self.unify(other).is_some()
     }
 
+    // a literal that can be assigned to a column of this type
     pub fn valid_value(&self, value: &ast::Value) -> bool {
         match (self, value) {
             (Type::Int(_), ast::Value::Int(_)) |
             (Type::Str(_), ast::Value::Str(_)) |
             (Type::Float(_), ast::Value::Float(_)) |
+            (Type::Float(_), ast::Value::Int(_)) |
             (Type::Date(_), ast::Value::Date(_)) |
             (Type::Numeric(_), ast::Value::Int(_)) |
             (Type::Numeric(_), ast::Value::Float(_)) |
-            (_, ast::Value::Null) => true,
+            (_, ast::Value::Null) |
+            (_, ast::Value::Param(_)) => true,
+            (Type::Date(_), ast::Value::Str(s)) => convert::str_to_date(s) != 0,
             (_, _) => false,
         }
     }
 
+    // whether CAST can turn a value of this type into `to`
+    pub fn castable(&self, to: &Self) -> bool {
+        match (self, to) {
+            (_, Type::Str(_)) | (Type::Str(_), _) => true,
+            (l, r) => (l.is_numeric() && r.is_numeric()) || l.same(r),
+        }
+    }
+
+    pub fn is_numeric(&self) -> bool {
+        match self {
+            Type::Int(_) | Type::Float(_) | Type::Numeric(_) => true,
+            _ => false,
+        }
+    }
+
+    // the coercion lattice: Int widens to Numeric and both widen to Float, a string widens to a date,
+    // other types only meet themselves
+    pub fn unify(&self, other: &Self) -> Option<Self> {
+        match (self, other) {
+            (l, r) if l.is_numeric() && r.is_numeric() => Some(match (l, r) {
+                (Type::Float(_), _) | (_, Type::Float(_)) => Type::Float(None),
+                (Type::Numeric(_), _) | (_, Type::Numeric(_)) => Type::Numeric(None),
+                (_, _) => Type::Int(None),
+            }),
+            (Type::Str(_), Type::Date(_)) | (Type::Date(_), Type::Str(_)) => Some(Type::Date(None)),
+            (l, r) if l.same(r) => Some(l.without_default()),
+            (_, _) => None,
+        }
+    }
+
+    pub fn without_default(&self) -> Self {
+        match self {
+            Type::Str(_) => Type::Str(None),
+            Type::Int(_) => Type::Int(None),
+            Type::Float(_) => Type::Float(None),
+            Type::Date(_) => Type::Date(None),
+            Type::Numeric(_) => Type::Numeric(None),
+        }
+    }
+
     pub fn from_type(ty: &ast::Type, value: &Option<ast::Value>) -> Self {
         use std::str::FromStr; 
         match ty {
@@ -182,6 +408,7 @@ pub struct ColumnType {
     pub is_primary: bool,
     pub is_foreign: bool,
     pub default_null: bool,
+    pub auto_increment: bool,
     pub foreign_table_name: String,
     pub foreign_table_column: String,
 }
@@ -199,14 +426,14 @@ impl ColumnType {
             self.data_type.to_string(), // Type
             String::from(if self.can_be_null {"YES"} else {"NO"}), // Null
             String::from(if self.is_primary {"PRI"} else if is_mul {"MUL"} else {""}), // Key
-            self.data_type.get_default_string(), // Default
+            if self.auto_increment {String::from("auto_increment")} else {self.data_type.get_default_string()}, // Default
             if self.is_foreign { format!("{}.{}", self.foreign_table_name, self.foreign_table_column)} else {String::from("")},
         ]
     }
 
     pub fn from_field(tb_name: &String, index: u32, field: &ast::Field) -> Self {
         match field {
-            ast::Field::ColumnField { col_name, ty, not_null, default_value } => {
+            ast::Field::ColumnField { col_name, ty, not_null, auto_increment, default_value, .. } => {
                 ColumnType {
                     tb_name: tb_name.clone(),
                     name: col_name.clone(),
@@ -222,6 +449,7 @@ impl ColumnType {
                     is_primary: false,
                     is_foreign: false,
                     default_null: default_value.is_some() && default_value.as_ref().unwrap().is_null(),
+                    auto_increment: *auto_increment,
                     foreign_table_name: String::new(),
                     foreign_table_column: String::new(),
                 }
@@ -229,6 +457,186 @@ impl ColumnType {
             _ => unreachable!(),
         }
     }
+
+    // the type of a value declared as `ty`, e.g. the target of CAST
+    pub fn from_type(ty: &ast::Type) -> Self {
+        ColumnType {
+            data_type: Type::from_type(ty, &None),
+            numeric_precision: match ty {
+                ast::Type::Numeric(_, p) => *p as u8 + 1,
+                _ => 0,
+            },
+            can_be_null: true,
+            ..Default::default()
+        }
+    }
+
+    // a Numeric result keeps the most decimal digits of its arguments
+    fn from_args(data_type: Type, cts: &Vec<ColumnType>) -> Self {
+        let numeric_precision = match data_type {
+            Type::Numeric(_) => cts.iter().map(|ct| ct.numeric_precision).max().unwrap_or(0),
+            _ => 0,
+        };
+        ColumnType {
+            data_type: data_type,
+            numeric_precision: numeric_precision,
+            can_be_null: true,
+            ..Default::default()
+        }
+    }
+
+    pub fn from_aggregate(aggr: &ast::Aggregate, ct: Option<&ColumnType>) -> Self {
+        let (data_type, numeric_precision) = match (&aggr.func, ct) {
+            (ast::AggrFunc::Count, _) | (_, None) => (Type::Int(None), 0),
+            (func, Some(ct)) => (match (func, &ct.data_type) {
+                (ast::AggrFunc::Avg, Type::Int(_)) => Type::Float(None),
+                (_, Type::Str(_)) => Type::Str(None),
+                (_, Type::Int(_)) => Type::Int(None),
+                (_, Type::Float(_)) => Type::Float(None),
+                (_, Type::Date(_)) => Type::Date(None),
+                (_, Type::Numeric(_)) => Type::Numeric(None),
+            }, ct.numeric_precision),
+        };
+        ColumnType {
+            name: aggr.name(),
+            data_type: data_type,
+            numeric_precision: numeric_precision,
+            can_be_null: true,
+            ..Default::default()
+        }
+    }
+
+    // whether `Data::from_value` reads the literal exactly as a value of this column
+    pub fn fits_value(&self, value: &ast::Value) -> bool {
+        match (&self.data_type, value) {
+            (Type::Numeric(_), ast::Value::Float(s)) =>
+                !s.contains(|c| c == 'e' || c == 'E') && s.split('.').nth(1).map_or(0, |d| d.len()) < self.numeric_precision as usize,
+            (data_type, value) => data_type.valid_value(value),
+        }
+    }
+
+    // the value of a column that an insert leaves out
+    pub fn default_value(&self) -> ast::Value {
+        let data = match &self.data_type {
+            Type::Str(d) => d.clone().map(Data::Str),
+            Type::Int(d) => d.map(Data::Int),
+            Type::Float(d) => d.map(Data::Float),
+            Type::Date(d) => d.map(Data::Date),
+            Type::Numeric(d) => d.map(Data::Numeric),
+        };
+        Data::to_value(&data, self)
+    }
+
+    // whether every value of `other` can be stored in this column
+    pub fn accepts(&self, other: &ColumnType) -> bool {
+        other.data_type.unify(&self.data_type).map_or(false, |ty| ty == self.data_type.without_default())
+    }
+
+    pub fn unify(l: &ColumnType, r: &ColumnType) -> Self {
+        let data_type = l.data_type.unify(&r.data_type).unwrap_or(l.data_type.without_default());
+        let numeric_precision = match data_type {
+            Type::Numeric(_) => std::cmp::max(l.numeric_precision, r.numeric_precision),
+            _ => 0,
+        };
+        ColumnType {
+            data_type: data_type,
+            numeric_precision: numeric_precision,
+            can_be_null: true,
+            ..Default::default()
+        }
+    }
+
+    pub fn from_expr(expr: &ast::Expr, ty: &Vec<ColumnType>) -> Self {
+        let mut ct = match expr {
+            ast::Expr::Value(value) => {
+                let (data_type, numeric_precision) = match value {
+                    ast::Value::Str(_) => (Type::Str(None), 0),
+                    ast::Value::Date(_) => (Type::Date(None), 0),
+                    ast::Value::Int(_) | ast::Value::Null | ast::Value::Param(_) |
+                    ast::Value::NextVal(_) | ast::Value::CurrVal(_) => (Type::Int(None), 0),
+                    // decimal literals are exact, only the exponent form is a float
+                    ast::Value::Float(s) if s.contains(|c| c == 'e' || c == 'E') => (Type::Float(None), 0),
+                    ast::Value::Float(s) => (Type::Numeric(None), s.split('.').nth(1).map_or(0, |d| d.len()) as u8 + 1),
+                };
+                ColumnType {
+                    data_type: data_type,
+                    numeric_precision: numeric_precision,
+                    can_be_null: true,
+                    ..Default::default()
+                }
+            },
+            ast::Expr::Column(col) => match ty.iter().find(|ct| ct.match_(col)) {
+                Some(ct) => ColumnType {
+                    data_type: ct.data_type.without_default(),
+                    numeric_precision: ct.numeric_precision,
+                    can_be_null: true,
+                    ..Default::default()
+                },
+                None => Default::default(),
+            },
+            ast::Expr::Binary { op: _, left, right } => Self::unify(&Self::from_expr(left, ty), &Self::from_expr(right, ty)),
+            ast::Expr::Neg(expr) => Self::from_expr(expr, ty),
+            ast::Expr::Subquery(_) => Default::default(),
+            ast::Expr::Func { func, args } => {
+                let cts: Vec<ColumnType> = args.iter().map(|arg| Self::from_expr(arg, ty)).collect();
+                let arg_types = args.iter().zip(cts.iter()).map(|(arg, ct)| function::known_type(arg, &ct.data_type)).collect();
+                Self::from_args(function::result_type(func, &arg_types).unwrap_or(Type::Int(None)), &cts)
+            },
+            ast::Expr::Case { whens, else_ } => {
+                let results: Vec<&ast::Expr> = whens.iter().map(|(_, expr)| expr).chain(else_.as_deref()).collect();
+                let cts: Vec<ColumnType> = results.iter().map(|expr| Self::from_expr(expr, ty)).collect();
+                let types = results.iter().zip(cts.iter()).map(|(expr, ct)| function::known_type(expr, &ct.data_type)).collect();
+                Self::from_args(function::common_type(&types).unwrap_or(Type::Int(None)), &cts)
+            },
+            ast::Expr::Cast { expr: _, ty } => Self::from_type(ty),
+        };
+        ct.name = expr.name();
+        ct
+    }
+}
+
+// a CHECK constraint as the table keeps it, the condition is parsed back from its text like a view
+#[derive(Clone, Debug)]
+pub struct CheckConstraint {
+    pub name: String,
+    pub definition: String,
+}
+
+impl CheckConstraint {
+    // the column and table checks in the order they are written
+    pub fn from_fields(field_list: &Vec<ast::Field>, tb_name: &String) -> Vec<Self> {
+        let mut checks: Vec<Self> = Vec::new();
+        for field in field_list {
+            let (ck_name, check) = match field {
+                ast::Field::ColumnField { check: Some(check), .. } => (None, check),
+                ast::Field::CheckField { ck_name, check } => (ck_name.as_ref(), check),
+                _ => continue,
+            };
+            checks.push(Self {
+                name: ck_name.cloned().unwrap_or(String::new()),
+                definition: check.definition.clone(),
+            });
+        }
+        let named: Vec<String> = checks.iter().map(|check| check.name.clone()).collect();
+        for check in checks.iter_mut().filter(|check| check.name.is_empty()) {
+            check.name = Self::fresh_name(tb_name, &named);
+        }
+        checks
+    }
+
+    // an unnamed check is called `tb_chk_n` with the first n not taken
+    pub fn fresh_name(tb_name: &String, taken: &Vec<String>) -> String {
+        (1..).map(|n| format!("{}_chk_{}", tb_name, n)).find(|name| !taken.contains(name)).unwrap()
+    }
+
+    pub fn clause(&self) -> Vec<ast::WhereClause> {
+        sql::parse_condition(&self.definition).unwrap()
+    }
+
+    // a record breaks the condition only when it is false, an unknown one lets it through
+    pub fn holds(clause: &Vec<ast::WhereClause>, record: &Record, ty: &Vec<ColumnType>) -> bool {
+        clause.iter().all(|clause| record.test(clause, ty, &|_| None) != Some(false))
+    }
 }
 
 pub struct ColumnTypeVec {
@@ -236,16 +644,22 @@ pub struct ColumnTypeVec {
 }
 
 impl ColumnTypeVec {
-    pub fn from_fields<'a, 'b>(field_list: &'a Vec<ast::Field>, tb_name: &'b String) -> (Self, Vec<u32>, Vec<(&'a String, Vec<u32>)>) {
+    pub fn from_fields<'a, 'b>(field_list: &'a Vec<ast::Field>, tb_name: &'b String) -> (Self, Vec<u32>, Vec<(&'a String, Vec<u32>, ast::ReferentialAction, ast::ReferentialAction)>, Vec<Vec<u32>>) {
         let mut primary_key: &Vec<String> = &Vec::new();
         let mut foreign_list = Vec::new();
+        let mut unique_list: Vec<Vec<&String>> = Vec::new();
 
         let mut cols: Vec<ColumnType> = Vec::new();
         let mut map: HashMap<String, usize> = HashMap::new();
 
-        for index in 0..field_list.len() {
-            match &field_list[index] {
-                ast::Field::ColumnField {col_name, ty, not_null, default_value} => {
+        for field in field_list {
+            match field {
+                ast::Field::ColumnField {col_name, ty, not_null, auto_increment, default_value, unique, check: _} => {
+                    // the position among the columns, table constraints in between do not count
+                    let index = cols.len();
+                    if *unique {
+                        unique_list.push(vec![col_name]);
+                    }
                     map.insert(col_name.clone(), index);
                     cols.push(ColumnType {
                         tb_name: tb_name.clone(),
@@ -262,6 +676,7 @@ impl ColumnTypeVec {
                         is_primary: false,
                         is_foreign: false,
                         default_null: default_value.is_some() && default_value.as_ref().unwrap().is_null(),
+                        auto_increment: *auto_increment,
                         foreign_table_name: String::new(),
                         foreign_table_column: String::new(),
                     });
@@ -269,9 +684,13 @@ impl ColumnTypeVec {
                 ast::Field::PrimaryKeyField {column_list} => {
                     primary_key = column_list;
                 },
-                ast::Field::ForeignKeyField { column_list, foreign_tb_name, foreign_column_list } => {
-                    foreign_list.push((column_list, foreign_tb_name, foreign_column_list))
-                }
+                ast::Field::UniqueField {column_list} => {
+                    unique_list.push(column_list.iter().collect());
+                },
+                ast::Field::ForeignKeyField { column_list, foreign_tb_name, foreign_column_list, on_delete, on_update } => {
+                    foreign_list.push((column_list, foreign_tb_name, foreign_column_list, *on_delete, *on_update))
+                },
+                ast::Field::CheckField { .. } => {},
             }
         }
         
@@ -286,7 +705,7 @@ impl ColumnTypeVec {
 
         let mut foreign_col_index  = Vec::new();
 
-        for (cs, ft_name, fcs) in foreign_list {
+        for (cs, ft_name, fcs, on_delete, on_update) in foreign_list {
             for (c_name, fc_name) in cs.iter().zip(fcs.iter()) {
                 let index = map.get(c_name).unwrap();
                 cols[*index].is_foreign = true;
@@ -294,7 +713,7 @@ impl ColumnTypeVec {
                 cols[*index].foreign_table_column = fc_name.clone();
             }
 
-            foreign_col_index.push((ft_name, cs.iter().map(|c_name| *map.get(c_name).unwrap() as u32).collect()));
+            foreign_col_index.push((ft_name, cs.iter().map(|c_name| *map.get(c_name).unwrap() as u32).collect(), on_delete, on_update));
         }
 
         // for fk in &foreign_key {
@@ -304,7 +723,24 @@ impl ColumnTypeVec {
         //     cols[*index].foreign_table_column = fk.2.clone();
         // }
 
-        (Self { cols: cols, }, primary_cols, foreign_col_index)
+        let unique_col_index = unique_list.iter().map(|cs| cs.iter().map(|c_name| *map.get(*c_name).unwrap() as u32).collect()).collect();
+
+        (Self { cols: cols, }, primary_cols, foreign_col_index, unique_col_index)
+    }
+
+    // the columns of a table made from the output of a select, without keys, defaults or NOT NULL
+    pub fn from_record_types(ty: &Vec<ColumnType>, tb_name: &String) -> Self {
+        Self {
+            cols: ty.iter().enumerate().map(|(index, ct)| ColumnType {
+                tb_name: tb_name.clone(),
+                name: ct.name.clone(),
+                index: index as u32,
+                data_type: ct.data_type.without_default(),
+                numeric_precision: ct.numeric_precision,
+                can_be_null: true,
+                ..Default::default()
+            }).collect(),
+        }
     }
 
     pub fn get_primary_index(&self) -> Vec<u32> {
@@ -369,8 +805,12 @@ impl Record {
     }
 
     pub fn from_value_lists(value_lists: &Vec<ast::Value>, cts: &Vec<ColumnType>) -> Self {
+        Self::from_data(value_lists.iter().zip(cts.iter()).map(|(value, ct)| Data::from_value(value, ct)).collect(), cts)
+    }
+
+    pub fn from_data(data: Vec<Option<Data>>, cts: &Vec<ColumnType>) -> Self {
         let mut cols = Vec::new();
-        for i in 0..value_lists.len() {
+        for (i, data) in data.into_iter().enumerate() {
             cols.push(ColumnData {
                 index: i as u32,
                 default: false,
@@ -381,7 +821,7 @@ impl Record {
                     &Type::Date(_) => 3,
                     &Type::Numeric(_) => 4,
                 },
-                data: Data::from_value(&value_lists[i], &cts[i])
+                data: data,
             });
         }
         Self {
@@ -408,14 +848,125 @@ impl Record {
         (None, None)
     }
 
+    pub fn eval(&self, expr: &ast::Expr, ty: &Vec<ColumnType>) -> Option<Data> {
+        match expr {
+            ast::Expr::Value(value) => Data::from_value(value, &ColumnType::from_expr(expr, ty)),
+            ast::Expr::Column(col) => self.get_match_data(col, ty).0,
+            ast::Expr::Binary { op, left, right } => {
+                let ct = ColumnType::from_expr(expr, ty);
+                let l = self.eval(left, ty)?.cast(&ColumnType::from_expr(left, ty), &ct)?;
+                let r = self.eval(right, ty)?.cast(&ColumnType::from_expr(right, ty), &ct)?;
+                Data::arith(op, l, r, &ct)
+            },
+            ast::Expr::Neg(expr) => match self.eval(expr, ty)? {
+                Data::Int(d) => d.checked_neg().map(Data::Int),
+                Data::Float(d) => Some(Data::Float(-d)),
+                Data::Numeric(d) => d.checked_neg().map(Data::Numeric),
+                _ => None,
+            },
+            // subqueries are replaced by their value before the expression is evaluated
+            ast::Expr::Subquery(_) => None,
+            ast::Expr::Func { func, args } => {
+                let args = args.iter().map(|arg| (self.eval(arg, ty), ColumnType::from_expr(arg, ty))).collect();
+                function::call(func, args, &ColumnType::from_expr(expr, ty))
+            },
+            // the conditions hold no subqueries
+            ast::Expr::Case { whens, else_ } => {
+                let result = whens.iter().find(|(cond, _)| self.test(cond, ty, &|_| None) == Some(true)).map(|(_, result)| result).or(else_.as_deref())?;
+                self.eval(result, ty)?.cast(&ColumnType::from_expr(result, ty), &ColumnType::from_expr(expr, ty))
+            },
+            ast::Expr::Cast { expr: arg, ty: cast_ty } => match (self.eval(arg, ty)?.convert(&ColumnType::from_expr(arg, ty), &ColumnType::from_expr(expr, ty))?, cast_ty) {
+                // a string is cut to the declared length
+                (Data::Str(d), ast::Type::Varchar(n)) => Some(Data::Str(d.chars().take(*n as usize).collect())),
+                (d, _) => Some(d),
+            },
+        }
+    }
+
+    // three-valued logic, None stands for unknown, `subquery` evaluates the clauses that hold a subquery
+    pub fn test<F>(&self, clause: &ast::WhereClause, ty: &Vec<ColumnType>, subquery: &F) -> Option<bool>
+        where F: Fn(&ast::WhereClause) -> Option<bool> {
+        match clause {
+            ast::WhereClause::And(clauses) => {
+                let res: Vec<Option<bool>> = clauses.iter().map(|clause| self.test(clause, ty, subquery)).collect();
+                if res.contains(&Some(false)) {Some(false)} else if res.contains(&None) {None} else {Some(true)}
+            },
+            ast::WhereClause::Or(clauses) => {
+                let res: Vec<Option<bool>> = clauses.iter().map(|clause| self.test(clause, ty, subquery)).collect();
+                if res.contains(&Some(true)) {Some(true)} else if res.contains(&None) {None} else {Some(false)}
+            },
+            ast::WhereClause::Not(clause) => {
+                self.test(clause, ty, subquery).map(|res| !res)
+            },
+            clause if clause.has_subquery() => subquery(clause),
+            ast::WhereClause::IsAssert{col, null} => {
+                Some(self.get_match_data(col, ty).0.is_none() == *null)
+            },
+            ast::WhereClause::Comparison{col, op, expr} => {
+                let (l_data, ct) = self.get_match_data(col, ty);
+                let l_data = l_data?;
+                let ct = ct.unwrap();
+                match expr {
+                    ast::Expr::Value(value) => compare_value(op, l_data, &ct, value),
+                    expr => compare_typed(op, l_data, &ct, self.eval(expr, ty)?, &ColumnType::from_expr(expr, ty)),
+                }
+            },
+            ast::WhereClause::Like{col, pattern, escape} => {
+                match self.get_match_data(col, ty).0 {
+                    Some(Data::Str(s)) => Some(string::like(&s, pattern, escape.as_ref().and_then(|escape| escape.chars().next()))),
+                    _ => None,
+                }
+            },
+            ast::WhereClause::In{col, values} => {
+                let (data, ct) = self.get_match_data(col, ty);
+                let data = data?;
+                let ct = ct.unwrap();
+                let mut has_null = false;
+                for value in values {
+                    match compare_value(&ast::Op::Equal, data.clone(), &ct, value) {
+                        Some(true) => return Some(true),
+                        Some(false) => {},
+                        None => has_null = true,
+                    }
+                }
+                if has_null {None} else {Some(false)}
+            },
+            ast::WhereClause::Between{col, low, high} => {
+                let (data, ct) = self.get_match_data(col, ty);
+                let data = data?;
+                let ct = ct.unwrap();
+                let res = vec![
+                    compare_value(&ast::Op::GreaterEqual, data.clone(), &ct, low),
+                    compare_value(&ast::Op::LessEqual, data, &ct, high),
+                ];
+                if res.contains(&Some(false)) {Some(false)} else if res.contains(&None) {None} else {Some(true)}
+            },
+            ast::WhereClause::ExprComparison{left, op, right} => {
+                match (self.eval(left, ty), self.eval(right, ty)) {
+                    (Some(l_data), Some(r_data)) => compare_typed(op, l_data, &ColumnType::from_expr(left, ty), r_data, &ColumnType::from_expr(right, ty)),
+                    _ => None,
+                }
+            },
+            ast::WhereClause::InSubquery{..} | ast::WhereClause::Exists(_) => unreachable!(),
+        }
+    }
+
     pub fn set_(&mut self, value_list: &Vec<ast::SetClause>, ty: &Vec<ColumnType>) {
+        // every expression sees the record as it was before the update
+        let mut new_data = Vec::new();
         for value in value_list {
             for i in 0..ty.len() {
                 if ty[i].name == value.col_name {
-                    self.cols[i].data = Data::from_value(&value.value, &ty[i]);
+                    new_data.push((i, match &value.expr {
+                        ast::Expr::Value(v) => Data::from_value(v, &ty[i]),
+                        expr => self.eval(expr, ty).and_then(|data| data.cast(&ColumnType::from_expr(expr, ty), &ty[i])),
+                    }));
                 }
             }
         }
+        for (i, data) in new_data {
+            self.cols[i].data = data;
+        }
     }
 
     /*
@@ -109,7 +109,15 @@ impl TableHandler {
     pub fn insert_record_data_column(&self, ptr: &StrPointer, ct: &ColumnType) {
         let (_, mut record_in_file) = self.get_record(ptr);
         let size_of_data = size_of::<ColumnDataInFile>();
-        let cd = ColumnDataInFile::null_data(ct);
+        // the rows already there take the default of the new column
+        let cd = match Data::from_value(&ct.default_value(), ct) {
+            Some(data) => {
+                let mut cd = Record::from_data(vec![Some(data)], &vec![ct.clone()]).cols.remove(0);
+                cd.index = ct.index;
+                ColumnDataInFile::from(self, &cd)
+            },
+            None => ColumnDataInFile::null_data(ct),
+        };
         record_in_file.record.push_str(cd.as_str());
 
         self.update_record_in_file(ptr, &record_in_file);
@@ -253,6 +261,48 @@ impl TableHandler {
         self.fh.update::<ColumnTypeInFile, u32>(&StrPointer::new(ptr), &ColumnTypeInFile::from(self, ct));
     }
 
+    // the counter of the auto-increment column
+    pub fn get_auto_increment(&self) -> i64 {
+        self.fh.get_auto_increment() as i64
+    }
+
+    pub fn set_auto_increment(&self, value: i64) {
+        self.fh.set_auto_increment(value as u64);
+    }
+
+    // for CheckConstraint, a table made before checks existed has no list yet
+    pub fn get_checks(&self) -> Vec<CheckConstraint> {
+        match self.fh.get_checks_ptr() {
+            0 => Vec::new(),
+            ptr => self.__get_ptrs(&StrPointer::new(ptr)).iter().map(|&p| self.fh.get::<CheckInFile, u32>(&StrPointer::new(p)).to_check(self)).collect(),
+        }
+    }
+
+    pub fn insert_check(&self, check: &CheckConstraint) {
+        let cp = self.fh.insert::<CheckInFile, u32>(&CheckInFile::from(self, check)).to_u64();
+        match self.fh.get_checks_ptr() {
+            0 => {
+                let ptr = self.insert_string(&unsafe{convert::vec_u64_to_string(&vec![cp])});
+                self.fh.set_checks_ptr(ptr.to_u64());
+            },
+            ptr => {
+                let ptrs_ptr = StrPointer::new(ptr);
+                let mut ptrs = self.__get_ptrs(&ptrs_ptr);
+                ptrs.push(cp);
+                self.update_string(&ptrs_ptr, &unsafe{convert::vec_u64_to_string(&ptrs)});
+            },
+        }
+    }
+
+    pub fn delete_check_from_index(&self, index: usize) {
+        let ptrs_ptr = StrPointer::new(self.fh.get_checks_ptr());
+        let mut ptrs = self.__get_ptrs(&ptrs_ptr);
+        assert!(index < ptrs.len());
+        self.delete(&StrPointer::new(ptrs[index]));
+        ptrs.remove(index);
+        self.update_string(&ptrs_ptr, &unsafe{convert::vec_u64_to_string(&ptrs)});
+    }
+
     // for BTree
     // TODO: use u8 instead of string, use iterator instead of copy
     pub fn __insert_btree(&self, btree: &BTree) -> StrPointer {
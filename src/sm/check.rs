@@ -1,6 +1,9 @@
 use crate::parser::ast::*;
 use crate::rm::record::*;
+use crate::rm::record::Type;
+use crate::rm::function;
 use crate::index::btree::*;
+use crate::rm::table_handler::TableHandler;
 
 use std::collections::HashMap;
 use std::collections::HashSet;
@@ -9,32 +12,68 @@ use crate::defer;
 
 use super::system_manager::SystemManager;
 use super::query_tree::QueryTree;
+use super::referential::Propagation;
 
 
 pub fn check_create_table(field_list: &Vec<Field>, sm: &SystemManager) -> bool {
     let mut name_field = HashMap::new();
     let mut primary_key: Vec<&Vec<String>> = Vec::new();
-    let mut foreign_list: Vec<(&Vec<String>, &String, &Vec<String>)> = Vec::new();
+    let mut foreign_list: Vec<(&Vec<String>, &String, &Vec<String>, [ReferentialAction; 2])> = Vec::new();
+    let mut unique_list: Vec<&Vec<String>> = Vec::new();
+    let mut check_list: Vec<&Check> = Vec::new();
+    let mut check_names: Vec<&String> = Vec::new();
+    let mut auto_increments = 0;
 
     for field in field_list {
         match field {
-            Field::ColumnField {col_name, ty, not_null, default_value} => {
+            Field::ColumnField {col_name, ty, not_null, auto_increment, default_value, unique: _, check} => {
                 if name_field.contains_key(col_name) {
                     return false;
                 }
+                // a table counts up a single integer column, which takes no default
+                if *auto_increment {
+                    use crate::parser::ast;
+                    let is_int = match ty {
+                        ast::Type::Int(_) => true,
+                        _ => false,
+                    };
+                    if !is_int || default_value.is_some() || auto_increments > 0 {
+                        return false;
+                    }
+                    auto_increments += 1;
+                }
                 if let Some(ref v) = default_value {
                     if !v.of_type(ty) {
                         return false;
                     }
                 }
                 name_field.insert(col_name, (col_name, ty, not_null, default_value));
+                check_list.extend(check);
             },
             Field::PrimaryKeyField {column_list} => {
                 primary_key.push(column_list);
             },
-            Field::ForeignKeyField { column_list, foreign_tb_name, foreign_column_list } => {
-                foreign_list.push((column_list, foreign_tb_name, foreign_column_list))
-            }
+            Field::ForeignKeyField { column_list, foreign_tb_name, foreign_column_list, on_delete, on_update } => {
+                foreign_list.push((column_list, foreign_tb_name, foreign_column_list, [*on_delete, *on_update]))
+            },
+            Field::UniqueField { column_list } => {
+                unique_list.push(column_list);
+            },
+            Field::CheckField { ck_name, check } => {
+                check_names.extend(ck_name);
+                check_list.push(check);
+            },
+        }
+    }
+
+    let cols: HashMap<String, Type> = name_field.iter().map(|(name, (_, ty, _, default_value))| ((*name).clone(), Type::from_type(ty, default_value))).collect();
+    if !check_no_repeat(&check_names.into_iter().cloned().collect()) || !check_list.into_iter().all(|check| check_check(check, &cols)) {
+        return false;
+    }
+
+    for column_list in unique_list {
+        if !check_no_repeat(column_list) || !column_list.iter().all(|col_name| name_field.contains_key(col_name)) {
+            return false;
         }
     }
 
@@ -55,7 +94,7 @@ pub fn check_create_table(field_list: &Vec<Field>, sm: &SystemManager) -> bool {
         }
     }
 
-    for (cols, ft_name, fcols) in foreign_list {
+    for (cols, ft_name, fcols, actions) in foreign_list {
         if cols.len() != fcols.len() {
             return false;
         }
@@ -69,6 +108,16 @@ pub fn check_create_table(field_list: &Vec<Field>, sm: &SystemManager) -> bool {
             return false;
         }
 
+        let fits = cols.iter().all(|col_name| {
+            let (_, _, not_null, default_value) = name_field.get(col_name).unwrap();
+            let nullable = !**not_null && !primary_key.iter().any(|pk| pk.contains(col_name));
+            let has_default = default_value.as_ref().map_or(false, |v| !v.is_null());
+            actions.iter().all(|action| check_referential_action(action, nullable, has_default))
+        });
+        if !fits {
+            return false;
+        }
+
         let th = sm.open_table(ft_name, false);
         if th.is_none() {
             return false;
@@ -138,6 +187,35 @@ pub fn check_create_table(field_list: &Vec<Field>, sm: &SystemManager) -> bool {
     true
 }
 
+// SET NULL needs a column that takes NULL, SET DEFAULT one that takes its default
+fn check_referential_action(action: &ReferentialAction, nullable: bool, has_default: bool) -> bool {
+    match action {
+        ReferentialAction::SetNull => nullable,
+        ReferentialAction::SetDefault => nullable || has_default,
+        _ => true,
+    }
+}
+
+// a CHECK uses the columns of its own table by their bare names, and holds neither subqueries nor placeholders
+fn check_check(check: &Check, cols: &HashMap<String, Type>) -> bool {
+    let valid_col = |col: &Column| col.tb_name.is_none() && cols.contains_key(&col.col_name);
+    let get_type = |col: &Column| cols.get(&col.col_name).unwrap().clone();
+    let mut clause = check.clause.clone();
+    let no_param = clause.iter_mut().flat_map(|c| c.values_mut()).all(|v| match v {
+        Value::Param(_) => false,
+        _ => true,
+    });
+    no_param && check.clause.iter().all(|c| check_where_clause(c, &valid_col, &get_type, &no_subquery))
+}
+
+// every record meets every CHECK of the table
+pub fn check_constraints(th: &TableHandler, records: &Vec<Record>, ty: &Vec<ColumnType>) -> bool {
+    th.get_checks().iter().all(|check| {
+        let clause = check.clause();
+        records.iter().all(|record| CheckConstraint::holds(&clause, record, ty))
+    })
+}
+
 pub fn check_drop_table(tb_name: &String, sm: &SystemManager) -> bool {
     let tables = sm.get_tables();
     for table in &tables {
@@ -196,6 +274,9 @@ pub fn check_insert_value(tb_name: &String, value_lists: &Vec<Vec<Value>>, sm: &
             }
         }
     }
+    if sm.prepare {
+        return true;
+    }
     let records: Vec<Record> = value_lists.iter().map(|v| Record::from_value_lists(v, &cts.cols)).collect();
 
     let mut inserted: Vec<(StrPointer, RawIndex)> = Vec::new();
@@ -222,6 +303,13 @@ pub fn check_insert_value(tb_name: &String, value_lists: &Vec<Vec<Value>>, sm: &
     if duplicate {
         return false;
     }
+    let unique_btrees = th.get_btrees().into_iter().filter(|t| t.is_unique()).collect();
+    if !check_unique(&th, &unique_btrees, &records, &HashSet::new()) {
+        return false;
+    }
+    if !check_constraints(&th, &records, &cts.cols) {
+        return false;
+    }
 
     let mut ft_name_fk_this_col: HashMap<&String, HashMap<&String, &ColumnType>> = HashMap::new();
 
@@ -242,9 +330,12 @@ pub fn check_insert_value(tb_name: &String, value_lists: &Vec<Vec<Value>>, sm: &
         assert!(fk_this_col.len() == primary_cols.len());
 
         // TODO: support part of primary key
-        // TODO: support null foreign key
         let ordered_index: Vec<u32> = primary_cols.iter().map(|p| fk_this_col.get(&p.name).unwrap().index).collect();
         for record in &records {
+            // a foreign key with a NULL references nothing, as SET NULL leaves it
+            if ordered_index.iter().any(|i| record.cols[*i as usize].data.is_none()) {
+                continue;
+            }
             let ri = RawIndex::from_record(record, &ordered_index);
             let res = pri_btree.search_record(&ri);
             if res.is_none() {
@@ -266,7 +357,23 @@ pub fn check_no_repeat(names: &Vec<String>) -> bool {
     true
 }
 
-pub fn check_select(tb_cols: &HashMap<&String, HashMap<String, ColumnType>>, selector: &Selector, where_clause: &Option<Vec<WhereClause>>) -> bool {
+// finds a column that is qualified or whose name is unique among the tables
+fn resolve_col<'a>(tb_cols: &'a HashMap<&String, HashMap<String, ColumnType>>, col: &Column) -> Option<&'a ColumnType> {
+    let mut cts = tb_cols.iter()
+        .filter(|(tb_name, _)| col.tb_name.as_ref().map_or(true, |tb| tb == **tb_name))
+        .filter_map(|(_, cols)| cols.get(&col.col_name));
+    match (cts.next(), cts.next()) {
+        (Some(ct), None) => Some(ct),
+        _ => None,
+    }
+}
+
+fn no_subquery(_: &TableStmt) -> Option<Vec<ColumnType>> {
+    None
+}
+
+// the where clause also sees the columns of `outer_cols`, the tables of the enclosing selects
+pub fn check_select(tb_cols: &HashMap<&String, HashMap<String, ColumnType>>, outer_cols: &HashMap<&String, HashMap<String, ColumnType>>, selector: &Selector, where_clause: &Option<Vec<WhereClause>>, group_clause: &Option<Vec<Column>>, having_clause: &Option<Vec<HavingClause>>, order_clause: &Option<Vec<OrderClause>>, sm: &SystemManager) -> bool {
     let mut col_tbs: HashMap<&String, HashSet<&String>> =  HashMap::new();
     for (tb_name, cols) in tb_cols {
         for (col_name, _) in cols {
@@ -301,50 +408,389 @@ pub fn check_select(tb_cols: &HashMap<&String, HashMap<String, ColumnType>>, sel
         tb_cols.get(tb_name).unwrap().get(&qualified_col.col_name).unwrap()
     };
 
+    let group_cols = group_clause.clone().unwrap_or(Vec::new());
+    for col in &group_cols {
+        if !valid_qualified_col(col) {
+            return false;
+        }
+    }
+    let aggregated = group_clause.is_some() || having_clause.is_some() || selector.has_aggregate();
+
+    let in_group = |col: &Column| -> bool {
+        let ct = get_col(col);
+        group_cols.iter().any(|g| {
+            let g = get_col(g);
+            g.tb_name == ct.tb_name && g.name == ct.name
+        })
+    };
+
+    let valid_item = |item: &SelectItem| -> bool {
+        match item {
+            SelectItem::Column(col) => valid_qualified_col(col) && (!aggregated || in_group(col)),
+            SelectItem::Expr(expr) => {
+                check_expr(expr, &valid_qualified_col, &|col: &Column| get_col(col).data_type.clone(), &no_subquery).is_some()
+                    && (!aggregated || expr.columns().into_iter().all(|col| in_group(col)))
+            },
+            SelectItem::Aggregate(aggr) => match &aggr.col {
+                Some(col) => valid_qualified_col(col) && match (&aggr.func, &get_col(col).data_type) {
+                    (AggrFunc::Sum, Type::Str(_)) | (AggrFunc::Sum, Type::Date(_)) |
+                    (AggrFunc::Avg, Type::Str(_)) | (AggrFunc::Avg, Type::Date(_)) => false,
+                    _ => true,
+                },
+                None => true,
+            },
+        }
+    };
+
+    let item_type = |item: &SelectItem| -> Type {
+        match item {
+            SelectItem::Column(col) => get_col(col).data_type.clone(),
+            SelectItem::Aggregate(aggr) => ColumnType::from_aggregate(aggr, aggr.col.as_ref().map(|col| get_col(col))).data_type,
+            SelectItem::Expr(expr) => check_expr(expr, &valid_qualified_col, &|col: &Column| get_col(col).data_type.clone(), &no_subquery).unwrap(),
+        }
+    };
+
     match selector {
-        Selector::Columns(qualified_cols) => {
-            for qualified_col in qualified_cols {
-                if !valid_qualified_col(qualified_col) {
+        Selector::Columns(items, _) => {
+            for item in items {
+                if !valid_item(item) {
                     return false;
                 }
             }
         },
-        _ => {}, 
+        Selector::All => {
+            if aggregated {
+                return false;
+            }
+        }, 
     }
 
-    if let Some(where_clause) = where_clause {
-        for sub_where in where_clause {
-            match sub_where {
-                WhereClause::IsAssert {col, null: _} => {
-                    if !valid_qualified_col(col) {
+    if let Some(having_clause) = having_clause {
+        for having in having_clause {
+            if !valid_item(&having.item) {
+                return false;
+            }
+            let ty = item_type(&having.item);
+            match &having.expr {
+                Expr::Value(v) => {
+                    if !comparable_value(&ty, v) {
                         return false;
                     }
                 },
-                WhereClause::Comparison {col, op: _, expr} => {
-                    if !valid_qualified_col(col) {
+                Expr::Column(c) => {
+                    if !valid_qualified_col(c) || !in_group(c) || !ty.comparable(&get_col(c).data_type) {
                         return false;
                     }
-                    let ct = get_col(col);
-                    match expr {
-                        Expr::Value(v) => {
-                            if !ct.data_type.valid_value(v) {
-                                return false;
-                            }
-                        },
-                        Expr::Column(c) => {
-                            let another_ct = get_col(c);
-                            if !ct.data_type.comparable(&another_ct.data_type) {
-                                return false;
-                            }
-                        }
+                },
+                expr => {
+                    let valid = match check_expr(expr, &valid_qualified_col, &|col: &Column| get_col(col).data_type.clone(), &no_subquery) {
+                        Some(t) => ty.unify(&t).is_some() && expr.columns().into_iter().all(|col| in_group(col)),
+                        None => false,
+                    };
+                    if !valid {
+                        return false;
                     }
                 },
             }
         }
     }
+
+    let selected = |col: &Column| -> bool {
+        let ct = get_col(col);
+        match selector {
+            Selector::Columns(items, _) => items.iter().any(|item| match item {
+                SelectItem::Column(c) => {
+                    let c = get_col(c);
+                    c.tb_name == ct.tb_name && c.name == ct.name
+                },
+                _ => false,
+            }),
+            Selector::All => true,
+        }
+    };
+
+    if let Some(order_clause) = order_clause {
+        for order in order_clause {
+            if !valid_qualified_col(&order.col) || (aggregated && !in_group(&order.col)) {
+                return false;
+            }
+            // grouped rows are sorted once projected, so the column has to be selected
+            if aggregated && !selected(&order.col) {
+                return false;
+            }
+        }
+    }
+
+    // a name known to these tables never refers to the enclosing ones
+    let is_inner = |col: &Column| -> bool {
+        match &col.tb_name {
+            Some(tb) => tb_cols.contains_key(tb),
+            None => col_tbs.contains_key(&col.col_name),
+        }
+    };
+    let valid_where_col = |col: &Column| -> bool {
+        if is_inner(col) {valid_qualified_col(col)} else {resolve_col(outer_cols, col).is_some()}
+    };
+    let get_where_type = |col: &Column| -> Type {
+        if is_inner(col) {get_col(col).data_type.clone()} else {resolve_col(outer_cols, col).unwrap().data_type.clone()}
+    };
+    let mut scope = outer_cols.clone();
+    scope.extend(tb_cols.iter().map(|(tb_name, cols)| (*tb_name, cols.clone())));
+    let check_nested = |query: &TableStmt| check_subquery(query, &scope, sm);
+
+    if let Some(where_clause) = where_clause {
+        for sub_where in where_clause {
+            if !check_where_clause(sub_where, &valid_where_col, &get_where_type, &check_nested) {
+                return false;
+            }
+        }
+    }
+    true
+}
+
+// the columns of every table by the name the query uses for it, which may be an alias,
+// None if a view no longer fits the tables it reads
+pub fn get_table_cols<'a>(table_list: &'a Vec<TableRef>, sm: &SystemManager) -> Option<HashMap<&'a String, HashMap<String, ColumnType>>> {
+    table_list.iter().map(|table| {
+        let mut cols: HashMap<String, ColumnType> = match sm.get_view(&table.tb_name) {
+            Some(query) => check_subquery(&query, &HashMap::new(), sm)?.into_iter().enumerate().map(|(i, ct)| {
+                (ct.name.clone(), ColumnType { index: i as u32, ..ct })
+            }).collect(),
+            None => {
+                let th = sm.open_table(&table.tb_name, false).unwrap();
+                defer!(th.close());
+                th.get_column_types_as_hashmap()
+            },
+        };
+        for ct in cols.values_mut() {
+            ct.tb_name = table.name().clone();
+        }
+        Some((table.name(), cols))
+    }).collect()
+}
+
+// a join condition may only use the tables of its from item up to the joined table,
+// USING and NATURAL need every common column to be in exactly one of the tables before
+pub fn check_join(table_list: &Vec<Name>, tb_cols: &HashMap<&String, HashMap<String, ColumnType>>, join_list: &Vec<Join>) -> bool {
+    let mut visible: HashMap<&String, &HashMap<String, ColumnType>> = HashMap::new();
+    for tb_name in table_list {
+        let join = join_list.iter().find(|join| &join.tb_name == tb_name);
+        if join.is_none() {
+            visible.clear();
+        }
+        let right = tb_cols.get(tb_name).unwrap();
+        let common = |name: &String| -> Option<bool> {
+            let left: Vec<&ColumnType> = visible.values().filter_map(|cols| cols.get(name)).collect();
+            match (left.len(), right.get(name)) {
+                (0, _) => None,
+                (1, Some(ct)) => Some(left[0].data_type.comparable(&ct.data_type)),
+                (_, _) => Some(false),
+            }
+        };
+        let valid = match join.and_then(|join| join.cond.as_ref()) {
+            Some(JoinCondition::On(clauses)) => {
+                let valid_col = |col: &Column| -> bool {
+                    resolve_col(tb_cols, col).map_or(false, |ct| &ct.tb_name == tb_name || visible.contains_key(&ct.tb_name))
+                };
+                let get_type = |col: &Column| -> Type {
+                    resolve_col(tb_cols, col).unwrap().data_type.clone()
+                };
+                clauses.iter().all(|clause| check_where_clause(clause, &valid_col, &get_type, &no_subquery))
+            },
+            Some(JoinCondition::Using(names)) => check_no_repeat(names) && names.iter().all(|name| right.contains_key(name) && common(name) == Some(true)),
+            Some(JoinCondition::Natural) => right.keys().all(|name| common(name) != Some(false)),
+            None => true,
+        };
+        if !valid {
+            return false;
+        }
+        visible.insert(tb_name, right);
+    }
     true
 }
 
+// checks a nested select and gives the types of its output columns
+pub fn check_subquery(query: &TableStmt, outer_cols: &HashMap<&String, HashMap<String, ColumnType>>, sm: &SystemManager) -> Option<Vec<ColumnType>> {
+    match query {
+        TableStmt::Select { distinct: _, table_list, join_list, selector, where_clause, group_clause, having_clause, order_clause, limit_clause: _ } => {
+            let mut tables = sm.get_tables();
+            tables.extend(sm.get_views());
+            let names: Vec<Name> = table_list.iter().map(|table| table.name().clone()).collect();
+            if !check_no_repeat(&names) || !table_list.iter().all(|table| tables.contains(&table.tb_name)) {
+                return None;
+            }
+            let tb_cols = get_table_cols(table_list, sm)?;
+            if !check_join(&names, &tb_cols, join_list) || !check_select(&tb_cols, outer_cols, selector, where_clause, group_clause, having_clause, order_clause, sm) {
+                return None;
+            }
+            let mut cts: Vec<ColumnType> = names.iter().flat_map(|name| {
+                let mut cts: Vec<ColumnType> = tb_cols[name].values().cloned().collect();
+                cts.sort_by_key(|ct| ct.index);
+                cts
+            }).collect();
+            if let Selector::Columns(items, aliases) = selector {
+                cts = items.iter().map(|item| match item {
+                    SelectItem::Column(col) => resolve_col(&tb_cols, col).unwrap().clone(),
+                    SelectItem::Aggregate(aggr) => ColumnType::from_aggregate(aggr, aggr.col.as_ref().map(|col| resolve_col(&tb_cols, col).unwrap())),
+                    SelectItem::Expr(expr) => ColumnType::from_expr(expr, &cts),
+                }).collect();
+                for (ct, alias) in cts.iter_mut().zip(aliases.iter()) {
+                    if let Some(alias) = alias {
+                        ct.name = alias.clone();
+                    }
+                }
+            }
+            Some(cts)
+        },
+        // both sides need the same number of columns with comparable types, ORDER BY uses the names of the left one
+        TableStmt::Compound { op: _, all: _, left, right, order_clause, limit_clause: _ } => {
+            let left = check_subquery(left, outer_cols, sm)?;
+            let right = check_subquery(right, outer_cols, sm)?;
+            let compatible = left.len() == right.len() && left.iter().zip(right.iter()).all(|(l, r)| {
+                l.data_type.same(&r.data_type) && l.numeric_precision == r.numeric_precision
+            });
+            let valid_order = order_clause.iter().flatten().all(|o| left.iter().filter(|ct| ct.match_(&o.col)).count() == 1);
+            if compatible && valid_order {Some(left)} else {None}
+        },
+        _ => None,
+    }
+}
+
+// a literal is compared with a column in the type both widen to, a string only where it reads as a date
+fn comparable_value(ty: &Type, value: &Value) -> bool {
+    match value {
+        Value::Int(_) | Value::Float(_) | Value::NextVal(_) | Value::CurrVal(_) => ty.is_numeric(),
+        Value::Date(_) => ty.comparable(&Type::Date(None)),
+        Value::Str(_) | Value::Null | Value::Param(_) => ty.valid_value(value),
+    }
+}
+
+pub fn check_where_clause<F, G, H>(sub_where: &WhereClause, valid_qualified_col: &F, get_type: &G, check_subquery: &H) -> bool
+    where F: Fn(&Column) -> bool, G: Fn(&Column) -> Type, H: Fn(&TableStmt) -> Option<Vec<ColumnType>> {
+    match sub_where {
+        WhereClause::IsAssert {col, null: _} => {
+            valid_qualified_col(col)
+        },
+        WhereClause::Comparison {col, op: _, expr} => {
+            if !valid_qualified_col(col) {
+                return false;
+            }
+            let ty = get_type(col);
+            match expr {
+                Expr::Value(v) => comparable_value(&ty, v),
+                Expr::Column(c) => valid_qualified_col(c) && ty.comparable(&get_type(c)),
+                expr => check_expr(expr, valid_qualified_col, get_type, check_subquery).and_then(|t| ty.unify(&t)).is_some(),
+            }
+        },
+        WhereClause::Like {col, pattern: _, escape} => {
+            let valid_escape = escape.as_ref().map_or(true, |escape| escape.chars().count() == 1);
+            valid_qualified_col(col) && valid_escape && match get_type(col) {
+                Type::Str(_) => true,
+                _ => false,
+            }
+        },
+        WhereClause::In {col, values} => {
+            valid_qualified_col(col) && {
+                let ty = get_type(col);
+                values.iter().all(|v| comparable_value(&ty, v))
+            }
+        },
+        WhereClause::InSubquery {col, query} => {
+            valid_qualified_col(col) && match check_subquery(query) {
+                Some(cts) => cts.len() == 1 && get_type(col).comparable(&cts[0].data_type),
+                None => false,
+            }
+        },
+        WhereClause::Exists(query) => {
+            check_subquery(query).is_some()
+        },
+        WhereClause::Between {col, low, high} => {
+            valid_qualified_col(col) && {
+                let ty = get_type(col);
+                comparable_value(&ty, low) && comparable_value(&ty, high)
+            }
+        },
+        // a placeholder compared with an expression takes its type
+        WhereClause::ExprComparison {left: Expr::Value(Value::Param(_)), op: _, right: expr} |
+        WhereClause::ExprComparison {left: expr, op: _, right: Expr::Value(Value::Param(_))} => {
+            check_expr(expr, valid_qualified_col, get_type, check_subquery).is_some()
+        },
+        WhereClause::ExprComparison {left, op: _, right} => {
+            match (check_expr(left, valid_qualified_col, get_type, check_subquery), check_expr(right, valid_qualified_col, get_type, check_subquery)) {
+                (Some(l), Some(r)) => l.unify(&r).is_some(),
+                _ => false,
+            }
+        },
+        WhereClause::And(clauses) | WhereClause::Or(clauses) => {
+            clauses.iter().all(|clause| check_where_clause(clause, valid_qualified_col, get_type, check_subquery))
+        },
+        WhereClause::Not(clause) => {
+            check_where_clause(clause, valid_qualified_col, get_type, check_subquery)
+        },
+    }
+}
+
+// gives the type of an expression, None if a column is unknown or arithmetic is applied to a non-numeric type
+pub fn check_expr<F, G, H>(expr: &Expr, valid_qualified_col: &F, get_type: &G, check_subquery: &H) -> Option<Type>
+    where F: Fn(&Column) -> bool, G: Fn(&Column) -> Type, H: Fn(&TableStmt) -> Option<Vec<ColumnType>> {
+    match expr {
+        Expr::Value(Value::Int(_)) | Expr::Value(Value::Null) | Expr::Value(Value::Param(_)) |
+        Expr::Value(Value::NextVal(_)) | Expr::Value(Value::CurrVal(_)) => Some(Type::Int(None)),
+        Expr::Value(Value::Float(_)) => Some(Type::Numeric(None)),
+        Expr::Value(Value::Str(_)) => Some(Type::Str(None)),
+        Expr::Value(Value::Date(_)) => Some(Type::Date(None)),
+        Expr::Column(col) => {
+            if valid_qualified_col(col) {Some(get_type(col).without_default())} else {None}
+        },
+        Expr::Binary {op: _, left, right} => {
+            let l = check_expr(left, valid_qualified_col, get_type, check_subquery)?;
+            let r = check_expr(right, valid_qualified_col, get_type, check_subquery)?;
+            if l.is_numeric() && r.is_numeric() {l.unify(&r)} else {None}
+        },
+        Expr::Neg(expr) => {
+            check_expr(expr, valid_qualified_col, get_type, check_subquery).filter(|t| t.is_numeric())
+        },
+        // a scalar subquery gives a single column, NULL when it has no rows
+        Expr::Subquery(query) => {
+            check_subquery(query).filter(|cts| cts.len() == 1).map(|cts| cts[0].data_type.without_default())
+        },
+        // the sequence is named by a literal
+        Expr::Func { func: Func::NextVal, args } | Expr::Func { func: Func::CurrVal, args } if !args.iter().all(|arg| match arg {
+            Expr::Value(Value::Str(_)) => true,
+            _ => false,
+        }) => None,
+        Expr::Func { func, args } => {
+            let mut arg_types = vec![];
+            for arg in args {
+                let ty = check_expr(arg, valid_qualified_col, get_type, check_subquery)?;
+                arg_types.push(function::known_type(arg, &ty));
+            }
+            function::result_type(func, &arg_types)
+        },
+        // the conditions are WHERE clauses without subqueries, the results meet in one type
+        Expr::Case { whens, else_ } => {
+            let mut types = vec![];
+            for (cond, result) in whens {
+                if !check_where_clause(cond, valid_qualified_col, get_type, &no_subquery) {
+                    return None;
+                }
+                types.push(function::known_type(result, &check_expr(result, valid_qualified_col, get_type, check_subquery)?));
+            }
+            if let Some(result) = else_ {
+                types.push(function::known_type(result, &check_expr(result, valid_qualified_col, get_type, check_subquery)?));
+            }
+            function::common_type(&types)
+        },
+        Expr::Cast { expr, ty } => {
+            let to = Type::from_type(ty, &None);
+            match function::known_type(expr, &check_expr(expr, valid_qualified_col, get_type, check_subquery)?) {
+                Some(from) if !from.castable(&to) => None,
+                _ => Some(to),
+            }
+        },
+    }
+}
+
 pub fn check_delete(tb_name: &String, map: &HashMap<String, ColumnType>, where_clause: &Option<Vec<WhereClause>>, sm: &SystemManager) -> bool {
     let valid_qualified_col = |qualified_col: &Column| -> bool {
         if let Some(ref tb) = qualified_col.tb_name {
@@ -358,77 +804,31 @@ pub fn check_delete(tb_name: &String, map: &HashMap<String, ColumnType>, where_c
         map.get(&qualified_col.col_name).unwrap()
     };
 
+    let mut scope = HashMap::new();
+    scope.insert(tb_name, map.clone());
     if let Some(where_clause) = where_clause {
         for sub_where in where_clause {
-            match sub_where {
-                WhereClause::IsAssert {col, null: _} => {
-                    if !valid_qualified_col(col) {
-                        return false;
-                    }
-                },
-                WhereClause::Comparison {col, op: _, expr} => {
-                    if !valid_qualified_col(col) {
-                        return false;
-                    }
-                    let ct = get_col(col);
-                    match expr {
-                        Expr::Value(v) => {
-                            if !ct.data_type.valid_value(v) {
-                                return false;
-                            }
-                        },
-                        Expr::Column(c) => {
-                            let another_ct = get_col(c);
-                            if !ct.data_type.comparable(&another_ct.data_type) {
-                                return false;
-                            }
-                        }
-                    }
-                },
+            if !check_where_clause(sub_where, &valid_qualified_col, &|col: &Column| get_col(col).data_type.clone(), &|query: &TableStmt| check_subquery(query, &scope, sm)) {
+                return false;
             }
         }
     }
 
-    // check if foreign
-    let foreign_tables = table_foreign_this_table(tb_name, sm);
-    if foreign_tables.len() > 0 {
-        let database = sm.current_database.as_ref().unwrap();
-        let mut tree = QueryTree::new(&sm.root_dir, database, sm.rm.clone());
-        tree.build(&vec![tb_name.clone()], &Selector::All, where_clause);
-        let record_list = tree.query();
-
-        let th = sm.open_table(tb_name, false).unwrap();
-        defer!(th.close());
-        let primary_cols = th.get_primary_column_index().unwrap();
-        let ris: Vec<RawIndex> = record_list.record.iter().map(
-            |record| {
-                RawIndex::from_record(record, &primary_cols)
-            }
-        ).collect();
-
-        for name in foreign_tables {
-            let fth = sm.open_table(&name, false).unwrap();
-            defer!(fth.close());
-            let foreign_btrees = fth.get_btrees().into_iter().filter_map(
-                |btree| {
-                    if btree.is_foreign() && btree.get_foreign_table_name() == tb_name {
-                        Some(btree)
-                    } else {
-                        None
-                    }
-                }
-            );
-            for fb in foreign_btrees {
-                for ri in &ris {
-                    if fb.search_record(ri).is_some() {
-                        return false;
-                    }
-                }
-            }
-        }
+    if sm.prepare {
+        return true;
     }
 
-    true
+    // the rows referencing them must let them go
+    let database = sm.current_database.as_ref().unwrap();
+    let mut tree = QueryTree::new(&sm.root_dir, database, sm.rm.clone());
+    tree.build(&vec![tb_name.clone()], &Selector::All, where_clause);
+    let record_list = tree.query();
+
+    let mut propagation = Propagation::new(sm);
+    for (ptr, record) in record_list.ptrs.into_iter().zip(record_list.record.into_iter()) {
+        propagation.delete(tb_name, ptr.to_u64(), record);
+    }
+    propagation.finish()
 }
 
 pub fn check_update(tb_name: &String, map: &HashMap<String, ColumnType>, set_clause: &Vec<SetClause>, where_clause: &Option<Vec<WhereClause>>, sm: &SystemManager) -> bool {
@@ -445,40 +845,27 @@ pub fn check_update(tb_name: &String, map: &HashMap<String, ColumnType>, set_cla
         map.get(col_name).unwrap()
     };
 
+    let mut scope = HashMap::new();
+    scope.insert(tb_name, map.clone());
     if let Some(where_clause) = where_clause {
         for sub_where in where_clause {
-            match sub_where {
-                WhereClause::IsAssert {col, null: _} => {
-                    if !valid_qualified_col(col) {
-                        return false;
-                    }
-                },
-                WhereClause::Comparison {col, op: _, expr} => {
-                    if !valid_qualified_col(col) {
-                        return false;
-                    }
-                    let ct = get_col(&col.col_name);
-                    match expr {
-                        Expr::Value(v) => {
-                            if !ct.data_type.valid_value(v) {
-                                return false;
-                            }
-                        },
-                        Expr::Column(c) => {
-                            let another_ct = get_col(&c.col_name);
-                            if !ct.data_type.comparable(&another_ct.data_type) {
-                                return false;
-                            }
-                        }
-                    }
-                },
+            if !check_where_clause(sub_where, &valid_qualified_col, &|col: &Column| get_col(&col.col_name).data_type.clone(), &|query: &TableStmt| check_subquery(query, &scope, sm)) {
+                return false;
             }
         }
     }
 
     for sub_set_clause in set_clause {
         let ct = get_col(&sub_set_clause.col_name);
-        if !ct.data_type.valid_value(&sub_set_clause.value) {
+        let valid = match &sub_set_clause.expr {
+            Expr::Value(v) => ct.data_type.valid_value(v),
+            // only widening assignments, e.g. an Int expression into a Numeric column
+            expr => match check_expr(expr, &valid_qualified_col, &|col: &Column| get_col(&col.col_name).data_type.clone(), &no_subquery) {
+                Some(t) => t.unify(&ct.data_type) == Some(ct.data_type.without_default()),
+                None => false,
+            },
+        };
+        if !valid {
             return false;
         }
     }
@@ -488,46 +875,33 @@ pub fn check_update(tb_name: &String, map: &HashMap<String, ColumnType>, set_cla
     if !no_repeat {
         return false;
     }
-
+    if sm.prepare {
+        return true;
+    }
 
     let database = sm.current_database.as_ref().unwrap();
     let mut tree = QueryTree::new(&sm.root_dir, database, sm.rm.clone());
     tree.build(&vec![tb_name.clone()], &Selector::All, where_clause);
     let record_list = tree.query();
+    let new_records = match sm.updated_records(&record_list, set_clause, false) {
+        Ok(new_records) => new_records,
+        Err(_) => return false,
+    };
 
     let th = sm.open_table(tb_name, false).unwrap();
     defer!(th.close());
 
     let affected_cols_index: HashSet<_> = set_column.iter().map(|name| map.get(name).unwrap().index).collect();
-    let pri_cols = th.get_primary_column_index().unwrap();
+    let pri_cols = th.get_primary_column_index().unwrap_or_default();
     let pri_affected = pri_cols.iter().fold(false, |affected, pri_index| affected || affected_cols_index.contains(pri_index));
     if pri_affected {
-        // primary key affected
-        let foreign_table = table_foreign_this_table(tb_name, sm);
-        if foreign_table.len() > 0 {
-            // referenced by other table
-            for table in foreign_table {
-                let fth = sm.open_table(&table, false).unwrap();
-                defer!(fth.close());
-                // find other table reference these primary columns
-                let foreign_btrees = fth.get_btrees().into_iter().filter_map(
-                    |btree| {
-                        if btree.is_foreign() && btree.get_foreign_table_name() == tb_name {
-                            Some(btree)
-                        } else {
-                            None
-                        }
-                    }
-                );
-                // tranverse all foreign keys btrees to find any row reference these record
-                for ftree in foreign_btrees {
-                    for record in &record_list.record {
-                        if ftree.search_record(&RawIndex::from_record(record, &pri_cols)).is_some() {
-                            return false;
-                        }
-                    }
-                }
-            }
+        // the rows referencing the old primary keys must take the new ones or let them go
+        let mut propagation = Propagation::new(sm);
+        for ((ptr, record), new_record) in record_list.ptrs.iter().zip(record_list.record.iter()).zip(new_records.iter()) {
+            propagation.update(tb_name, ptr.to_u64(), record.clone(), new_record.clone());
+        }
+        if !propagation.finish() {
+            return false;
         }
     }
     // affect foreign btree
@@ -539,7 +913,6 @@ pub fn check_update(tb_name: &String, map: &HashMap<String, ColumnType>, set_cla
 
     // println!("{}", affected_foreign_btree.len());
 
-    let (ptr, mut pri_btree) = th.get_primary_btree_with_ptr().unwrap();
     let mut deleted = Vec::new();
     let mut inserted = Vec::new();
 
@@ -553,11 +926,11 @@ pub fn check_update(tb_name: &String, map: &HashMap<String, ColumnType>, set_cla
         defer!(fth.close());
         let pri_btree = fth.get_primary_btree().unwrap();
 
-        for (ptr, record) in record_list.ptrs.iter().zip(record_list.record.iter()) {
-            let mut new_record = record.clone();
-            new_record.set_(set_clause, &record_list.ty);
-
-            let ri = RawIndex::from_record(&new_record, &btree.index_col);
+        for new_record in &new_records {
+            if btree.index_col.iter().any(|i| new_record.cols[*i as usize].data.is_none()) {
+                continue;
+            }
+            let ri = RawIndex::from_record(new_record, &btree.index_col);
             // println!("{:?}", &ri);
             if pri_btree.search_record(&ri).is_none() {
                 return false;
@@ -565,15 +938,13 @@ pub fn check_update(tb_name: &String, map: &HashMap<String, ColumnType>, set_cla
         }
     }
     if pri_affected {
-        for (ptr, record) in record_list.ptrs.iter().zip(record_list.record.iter()) {
-            let mut new_record = record.clone();
-            new_record.set_(set_clause, &record_list.ty);
-
+        let (ptr, mut pri_btree) = th.get_primary_btree_with_ptr().unwrap();
+        for ((ptr, record), new_record) in record_list.ptrs.iter().zip(record_list.record.iter()).zip(new_records.iter()) {
             let ri = RawIndex::from_record(record, &pri_cols);
             pri_btree.delete_record(&ri, ptr.to_u64());
             deleted.push((ptr, ri));
 
-            let new_ri = RawIndex::from_record(&new_record, &pri_cols);
+            let new_ri = RawIndex::from_record(new_record, &pri_cols);
             let dup = pri_btree.insert_record(&new_ri, ptr.to_u64(), false);
             if dup {
                 duplicate = true;
@@ -597,9 +968,56 @@ pub fn check_update(tb_name: &String, map: &HashMap<String, ColumnType>, set_cla
         }
     }
 
+    let unique_btrees = th.get_btrees().into_iter().filter(|t|
+        t.is_unique() && t.index_col.iter().any(|i| affected_cols_index.contains(i))
+    ).collect();
+    let updated = record_list.ptrs.iter().map(|ptr| ptr.to_u64()).collect();
+    if !check_unique(&th, &unique_btrees, &new_records, &updated) {
+        return false;
+    }
+    if !check_constraints(&th, &new_records, &record_list.ty) {
+        return false;
+    }
+
+    true
+}
+
+// a key must not be held by a row outside `replaced` nor by two of the records, a key with a NULL never conflicts
+pub fn check_unique(th: &TableHandler, btrees: &Vec<BTree>, records: &Vec<Record>, replaced: &HashSet<u64>) -> bool {
+    for btree in btrees {
+        let mut seen = HashSet::new();
+        for record in records {
+            if btree.index_col.iter().any(|i| record.cols[*i as usize].data.is_none()) {
+                continue;
+            }
+            let key = RawIndex::from_record(record, &btree.index_col);
+            let taken = match btree.search_record(&key) {
+                Some(bucket) => bucket.data.iter().any(|ptr| !replaced.contains(ptr) && {
+                    let (old, _) = th.get_record_(*ptr);
+                    btree.index_col.iter().all(|i| old.cols[*i as usize].data.is_some())
+                }),
+                None => false,
+            };
+            if taken || !seen.insert(key.index) {
+                return false;
+            }
+        }
+    }
     true
 }
 
+pub fn check_unique_data(tb_name: &String, column_list: &Vec<String>, sm: &SystemManager) -> bool {
+    let database = sm.current_database.as_ref().unwrap();
+    let mut tree = QueryTree::new(&sm.root_dir, database, sm.rm.clone());
+    tree.build(&vec![tb_name.clone()], &Selector::All, &None);
+    let record_list = tree.query();
+    let index: Vec<u32> = column_list.iter().map(|name| record_list.ty.iter().position(|ct| &ct.name == name).unwrap() as u32).collect();
+    let mut seen = HashSet::new();
+    record_list.record.iter()
+        .filter(|record| index.iter().all(|i| record.cols[*i as usize].data.is_some()))
+        .all(|record| seen.insert(RawIndex::from_record(record, &index).index))
+}
+
 pub fn check_create_index(idx_name: &String, map: &HashMap<String, ColumnType>, column_list: &Vec<String>, btrees: &Vec<BTree>) -> bool {
     column_list.len() > 0 
         && check_no_repeat(column_list)
@@ -608,21 +1026,60 @@ pub fn check_create_index(idx_name: &String, map: &HashMap<String, ColumnType>,
 }
 
 pub fn check_drop_index(idx_name: &String, btrees: &Vec<BTree>) -> bool {
-    btrees.iter().fold(false, |found, btree| found || (&btree.index_name == idx_name && btree.is_index()))
+    btrees.iter().fold(false, |found, btree| found || (&btree.index_name == idx_name && (btree.is_index() || btree.is_unique())))
 }
 
-pub fn check_add_column(map: &HashMap<String, ColumnType>, field: &Field) -> bool {
+pub fn check_add_column(tb_name: &String, map: &HashMap<String, ColumnType>, field: &Field, sm: &SystemManager) -> bool {
     match field {
-        Field::ColumnField { col_name, ty: _, not_null: _, default_value: _ } => {
-            if map.contains_key(col_name) {
+        Field::ColumnField { col_name, ty, not_null: _, auto_increment, default_value, unique, check } => {
+            // the rows already there would be left without a value
+            if map.contains_key(col_name) || *auto_increment {
                 return false;
             }
+            // every existing row gets the default, so a unique column can only default to NULL on a table with rows
+            if *unique && default_value.as_ref().map_or(false, |v| !v.is_null()) {
+                let database = sm.current_database.as_ref().unwrap();
+                let mut tree = QueryTree::new(&sm.root_dir, database, sm.rm.clone());
+                tree.build(&vec![tb_name.clone()], &Selector::All, &None);
+                if tree.query().record.len() > 1 {
+                    return false;
+                }
+            }
+            if let Some(check) = check {
+                let mut cols: HashMap<String, Type> = map.iter().map(|(name, ct)| (name.clone(), ct.data_type.clone())).collect();
+                cols.insert(col_name.clone(), Type::from_type(ty, default_value));
+                if !check_check(check, &cols) {
+                    return false;
+                }
+
+                // the rows already there get the default in the new column
+                let database = sm.current_database.as_ref().unwrap();
+                let mut tree = QueryTree::new(&sm.root_dir, database, sm.rm.clone());
+                tree.build(&vec![tb_name.clone()], &Selector::All, &None);
+                let record_list = tree.query();
+                let mut ty = record_list.ty.clone();
+                let new_column = ColumnType::from_field(tb_name, ty.len() as u32, field);
+                let default = Data::from_value(&new_column.default_value(), &new_column);
+                ty.push(new_column);
+                let all_hold = record_list.record.iter().all(|record| {
+                    let record = Record::from_data(record.cols.iter().map(|c| c.data.clone()).chain(std::iter::once(default.clone())).collect(), &ty);
+                    CheckConstraint::holds(&check.clause, &record, &ty)
+                });
+                if !all_hold {
+                    return false;
+                }
+            }
         },
         _ => return false,
     }
     true
 }
 
+// whether a CHECK of the table reads the column
+fn used_by_check(th: &TableHandler, col_name: &String) -> bool {
+    th.get_checks().iter().any(|check| check.clause().iter().any(|clause| clause.columns().iter().any(|col| &col.col_name == col_name)))
+}
+
 pub fn check_drop_column(tb_name: &String, col_name: &String, sm: &SystemManager) -> bool {
     let th = sm.open_table(tb_name, false).unwrap();
     defer!(th.close());
@@ -632,6 +1089,10 @@ pub fn check_drop_column(tb_name: &String, col_name: &String, sm: &SystemManager
         return false;
     }
 
+    if used_by_check(&th, col_name) {
+        return false;
+    }
+
     let del_index = map.get(col_name).unwrap().index;
     let pri_cols = th.get_primary_column_index();
 
@@ -675,7 +1136,8 @@ pub fn check_change_column(tb_name: &String, col_name: &String, field: &Field, s
     }
 
     let cf = match field {
-        Field::ColumnField{ col_name: _, ty: _, not_null: _, default_value: _ } => true,
+        // a column that counts up takes no default, as in a create table
+        Field::ColumnField{ col_name: _, ty: _, not_null: _, auto_increment, default_value, unique: _, check: None } => !(*auto_increment && default_value.is_some()),
         _ => false,
     };
 
@@ -683,6 +1145,10 @@ pub fn check_change_column(tb_name: &String, col_name: &String, field: &Field, s
         return false;
     }
 
+    if used_by_check(&th, col_name) {
+        return false;
+    }
+
     let origin_col = map.get(col_name).unwrap();
     let index = origin_col.index;
 
@@ -694,12 +1160,18 @@ pub fn check_change_column(tb_name: &String, col_name: &String, field: &Field, s
         return false;
     }
 
+    // the column may keep its name
     let new_col = ColumnType::from_field(&origin_col.tb_name, index, field);
-    if map.contains_key(&new_col.name) {
+    if &new_col.name != col_name && map.contains_key(&new_col.name) {
+        return false;
+    }
+
+    if !origin_col.data_type.same(&new_col.data_type) {
         return false;
     }
 
-    if !origin_col.data_type.comparable(&new_col.data_type) {
+    // only a column that counts up already keeps doing so
+    if new_col.auto_increment && !origin_col.auto_increment {
         return false;
     }
 
@@ -778,7 +1250,7 @@ pub fn check_drop_constraint_primary_key(tb_name: &String, pk_name: &String, sm:
     true
 }
 
-pub fn check_add_constraint_foreign_key(tb_name: &String, fk_name: &String, column_list: &Vec<String>, foreign_tb_name: &String, foreign_column_list: &Vec<String>, sm: &SystemManager) -> bool {
+pub fn check_add_constraint_foreign_key(tb_name: &String, fk_name: &String, column_list: &Vec<String>, foreign_tb_name: &String, foreign_column_list: &Vec<String>, actions: [ReferentialAction; 2], sm: &SystemManager) -> bool {
     if column_list.len() != foreign_column_list.len() {
         return false;
     }
@@ -797,6 +1269,15 @@ pub fn check_add_constraint_foreign_key(tb_name: &String, fk_name: &String, colu
         return false;
     }
 
+    let fits = column_list.iter().all(|c| {
+        let ct = map.get(c).unwrap();
+        actions.iter().all(|action| check_referential_action(action, ct.can_be_null, ct.has_default && !ct.default_null))
+    });
+    if !fits {
+        th.close();
+        return false;
+    }
+
     let fth = sm.open_table(foreign_tb_name, false);
     if fth.is_none() {
         th.close();
@@ -832,7 +1313,7 @@ pub fn check_add_constraint_foreign_key(tb_name: &String, fk_name: &String, colu
     for (this_col, foreign_col) in this_table_column.iter().zip(foreign_table_column.iter()) {
         let this_type = &this_col.data_type;
         let foreign_type = &foreign_col.data_type;
-        if !this_type.comparable(foreign_type) {
+        if !this_type.same(foreign_type) {
             th.close();
             return false;
         }
@@ -864,3 +1345,216 @@ pub fn check_drop_constraint_foreign_key(tb_name: &String, fk_name: &String, sm:
 
     true
 }
+
+// the rows already in the table must meet the new check
+pub fn check_add_constraint_check(tb_name: &String, ck_name: &String, check: &Check, sm: &SystemManager) -> bool {
+    let th = sm.open_table(tb_name, false).unwrap();
+    defer!(th.close());
+    if th.get_checks().iter().any(|c| &c.name == ck_name) {
+        return false;
+    }
+    let cols: HashMap<String, Type> = th.get_column_types().cols.into_iter().map(|ct| (ct.name, ct.data_type)).collect();
+    if !check_check(check, &cols) {
+        return false;
+    }
+
+    let database = sm.current_database.as_ref().unwrap();
+    let mut tree = QueryTree::new(&sm.root_dir, database, sm.rm.clone());
+    tree.build(&vec![tb_name.clone()], &Selector::All, &None);
+    let record_list = tree.query();
+    record_list.record.iter().all(|record| CheckConstraint::holds(&check.clause, record, &record_list.ty))
+}
+
+pub fn check_drop_constraint_check(tb_name: &String, ck_name: &String, sm: &SystemManager) -> bool {
+    let th = sm.open_table(tb_name, false).unwrap();
+    defer!(th.close());
+    th.get_checks().iter().any(|c| &c.name == ck_name)
+}
+
+// the types the values bound to the placeholders of `stmt` must have and whether they may be NULL,
+// a placeholder used in several places gets every one of them
+pub fn param_types(stmt: &TableStmt, sm: &SystemManager) -> HashMap<usize, Vec<(Type, bool)>> {
+    let mut types = HashMap::new();
+    query_param_types(stmt, &HashMap::new(), sm, &mut types);
+    types
+}
+
+fn query_param_types(stmt: &TableStmt, outer_cols: &HashMap<&String, HashMap<String, ColumnType>>, sm: &SystemManager, types: &mut HashMap<usize, Vec<(Type, bool)>>) {
+    match stmt {
+        TableStmt::Insert { tb_name, column_list, value_lists } => {
+            let th = sm.open_table(tb_name, false).unwrap();
+            let cols = th.get_column_types().cols;
+            th.close();
+            let targets: Vec<&ColumnType> = match column_list {
+                Some(column_list) => column_list.iter().filter_map(|name| cols.iter().find(|ct| &ct.name == name)).collect(),
+                None => cols.iter().collect(),
+            };
+            for values in value_lists {
+                for (value, ct) in values.iter().zip(targets.iter()) {
+                    if let Value::Param(n) = value {
+                        types.entry(*n).or_insert(vec![]).push((ct.data_type.without_default(), ct.can_be_null));
+                    }
+                }
+            }
+        },
+        TableStmt::InsertSelect { tb_name: _, column_list: _, query } => query_param_types(query, outer_cols, sm, types),
+        TableStmt::Update { tb_name, set_clause, where_clause } => {
+            let th = sm.open_table(tb_name, false).unwrap();
+            let map = th.get_column_types_as_hashmap();
+            th.close();
+            for set in set_clause {
+                let ct = &map[&set.col_name];
+                match &set.expr {
+                    Expr::Value(Value::Param(n)) => types.entry(*n).or_insert(vec![]).push((ct.data_type.without_default(), ct.can_be_null)),
+                    expr => expr_param_types(expr, &ct.data_type.without_default(), &HashMap::new(), outer_cols, sm, types),
+                }
+            }
+            let mut tb_cols = HashMap::new();
+            tb_cols.insert(tb_name, map);
+            for sub_where in where_clause.iter().flatten() {
+                where_param_types(sub_where, &tb_cols, outer_cols, sm, types);
+            }
+        },
+        TableStmt::Delete { tb_name, where_clause } => {
+            let th = sm.open_table(tb_name, false).unwrap();
+            let map = th.get_column_types_as_hashmap();
+            th.close();
+            let mut tb_cols = HashMap::new();
+            tb_cols.insert(tb_name, map);
+            for sub_where in where_clause.iter().flatten() {
+                where_param_types(sub_where, &tb_cols, outer_cols, sm, types);
+            }
+        },
+        TableStmt::Select { distinct: _, table_list, join_list, selector, where_clause, group_clause: _, having_clause, order_clause: _, limit_clause: _ } => {
+            let tb_cols = match get_table_cols(table_list, sm) {
+                Some(tb_cols) => tb_cols,
+                None => return,
+            };
+            let numeric = Type::Numeric(None);
+            if let Selector::Columns(items, _) = selector {
+                for item in items {
+                    if let SelectItem::Expr(expr) = item {
+                        expr_param_types(expr, &numeric, &tb_cols, outer_cols, sm, types);
+                    }
+                }
+            }
+            for join in join_list {
+                if let Some(JoinCondition::On(clauses)) = &join.cond {
+                    for clause in clauses {
+                        where_param_types(clause, &tb_cols, outer_cols, sm, types);
+                    }
+                }
+            }
+            for sub_where in where_clause.iter().flatten() {
+                where_param_types(sub_where, &tb_cols, outer_cols, sm, types);
+            }
+            for having in having_clause.iter().flatten() {
+                let ct = match &having.item {
+                    SelectItem::Column(col) => resolve_col(&tb_cols, col).cloned(),
+                    SelectItem::Aggregate(aggr) => Some(ColumnType::from_aggregate(aggr, aggr.col.as_ref().and_then(|col| resolve_col(&tb_cols, col)))),
+                    SelectItem::Expr(expr) => {
+                        expr_param_types(expr, &numeric, &tb_cols, outer_cols, sm, types);
+                        None
+                    },
+                };
+                match (&having.expr, ct) {
+                    (Expr::Value(Value::Param(n)), Some(ct)) => types.entry(*n).or_insert(vec![]).push((ct.data_type.without_default(), true)),
+                    (expr, _) => expr_param_types(expr, &numeric, &tb_cols, outer_cols, sm, types),
+                }
+            }
+        },
+        TableStmt::Compound { op: _, all: _, left, right, order_clause: _, limit_clause: _ } => {
+            query_param_types(left, outer_cols, sm, types);
+            query_param_types(right, outer_cols, sm, types);
+        },
+        _ => {},
+    }
+}
+
+// the columns of the statement itself hide those of the enclosing ones
+fn where_param_types(sub_where: &WhereClause, tb_cols: &HashMap<&String, HashMap<String, ColumnType>>, outer_cols: &HashMap<&String, HashMap<String, ColumnType>>, sm: &SystemManager, types: &mut HashMap<usize, Vec<(Type, bool)>>) {
+    let col_type = |col: &Column| resolve_col(tb_cols, col).or_else(|| resolve_col(outer_cols, col)).map(|ct| (ct.data_type.without_default(), true));
+    let mut bind = |value: &Value, ty: Option<(Type, bool)>| {
+        if let (Value::Param(n), Some(ty)) = (value, ty) {
+            types.entry(*n).or_insert(vec![]).push(ty);
+        }
+    };
+    match sub_where {
+        WhereClause::Comparison { col, op: _, expr: Expr::Value(value) } => bind(value, col_type(col)),
+        WhereClause::ExprComparison { left: Expr::Column(col), op: _, right: Expr::Value(value) } |
+        WhereClause::ExprComparison { left: Expr::Value(value), op: _, right: Expr::Column(col) } => bind(value, col_type(col)),
+        WhereClause::In { col, values } => {
+            for value in values {
+                bind(value, col_type(col));
+            }
+        },
+        WhereClause::Between { col, low, high } => {
+            bind(low, col_type(col));
+            bind(high, col_type(col));
+        },
+        WhereClause::Comparison { col: _, op: _, expr } => expr_param_types(expr, &Type::Numeric(None), tb_cols, outer_cols, sm, types),
+        // a placeholder compared with an expression takes the type of the expression
+        WhereClause::ExprComparison { left: Expr::Value(value @ Value::Param(_)), op: _, right: expr } |
+        WhereClause::ExprComparison { left: expr, op: _, right: Expr::Value(value @ Value::Param(_)) } => {
+            let ty = check_expr(expr, &|col: &Column| col_type(col).is_some(), &|col: &Column| col_type(col).unwrap().0, &no_subquery);
+            bind(value, ty.map(|ty| (ty, true)));
+            expr_param_types(expr, &Type::Numeric(None), tb_cols, outer_cols, sm, types);
+        },
+        WhereClause::ExprComparison { left, op: _, right } => {
+            expr_param_types(left, &Type::Numeric(None), tb_cols, outer_cols, sm, types);
+            expr_param_types(right, &Type::Numeric(None), tb_cols, outer_cols, sm, types);
+        },
+        WhereClause::InSubquery { col: _, query } | WhereClause::Exists(query) => {
+            query_param_types(query, &nested_scope(tb_cols, outer_cols), sm, types);
+        },
+        WhereClause::And(clauses) | WhereClause::Or(clauses) => {
+            for clause in clauses {
+                where_param_types(clause, tb_cols, outer_cols, sm, types);
+            }
+        },
+        WhereClause::Not(clause) => where_param_types(clause, tb_cols, outer_cols, sm, types),
+        WhereClause::IsAssert { .. } | WhereClause::Like { .. } => {},
+    }
+}
+
+// placeholders in arithmetic take `ty`, the type of the column an update assigns to or any number
+fn expr_param_types(expr: &Expr, ty: &Type, tb_cols: &HashMap<&String, HashMap<String, ColumnType>>, outer_cols: &HashMap<&String, HashMap<String, ColumnType>>, sm: &SystemManager, types: &mut HashMap<usize, Vec<(Type, bool)>>) {
+    match expr {
+        Expr::Binary { op: _, left, right } => {
+            for side in vec![left, right] {
+                match side.as_ref() {
+                    Expr::Value(Value::Param(n)) => types.entry(*n).or_insert(vec![]).push((ty.clone(), false)),
+                    side => expr_param_types(side, ty, tb_cols, outer_cols, sm, types),
+                }
+            }
+        },
+        Expr::Neg(expr) => match expr.as_ref() {
+            Expr::Value(Value::Param(n)) => types.entry(*n).or_insert(vec![]).push((ty.clone(), false)),
+            expr => expr_param_types(expr, ty, tb_cols, outer_cols, sm, types),
+        },
+        Expr::Subquery(query) => query_param_types(query, &nested_scope(tb_cols, outer_cols), sm, types),
+        // the arguments of a function take values of any type
+        Expr::Func { func: _, args } => {
+            for arg in args {
+                expr_param_types(arg, ty, tb_cols, outer_cols, sm, types);
+            }
+        },
+        Expr::Case { whens, else_ } => {
+            for (cond, result) in whens {
+                where_param_types(cond, tb_cols, outer_cols, sm, types);
+                expr_param_types(result, ty, tb_cols, outer_cols, sm, types);
+            }
+            if let Some(result) = else_ {
+                expr_param_types(result, ty, tb_cols, outer_cols, sm, types);
+            }
+        },
+        Expr::Cast { expr, ty: _ } => expr_param_types(expr, ty, tb_cols, outer_cols, sm, types),
+        Expr::Value(_) | Expr::Column(_) => {},
+    }
+}
+
+fn nested_scope<'a>(tb_cols: &HashMap<&'a String, HashMap<String, ColumnType>>, outer_cols: &HashMap<&'a String, HashMap<String, ColumnType>>) -> HashMap<&'a String, HashMap<String, ColumnType>> {
+    let mut scope = outer_cols.clone();
+    scope.extend(tb_cols.iter().map(|(tb_name, cols)| (*tb_name, cols.clone())));
+    scope
+}
@@ -1,3 +1,5 @@
 pub mod system_manager;
 pub mod check;
-mod query_tree;
\ No newline at end of file
+mod query_tree;
+mod sequence;
+mod referential;
\ No newline at end of file
@@ -1,16 +1,34 @@
 use crate::parser::ast;
+use crate::parser::sql;
 use crate::rm::record::*;
 use crate::rm::record_manager::*;
 use crate::rm::pagedef::*;
+use crate::rm::table_handler::TableHandler;
 use crate::index::btree::*;
+use crate::utils::string;
+use super::sequence::Sequences;
 use std::path::PathBuf;
+use std::fs;
+use std::cmp::Ordering;
 use std::rc::Rc;
 use std::cell::RefCell;
+use std::cell::Cell;
+use std::time::{Duration, Instant};
 use std::collections::HashMap;
+use std::collections::HashSet;
 
 trait QueryNode {
     fn get_name(&self) -> &str;
     fn query(&self) -> RecordList;
+
+    // what EXPLAIN prints after the name
+    fn get_detail(&self) -> String {
+        String::new()
+    }
+
+    fn get_sons(&self) -> Vec<&dyn QueryNode> {
+        Vec::new()
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -208,6 +226,29 @@ impl RangeCondition {
             None => false,
         }
     }
+
+    // the bounds as comparisons, `ty` gives the precision of numeric bounds
+    fn name(&self, ty: &Vec<ColumnType>) -> String {
+        let col = self.col.name();
+        let ct = ty.iter().find(|ct| ct.name == self.col.col_name).unwrap();
+        let value = |data: &Data| Data::to_value(&Some(data.clone()), ct).name();
+        let mut bounds = Vec::new();
+        if self.is_single() {
+            bounds.push(format!("{} = {}", col, value(self.range.min.as_ref().unwrap())));
+        }
+        else {
+            if let Some(ref min) = self.range.min {
+                bounds.push(format!("{} {} {}", col, if self.range.min_equal {">="} else {">"}, value(min)));
+            }
+            if let Some(ref max) = self.range.max {
+                bounds.push(format!("{} {} {}", col, if self.range.max_equal {"<="} else {"<"}, value(max)));
+            }
+        }
+        for data in &self.range.not_equal {
+            bounds.push(format!("{} <> {}", col, value(data)));
+        }
+        bounds.join(" and ")
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -225,18 +266,13 @@ impl PairCondition {
             false
         }
         else {
-            let l_data = l_data.unwrap();
-            let r_data = r_data.unwrap();
-            match &self.op {
-                ast::Op::Equal => l_data == r_data,
-                ast::Op::NotEqual => l_data != r_data,
-                ast::Op::LessEqual => l_data <= r_data,
-                ast::Op::GreaterEqual => l_data >= r_data,
-                ast::Op::Less => l_data < r_data,
-                ast::Op::Greater => l_data > r_data,
-            }
+            compare_data(&self.op, &l_data.unwrap(), &r_data.unwrap())
         }
     }
+
+    fn name(&self) -> String {
+        format!("{} {} {}", self.l_col.name(), self.op.symbol(), self.r_col.name())
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -250,6 +286,83 @@ impl NullCondition {
         let data = record.get_match_data(&self.col, ty).0;
         (self.is_null && data.is_none()) || (!self.is_null && data.is_some())
     }
+
+    fn name(&self) -> String {
+        format!("{} is {}null", self.col.name(), if self.is_null {""} else {"not "})
+    }
+}
+
+#[derive(Debug, Clone)]
+struct ExprCondition {
+    pub clause: ast::WhereClause,
+    pub branches: Vec<Vec<RangeCondition>>, // range conditions of every OR branch, used to union the index ranges
+}
+
+impl ExprCondition {
+    // three-valued logic, None stands for unknown, `tree` runs the correlated subqueries
+    fn eval(clause: &ast::WhereClause, record: &Record, ty: &Vec<ColumnType>, tree: &QueryTree) -> Option<bool> {
+        match clause {
+            ast::WhereClause::Comparison{col, op, expr} if expr.has_subquery() => {
+                let clause = ast::WhereClause::ExprComparison {
+                    left: ast::Expr::Column(col.clone()),
+                    op: op.clone(),
+                    right: tree.resolve_expr(expr, Some((record, ty))),
+                };
+                ExprCondition::eval(&clause, record, ty, tree)
+            },
+            ast::WhereClause::ExprComparison{left, op, right} if left.has_subquery() || right.has_subquery() => {
+                let clause = ast::WhereClause::ExprComparison {
+                    left: tree.resolve_expr(left, Some((record, ty))),
+                    op: op.clone(),
+                    right: tree.resolve_expr(right, Some((record, ty))),
+                };
+                ExprCondition::eval(&clause, record, ty, tree)
+            },
+            ast::WhereClause::InSubquery{col, query} => {
+                let (data, ct) = record.get_match_data(col, ty);
+                let data = data?;
+                let ct = ct.unwrap();
+                let list = tree.run_subquery(&tree.bind(query, &Vec::new(), record, ty), &None);
+                let mut has_null = false;
+                for r in &list.record {
+                    match r.cols[0].data {
+                        Some(ref v) => if compare_typed(&ast::Op::Equal, data.clone(), &ct, v.clone(), &list.ty[0]) == Some(true) {
+                            return Some(true);
+                        },
+                        None => has_null = true,
+                    }
+                }
+                if has_null {None} else {Some(false)}
+            },
+            ast::WhereClause::Exists(query) => {
+                let list = tree.run_subquery(&tree.bind(query, &Vec::new(), record, ty), &Some(ast::LimitClause { limit: 1, offset: 0 }));
+                Some(list.record.len() > 0)
+            },
+            clause => record.test(clause, ty, &|clause| ExprCondition::eval(clause, record, ty, tree)),
+        }
+    }
+
+    fn match_(&self, record: &Record, ty: &Vec<ColumnType>, tree: &QueryTree) -> bool {
+        ExprCondition::eval(&self.clause, record, ty, tree) == Some(true)
+    }
+}
+
+// a clause that always gives `res`: AND of nothing is true, OR of nothing is false and NULL = NULL is unknown
+fn constant(res: Option<bool>) -> ast::WhereClause {
+    match res {
+        Some(true) => ast::WhereClause::And(Vec::new()),
+        Some(false) => ast::WhereClause::Or(Vec::new()),
+        None => ast::WhereClause::ExprComparison {
+            left: ast::Expr::Value(ast::Value::Null),
+            op: ast::Op::Equal,
+            right: ast::Expr::Value(ast::Value::Null),
+        },
+    }
+}
+
+// whether the tables can resolve the column without the enclosing selects
+fn is_inner(name_cols: &Vec<(String, HashMap<String, ColumnType>)>, col: &ast::Column) -> bool {
+    name_cols.iter().any(|(tb_name, cols)| col.tb_name.as_ref().map_or(true, |tb| tb == tb_name) && cols.contains_key(&col.col_name))
 }
 
 struct SelectNode {
@@ -258,9 +371,14 @@ struct SelectNode {
     pub rm: Rc<RefCell<RecordManager>>,
     pub son: Option<Box<dyn QueryNode>>,
     pub table_list: Option<ast::Name>,
+    pub alias: Option<ast::Name>,
+    pub view: Option<Rc<RecordList>>, // the rows of a view, read instead of a table
     pub range_conds: Vec<RangeCondition>,
     pub pair_conds: Vec<PairCondition>,
     pub null_conds: Vec<NullCondition>,
+    pub expr_conds: Vec<ExprCondition>,
+    pub order_index: Option<(Vec<u32>, bool)>, // (index_col, asc)
+    pub limit: Option<usize>, // stop scanning once this many records are found
     /*
     pub table_list: Vec<ast::Name>,
     pub condition: Option<Vec<ast::WhereClause>>,
@@ -287,14 +405,24 @@ impl SelectNode {
         for cond in &self.null_conds {
             valid &= cond.match_(record, ty);
         }
+        if self.expr_conds.len() > 0 {
+            let tree = QueryTree::new(&self.root_dir, &self.database, self.rm.clone());
+            for cond in &self.expr_conds {
+                valid &= cond.match_(record, ty, &tree);
+            }
+        }
         valid
     }
 
-    fn used_index_count(&self, index_col: &Vec<u32>, ty: &Vec<ColumnType>) -> usize {
+    fn is_full(&self, record_list: &RecordList) -> bool {
+        self.limit.map_or(false, |limit| record_list.record.len() >= limit)
+    }
+
+    fn used_index_count(index_col: &Vec<u32>, range_conds: &Vec<RangeCondition>, ty: &Vec<ColumnType>) -> usize {
         let mut count = 0;
         for index in index_col {
             let mut can_continue = false;
-            for cond in &self.range_conds {
+            for cond in range_conds {
                 if cond.col.col_name == ty[*index as usize].name {
                     count += 1;
                     can_continue = cond.is_single();
@@ -308,7 +436,20 @@ impl SelectNode {
         count 
     }
 
-    fn get_index(&self, index_col: &Vec<u32>, ty: &Vec<ColumnType>) -> (RawIndex, bool, bool) {
+    fn best_btree<'a, 'b>(btrees: &'a Vec<BTree<'b>>, range_conds: &Vec<RangeCondition>, ty: &Vec<ColumnType>) -> Option<&'a BTree<'b>> {
+        let mut best_btree = None;
+        let mut max_used = 0;
+        for btree in btrees {
+            let used = SelectNode::used_index_count(&btree.index_col, range_conds, ty);
+            if used > max_used {
+                best_btree = Some(btree);
+                max_used = used;
+            }
+        }
+        best_btree
+    }
+
+    fn get_index(index_col: &Vec<u32>, range_conds: &Vec<RangeCondition>, ty: &Vec<ColumnType>) -> (RawIndex, bool, bool) {
         let mut direction = true; // false -> left,  true -> right
         let mut can_be_equal = false;
         let mut raw_index = RawIndex {
@@ -316,7 +457,7 @@ impl SelectNode {
         };
         for index in index_col {
             let mut can_continue = false;
-            for cond in &self.range_conds {
+            for cond in range_conds {
                 if cond.col.col_name == ty[*index as usize].name {
                     if cond.range.min.is_some() {
                         raw_index.index.push(cond.range.min.clone().unwrap());
@@ -338,6 +479,209 @@ impl SelectNode {
         }
         (raw_index, direction, can_be_equal)
    }
+
+    // the key where a scan in the given direction leaves the range, as (columns, key, inclusive)
+    fn get_end(index_col: &Vec<u32>, range_conds: &Vec<RangeCondition>, ty: &Vec<ColumnType>, direction: bool) -> Option<(Vec<u32>, RawIndex, bool)> {
+        let mut cols = Vec::new();
+        let mut raw_index = RawIndex {
+            index: Vec::new(),
+        };
+        let mut inclusive = true;
+        for index in index_col {
+            let cond = range_conds.iter().find(|cond| cond.col.col_name == ty[*index as usize].name);
+            match cond {
+                Some(cond) if cond.is_single() => {
+                    cols.push(*index);
+                    raw_index.index.push(cond.range.min.clone().unwrap());
+                }
+                Some(cond) => {
+                    let bound = if direction {(&cond.range.max, cond.range.max_equal)} else {(&cond.range.min, cond.range.min_equal)};
+                    if let Some(data) = bound.0 {
+                        cols.push(*index);
+                        raw_index.index.push(data.clone());
+                        inclusive = bound.1;
+                    }
+                    break;
+                }
+                None => break,
+            }
+        }
+        if cols.len() > 0 {
+            Some((cols, raw_index, inclusive))
+        }
+        else {
+            None
+        }
+    }
+
+    fn get_order_index(&self, index_col: &Vec<u32>, ty: &Vec<ColumnType>, asc: bool) -> Option<(RawIndex, bool)> {
+        let mut raw_index = RawIndex {
+            index: Vec::new(),
+        };
+        let mut can_be_equal = true;
+        for index in index_col {
+            let cond = self.range_conds.iter().find(|cond| cond.col.col_name == ty[*index as usize].name);
+            match cond {
+                Some(cond) if cond.is_single() => {
+                    raw_index.index.push(cond.range.min.clone().unwrap());
+                }
+                Some(cond) => {
+                    let bound = if asc {(&cond.range.min, cond.range.min_equal)} else {(&cond.range.max, cond.range.max_equal)};
+                    if let Some(data) = bound.0 {
+                        raw_index.index.push(data.clone());
+                        can_be_equal = bound.1;
+                    }
+                    break;
+                }
+                None => break,
+            }
+        }
+        // a prefix is enough for the lower bound, the upper bound is only exact with a full key
+        if (asc && raw_index.index.len() > 0) || raw_index.index.len() == index_col.len() {
+            Some((raw_index, can_be_equal))
+        }
+        else {
+            None
+        }
+    }
+
+    fn scan(&self, th: &TableHandler, bucket: Option<Bucket>, direction: bool, end: Option<(Vec<u32>, RawIndex, bool)>, record_list: &mut RecordList) {
+        let mut bucket = bucket;
+        while bucket.is_some() && !self.is_full(record_list) {
+            let bucket_ = bucket.unwrap();
+            for data in &bucket_.data {
+                let record = th.get_record_(*data).0;
+                if let Some((ref cols, ref key, inclusive)) = end {
+                    // all records in a bucket share the same key, so the scan can stop here
+                    let beyond = match RawIndex::from_record(&record, cols).partial_cmp(key) {
+                        Some(Ordering::Equal) => !inclusive,
+                        Some(Ordering::Greater) => direction,
+                        Some(Ordering::Less) => !direction,
+                        None => false,
+                    };
+                    if beyond {
+                        return;
+                    }
+                }
+                if self.is_valid(&record, &record_list.ty) {
+                    record_list.record.push(record);
+                    record_list.ptrs.push(StrPointer::new(*data));
+                    if self.is_full(record_list) {
+                        return;
+                    }
+                }
+            }
+            if direction { // right
+                bucket = if bucket_.next == 0 {None} else {Some(th.get_bucket_(bucket_.next))};
+            }
+            else { // left
+                bucket = if bucket_.prev == 0 {None} else {Some(th.get_bucket_(bucket_.prev))};
+            }
+        }
+    }
+
+    fn scan_range(&self, th: &TableHandler, btree: &BTree, range_conds: &Vec<RangeCondition>, record_list: &mut RecordList) {
+        let (raw_index, direction, can_be_equal) = SelectNode::get_index(&btree.index_col, range_conds, &record_list.ty);
+        let end = SelectNode::get_end(&btree.index_col, range_conds, &record_list.ty, direction);
+        let bucket = btree.search_record_with_op(&raw_index, !direction, can_be_equal);
+        self.scan(th, bucket, direction, end, record_list);
+    }
+
+    // the conditions of a branch together with the ones every record has to satisfy anyway
+    fn merge_conds(&self, branch: &Vec<RangeCondition>) -> Vec<RangeCondition> {
+        let mut merged = self.range_conds.clone();
+        for cond in branch {
+            match merged.iter_mut().find(|merged_cond| merged_cond.col.col_name == cond.col.col_name) {
+                Some(merged_cond) => merged_cond.range = merged_cond.range.intersection(&cond.range),
+                None => merged.push(cond.clone()),
+            }
+        }
+        merged
+    }
+
+    // an OR or IN whose branches can all use more of an index than the plain range conditions
+    // is read as the union of the branch ranges, these are the branches with their btrees
+    fn union_btrees<'a, 'b>(&self, btrees: &'a Vec<BTree<'b>>, plain_used: usize, ty: &Vec<ColumnType>) -> Option<Vec<(Vec<RangeCondition>, &'a BTree<'b>)>> {
+        for cond in &self.expr_conds {
+            if cond.branches.len() == 0 {
+                continue;
+            }
+            let branches: Vec<Vec<RangeCondition>> = cond.branches.iter().map(|branch| self.merge_conds(branch)).collect();
+            let branch_btrees: Option<Vec<&BTree>> = branches.iter().map(|branch| {
+                SelectNode::best_btree(btrees, branch, ty).filter(|btree| {
+                    SelectNode::used_index_count(&btree.index_col, branch, ty) > plain_used &&
+                    SelectNode::get_index(&btree.index_col, branch, ty).0.index.len() > 0
+                })
+            }).collect();
+            if let Some(branch_btrees) = branch_btrees {
+                return Some(branches.into_iter().zip(branch_btrees.into_iter()).collect());
+            }
+        }
+        None
+    }
+
+    fn scan_union(&self, th: &TableHandler, btrees: &Vec<BTree>, plain_used: usize, record_list: &mut RecordList) -> bool {
+        if let Some(branches) = self.union_btrees(btrees, plain_used, &record_list.ty) {
+            let mut seen = HashSet::new();
+            for (branch, btree) in &branches {
+                let mut branch_list = RecordList {
+                    ty: record_list.ty.clone(),
+                    record: Vec::new(),
+                    ptrs: Vec::new(),
+                };
+                self.scan_range(th, btree, branch, &mut branch_list);
+                for (record, ptr) in branch_list.record.into_iter().zip(branch_list.ptrs.into_iter()) {
+                    if !self.is_full(record_list) && seen.insert(ptr.to_u64()) {
+                        record_list.record.push(record);
+                        record_list.ptrs.push(ptr);
+                    }
+                }
+            }
+            return true;
+        }
+        false
+    }
+
+    fn open_table(&self) -> TableHandler {
+        let mut path: PathBuf = [self.root_dir.clone(), self.database.clone(), self.table_list.clone().unwrap()].iter().collect();
+        path.set_extension("rua");
+        self.rm.borrow_mut().open_table(path.to_str().unwrap(), false)
+    }
+
+    fn index_name(btree: &BTree, ty: &Vec<ColumnType>) -> String {
+        let cols: Vec<&str> = btree.index_col.iter().map(|index| ty[*index as usize].name.as_str()).collect();
+        let name = if btree.is_primary() {
+            "primary key".to_owned()
+        }
+        else if btree.is_foreign() {
+            format!("foreign key {}", btree.get_foreign_constraint_name())
+        }
+        else {
+            format!("index {}", btree.index_name)
+        };
+        format!("{} ({})", name, cols.join(", "))
+    }
+
+    // how `query` reads the table, it makes the same choices
+    fn access_path(&self, btrees: &Vec<BTree>, ty: &Vec<ColumnType>) -> String {
+        if let Some((ref order_col, asc)) = self.order_index {
+            let btree = btrees.iter().find(|btree| &btree.index_col == order_col).unwrap();
+            return format!("ordered scan on {} {}", SelectNode::index_name(btree, ty), if asc {"asc"} else {"desc"});
+        }
+        let plain_btree = SelectNode::best_btree(btrees, &self.range_conds, ty);
+        let plain_used = plain_btree.map_or(0, |btree| SelectNode::used_index_count(&btree.index_col, &self.range_conds, ty));
+        if let Some(branches) = self.union_btrees(btrees, plain_used, ty) {
+            let scans: Vec<String> = branches.iter().map(|(branch, btree)| {
+                let bounds: Vec<String> = branch.iter().map(|cond| cond.name(ty)).filter(|bound| bound.len() > 0).collect();
+                format!("{} for {}", SelectNode::index_name(btree, ty), bounds.join(" and "))
+            }).collect();
+            return format!("union of index scans on {}", scans.join("; "));
+        }
+        match plain_btree {
+            Some(btree) => format!("index scan on {}", SelectNode::index_name(btree, ty)),
+            None => "full scan".to_owned(),
+        }
+    }
 }
 
 impl QueryNode for SelectNode {
@@ -362,59 +706,61 @@ impl QueryNode for SelectNode {
                 }
                 record_list
             }
+            None if self.view.is_some() => {
+                let view = self.view.as_ref().unwrap();
+                let tb_name = self.alias.clone().or(self.table_list.clone()).unwrap();
+                let mut record_list = RecordList {
+                    ty: view.ty.iter().enumerate().map(|(i, ct)| ColumnType { tb_name: tb_name.clone(), index: i as u32, ..ct.clone() }).collect(),
+                    record: Vec::new(),
+                    ptrs: Vec::new(),
+                };
+                for (record, ptr) in view.record.iter().zip(view.ptrs.iter()) {
+                    if self.is_full(&record_list) {
+                        break;
+                    }
+                    if self.is_valid(record, &record_list.ty) {
+                        record_list.record.push(record.clone());
+                        record_list.ptrs.push(*ptr);
+                    }
+                }
+                record_list
+            }
             None => {
-                let mut path: PathBuf = [self.root_dir.clone(), self.database.clone(), self.table_list.clone().unwrap()].iter().collect();
-                path.set_extension("rua");
-                let th = self.rm.borrow_mut().open_table(path.to_str().unwrap(), false);
+                let th = self.open_table();
                 let mut record_list = RecordList {
                     ty: th.get_column_types().cols,
                     record: Vec::new(),
                     ptrs: Vec::new(),
                 };
-
-                let btrees = th.get_btrees();
-                let mut best_btree: Option<&BTree> = None;
-                let mut max_used = 0;
-                for btree in &btrees {
-                    let used = self.used_index_count(&btree.index_col, &record_list.ty);
-                    if used > max_used {
-                        best_btree = Some(btree);
-                        max_used = used;
+                // columns of an aliased table are known by the alias
+                if let Some(ref alias) = self.alias {
+                    for ct in &mut record_list.ty {
+                        ct.tb_name = alias.clone();
                     }
                 }
-                if best_btree.is_none() {
-                    let btree = th.get_born_btree();
-                    let mut bucket = btree.first_bucket();
-                    while bucket.is_some() {
-                        let bucket_ = bucket.unwrap();
-                        for data in &bucket_.data {
-                            let record = th.get_record_(*data).0;
-                            if self.is_valid(&record, &record_list.ty) {
-                                record_list.record.push(record);
-                                record_list.ptrs.push(StrPointer::new(*data));
-                            }
-                        }
-                        bucket = if bucket_.next == 0 {None} else {Some(th.get_bucket_(bucket_.next))};
-                    }
+
+                let btrees = th.get_btrees();
+                if let Some((ref order_col, asc)) = self.order_index {
+                    let btree = btrees.iter().find(|btree| &btree.index_col == order_col).unwrap();
+                    let bucket = match self.get_order_index(&btree.index_col, &record_list.ty, asc) {
+                        Some((raw_index, can_be_equal)) => btree.search_record_with_op(&raw_index, !asc, can_be_equal),
+                        None => if asc {btree.first_bucket()} else {btree.last_bucket()},
+                    };
+                    self.scan(&th, bucket, asc, None, &mut record_list);
+                    th.close();
+                    return record_list;
                 }
-                else {
-                    let btree = best_btree.unwrap();
-                    let (raw_index, direction, can_be_equal) = self.get_index(&btree.index_col, &record_list.ty);
-                    let mut bucket = btree.search_record_with_op(&raw_index, !direction, can_be_equal);
-                    while bucket.is_some() {
-                        let bucket_ = bucket.unwrap();
-                        for data in &bucket_.data {
-                            let record = th.get_record_(*data).0;
-                            if self.is_valid(&record, &record_list.ty) {
-                                record_list.record.push(record);
-                                record_list.ptrs.push(StrPointer::new(*data));
-                            }
-                        }
-                        if direction { // right
-                            bucket = if bucket_.next == 0 {None} else {Some(th.get_bucket_(bucket_.next))};
+
+                let plain_btree = SelectNode::best_btree(&btrees, &self.range_conds, &record_list.ty);
+                let plain_used = plain_btree.map_or(0, |btree| SelectNode::used_index_count(&btree.index_col, &self.range_conds, &record_list.ty));
+                if !self.scan_union(&th, &btrees, plain_used, &mut record_list) {
+                    match plain_btree {
+                        Some(btree) => {
+                            self.scan_range(&th, btree, &self.range_conds, &mut record_list);
                         }
-                        else { // left
-                            bucket = if bucket_.prev == 0 {None} else {Some(th.get_bucket_(bucket_.prev))};
+                        None => {
+                            let btree = th.get_born_btree();
+                            self.scan(&th, btree.first_bucket(), true, None, &mut record_list);
                         }
                     }
                 }
@@ -423,151 +769,988 @@ impl QueryNode for SelectNode {
             }
         }
     }
+
+    fn get_detail(&self) -> String {
+        let mut detail = Vec::new();
+        let mut ty = Vec::new();
+        if let Some(ref tb_name) = self.table_list {
+            let table = match self.alias {
+                Some(ref alias) => format!("{} as {}", tb_name, alias),
+                None => tb_name.clone(),
+            };
+            match self.view {
+                Some(ref view) => {
+                    ty = view.ty.clone();
+                    detail.push(format!("view {}", table));
+                },
+                None => {
+                    let th = self.open_table();
+                    ty = th.get_column_types().cols;
+                    detail.push(format!("table {}", table));
+                    detail.push(self.access_path(&th.get_btrees(), &ty));
+                    th.close();
+                },
+            }
+        }
+        let conds: Vec<String> = self.range_conds.iter().map(|cond| cond.name(&ty))
+            .chain(self.null_conds.iter().map(|cond| cond.name()))
+            .chain(self.pair_conds.iter().map(|cond| cond.name()))
+            .chain(self.expr_conds.iter().map(|cond| match cond.clause {
+                ast::WhereClause::Or(_) => format!("({})", cond.clause.name()),
+                _ => cond.clause.name(),
+            }))
+            .filter(|cond| cond.len() > 0).collect();
+        if conds.len() > 0 {
+            detail.push(format!("where {}", conds.join(" and ")));
+        }
+        if let Some(limit) = self.limit {
+            detail.push(format!("stop after {} rows", limit));
+        }
+        detail.join(", ")
+    }
+
+    fn get_sons(&self) -> Vec<&dyn QueryNode> {
+        self.son.iter().map(|son| son.as_ref()).collect()
+    }
 }
 
-struct ProjectNode {
+struct LimitNode {
     pub son: Box<dyn QueryNode>,
-    pub cols: Vec<ast::Column>,
+    pub limit: usize,
+    pub offset: usize,
 }
 
-impl QueryNode for ProjectNode {
+impl QueryNode for LimitNode {
     fn get_name(&self) -> &str {
-        "project_node"
+        "limit_node"
     }
 
     fn query(&self) -> RecordList {
-        if self.cols.len() == 0 {
-            self.son.query()
-        }
-        else {
-            let record_list = self.son.query();
-            let mut sub_cols = Vec::new();
-            for i in 0..record_list.ty.len() {
-                for col in &self.cols {
-                    if record_list.ty[i].match_(col) {
-                        sub_cols.push(i);
-                    }
-                }
-            }
-            record_list.sub_record_list(&sub_cols)
+        let record_list = self.son.query();
+        RecordList {
+            ty: record_list.ty,
+            record: record_list.record.into_iter().skip(self.offset).take(self.limit).collect(),
+            ptrs: record_list.ptrs.into_iter().skip(self.offset).take(self.limit).collect(),
         }
     }
+
+    fn get_detail(&self) -> String {
+        format!("limit {} offset {}", self.limit, self.offset)
+    }
+
+    fn get_sons(&self) -> Vec<&dyn QueryNode> {
+        vec![self.son.as_ref()]
+    }
 }
 
-struct ProductNode {
-    pub son: Vec<Box<dyn QueryNode>>,
+fn record_key(record: &Record) -> Vec<Option<Data>> {
+    record.cols.iter().map(|col| col.data.clone()).collect()
 }
 
-impl ProductNode {
-    fn concat(records: &Vec<RecordList>, pos: usize, current_record: &mut Record, result: &mut RecordList) {
-        if pos == records.len() {
-            result.record.push(current_record.clone());
-            result.ptrs.push(StrPointer::new(0));
-            return;
-        }
-        let record_list = &records[pos];
-        for r in &record_list.record {
-            for col in &r.cols {
-                current_record.cols.push(col.clone());
-            }
-            ProductNode::concat(records, pos + 1, current_record, result);
-            for _ in &r.cols {
-                current_record.cols.pop();
+// keeps the first of the equal records, NULLs are equal to each other here
+struct DistinctNode {
+    pub son: Box<dyn QueryNode>,
+}
+
+impl DistinctNode {
+    fn dedup(record_list: RecordList) -> RecordList {
+        let mut seen = HashSet::new();
+        let mut result = RecordList {
+            ty: record_list.ty,
+            record: Vec::new(),
+            ptrs: Vec::new(),
+        };
+        for (record, ptr) in record_list.record.into_iter().zip(record_list.ptrs.into_iter()) {
+            if seen.insert(record_key(&record)) {
+                result.record.push(record);
+                result.ptrs.push(ptr);
             }
         }
+        result
     }
 }
 
-impl QueryNode for ProductNode {
+impl QueryNode for DistinctNode {
     fn get_name(&self) -> &str {
-        "product_name"
+        "distinct_node"
     }
 
     fn query(&self) -> RecordList {
-        let mut record_lists: Vec<RecordList> = self.son.iter().map(|node| node.query()).collect();
-        if record_lists.len() == 1 {
-            record_lists.pop().unwrap()
-        }
-        else {
-            let mut ty = Vec::new();
-            for record_list in &record_lists {
-                for t in &record_list.ty {
-                    ty.push(t.clone());
-                }
-            }
-            let mut result = RecordList {
-                ty: ty,
-                record: Vec::new(),
-                ptrs: Vec::new(),
-            };
-            ProductNode::concat(&record_lists, 0, &mut Record{ cols: Vec::new(), }, &mut result);
-            result
-        }
+        DistinctNode::dedup(self.son.query())
+    }
+
+    fn get_sons(&self) -> Vec<&dyn QueryNode> {
+        vec![self.son.as_ref()]
     }
 }
 
-pub struct QueryTree {
-    root_dir: String,
-    database: String,
-    rm: Rc<RefCell<RecordManager>>,
-    root: Option<Box<dyn QueryNode>>,
+// the columns are named after the left select, ALL keeps as many copies as the multisets would
+struct SetOpNode {
+    pub op: ast::SetOp,
+    pub all: bool,
+    pub left: QueryTree,
+    pub right: QueryTree,
 }
 
-impl QueryTree {
-    pub fn new(root_dir: &String, database_dir: &String, rm: Rc<RefCell<RecordManager>>) -> Self {
-        Self {
-            root_dir: root_dir.clone(),
-            database: database_dir.clone(),
-            rm: rm.clone(),
-            root: None,
-        }
+impl QueryNode for SetOpNode {
+    fn get_name(&self) -> &str {
+        "set_op_node"
     }
-    
-    pub fn get_column_type(&self, name_cols: &Vec<(String, HashMap<String, ColumnType>)>, cond: &ast::Column) -> ColumnType {
-        assert!(cond.tb_name.is_some());
-        let cond_tb_name = cond.tb_name.as_ref().unwrap();
-        for pair in name_cols {
-            let tb_name = &pair.0;
-            let hashmap = &pair.1;
-            if tb_name == cond_tb_name {
-                return hashmap.get(&cond.col_name).unwrap().clone()
+
+    fn query(&self) -> RecordList {
+        let left = self.left.query();
+        let right = self.right.query();
+        let mut result = RecordList {
+            ty: left.ty,
+            record: Vec::new(),
+            ptrs: Vec::new(),
+        };
+        if self.op == ast::SetOp::Union {
+            for (record, ptr) in left.record.into_iter().zip(left.ptrs.into_iter()).chain(right.record.into_iter().zip(right.ptrs.into_iter())) {
+                result.record.push(record);
+                result.ptrs.push(ptr);
+            }
+            if self.all {
+                return result;
             }
+            return DistinctNode::dedup(result);
         }
-        unreachable!()
-    }
-
-    pub fn get_table_name(&self, name_cols: &Vec<(String, HashMap<String, ColumnType>)>, cond: &ast::Column) -> String {
-        if cond.tb_name.is_some() {
-            return cond.tb_name.as_ref().unwrap().clone();
+        let mut counts: HashMap<Vec<Option<Data>>, usize> = HashMap::new();
+        for record in &right.record {
+            *counts.entry(record_key(record)).or_insert(0) += 1;
         }
-        for pair in name_cols {
-            let tb_name = &pair.0;
-            let hashmap = &pair.1;
-            if hashmap.get(&cond.col_name).is_some() {
-                return tb_name.clone();
+        let mut seen = HashSet::new();
+        for (record, ptr) in left.record.into_iter().zip(left.ptrs.into_iter()) {
+            let key = record_key(&record);
+            let count = counts.get_mut(&key);
+            let found = count.as_ref().map_or(false, |count| **count > 0);
+            let keep = match self.op {
+                ast::SetOp::Intersect => found,
+                _ => !found,
+            };
+            if self.all {
+                if found {
+                    *count.unwrap() -= 1;
+                }
+            }
+            else if !seen.insert(key) {
+                continue;
+            }
+            if keep {
+                result.record.push(record);
+                result.ptrs.push(ptr);
             }
         }
-        unreachable!()
+        result
     }
 
-    fn create_conds(&self, table_list: &Vec<ast::Name>, where_clause: &Option<Vec<ast::WhereClause>>, range_conds: &mut Vec<RangeCondition>, null_conds: &mut Vec<NullCondition>, pair_conds: &mut Vec<PairCondition>) {
-        let name_cols: Vec<(String, HashMap<String, ColumnType>)> = table_list.iter()
-                        .map(|tb_name| {
-                            let mut path: PathBuf = [self.root_dir.clone(), self.database.clone(), tb_name.clone()].iter().collect();
-                            path.set_extension("rua");
-                            let th = self.rm.borrow_mut().open_table(path.to_str().unwrap(), false);
-                            let map = th.get_column_types_as_hashmap();
-                            th.close();
-                            (tb_name.clone(), map)
-                        }).collect();
-        match where_clause {
-            Some(ref conds) => {
-                for cond in conds {
-                    match cond {
-                        ast::WhereClause::IsAssert{col, null} => { // null
-                            let col = ast::Column {
-                                tb_name: if col.tb_name.is_none() {Some(self.get_table_name(&name_cols, col))} else {col.tb_name.clone()},
-                                col_name: col.col_name.clone(),
+    fn get_detail(&self) -> String {
+        let op = match self.op {
+            ast::SetOp::Union => "union",
+            ast::SetOp::Intersect => "intersect",
+            ast::SetOp::Except => "except",
+        };
+        if self.all {format!("{} all", op)} else {op.to_owned()}
+    }
+
+    fn get_sons(&self) -> Vec<&dyn QueryNode> {
+        self.left.root.iter().chain(self.right.root.iter()).map(|root| root.as_ref()).collect()
+    }
+}
+
+struct ProjectNode {
+    pub son: Box<dyn QueryNode>,
+    pub items: Vec<ast::SelectItem>, // columns and expressions
+    pub sequences: Rc<RefCell<Sequences>>,
+    pub error: Rc<RefCell<Option<String>>>,
+}
+
+impl QueryNode for ProjectNode {
+    fn get_name(&self) -> &str {
+        "project_node"
+    }
+
+    fn query(&self) -> RecordList {
+        let record_list = self.son.query();
+        let ty = &record_list.ty;
+        let sub_cols: Option<Vec<usize>> = self.items.iter().map(|item| match item {
+            ast::SelectItem::Column(col) => ty.iter().position(|ct| ct.match_(col)),
+            _ => None,
+        }).collect();
+        if let Some(sub_cols) = sub_cols {
+            return record_list.sub_record_list(&sub_cols);
+        }
+        let new_ty: Vec<ColumnType> = self.items.iter().map(|item| match item {
+            ast::SelectItem::Column(col) => ty.iter().find(|ct| ct.match_(col)).unwrap().clone(),
+            ast::SelectItem::Expr(expr) => ColumnType::from_expr(expr, ty),
+            ast::SelectItem::Aggregate(_) => unreachable!(),
+        }).collect();
+        let record = record_list.record.iter().map(|record| {
+            Record::from_data(self.items.iter().map(|item| match item {
+                ast::SelectItem::Column(col) => record.get_match_data(col, ty).0,
+                // nextval moves on once for every row
+                ast::SelectItem::Expr(expr) if expr.has_sequence() => match self.sequences.borrow_mut().resolve(expr) {
+                    Ok(expr) => record.eval(&expr, ty),
+                    Err(message) => {
+                        *self.error.borrow_mut() = Some(message);
+                        None
+                    },
+                },
+                ast::SelectItem::Expr(expr) => record.eval(expr, ty),
+                ast::SelectItem::Aggregate(_) => unreachable!(),
+            }).collect(), &new_ty)
+        }).collect();
+        RecordList {
+            ty: new_ty,
+            record: record,
+            ptrs: record_list.ptrs,
+        }
+    }
+
+    fn get_detail(&self) -> String {
+        self.items.iter().map(|item| item.name()).collect::<Vec<String>>().join(", ")
+    }
+
+    fn get_sons(&self) -> Vec<&dyn QueryNode> {
+        vec![self.son.as_ref()]
+    }
+}
+
+struct SortNode {
+    pub son: Box<dyn QueryNode>,
+    pub order: Vec<ast::OrderClause>,
+}
+
+impl SortNode {
+    fn compare(l: &Option<Data>, r: &Option<Data>) -> Ordering {
+        match (l, r) {
+            (None, None) => Ordering::Equal,
+            (None, Some(_)) => Ordering::Less,
+            (Some(_), None) => Ordering::Greater,
+            (Some(l), Some(r)) => l.partial_cmp(r).unwrap_or(Ordering::Equal),
+        }
+    }
+}
+
+impl QueryNode for SortNode {
+    fn get_name(&self) -> &str {
+        "sort_node"
+    }
+
+    fn query(&self) -> RecordList {
+        let record_list = self.son.query();
+        let keys: Vec<Vec<Option<Data>>> = record_list.record.iter().map(
+            |record| self.order.iter().map(|o| record.get_match_data(&o.col, &record_list.ty).0).collect()
+        ).collect();
+        let mut pos: Vec<usize> = (0..record_list.record.len()).collect();
+        pos.sort_by(|&a, &b| {
+            for (i, o) in self.order.iter().enumerate() {
+                let res = SortNode::compare(&keys[a][i], &keys[b][i]);
+                if res != Ordering::Equal {
+                    return if o.asc {res} else {res.reverse()};
+                }
+            }
+            Ordering::Equal
+        });
+        RecordList {
+            record: pos.iter().map(|&i| record_list.record[i].clone()).collect(),
+            ptrs: pos.iter().map(|&i| record_list.ptrs[i]).collect(),
+            ty: record_list.ty,
+        }
+    }
+
+    fn get_detail(&self) -> String {
+        let order: Vec<String> = self.order.iter().map(|o| format!("{} {}", o.col.name(), if o.asc {"asc"} else {"desc"})).collect();
+        format!("order by {}", order.join(", "))
+    }
+
+    fn get_sons(&self) -> Vec<&dyn QueryNode> {
+        vec![self.son.as_ref()]
+    }
+}
+
+struct Accumulator {
+    pub aggr: ast::Aggregate,
+    pub count: i64,
+    pub value: Option<Data>,
+    // a SUM or AVG that went out of the range of its type, it has no result
+    pub overflow: bool,
+}
+
+impl Accumulator {
+    fn new(aggr: &ast::Aggregate) -> Self {
+        Self {
+            aggr: aggr.clone(),
+            count: 0,
+            value: None,
+            overflow: false,
+        }
+    }
+
+    fn add(l: Data, r: Data) -> Option<Data> {
+        match (l, r) {
+            (Data::Int(l), Data::Int(r)) => l.checked_add(r).map(Data::Int),
+            (Data::Float(l), Data::Float(r)) => Some(Data::Float(l + r)),
+            (Data::Numeric(l), Data::Numeric(r)) => l.checked_add(r).map(Data::Numeric),
+            _ => unreachable!(),
+        }
+    }
+
+    fn update(&mut self, record: &Record, ty: &Vec<ColumnType>) {
+        if self.overflow {
+            return;
+        }
+        let data = match self.aggr.col {
+            Some(ref col) => record.get_match_data(col, ty).0,
+            None => { // count(*)
+                self.count += 1;
+                return;
+            }
+        };
+        if let Some(data) = data {
+            self.count += 1;
+            self.value = Some(match (self.value.take(), &self.aggr.func) {
+                (None, _) => data,
+                (Some(value), ast::AggrFunc::Sum) | (Some(value), ast::AggrFunc::Avg) => match Accumulator::add(value, data) {
+                    Some(sum) => sum,
+                    None => {
+                        self.overflow = true;
+                        return;
+                    },
+                },
+                (Some(value), ast::AggrFunc::Min) => if data < value {data} else {value},
+                (Some(value), ast::AggrFunc::Max) => if data > value {data} else {value},
+                (Some(value), ast::AggrFunc::Count) => value,
+            });
+        }
+    }
+
+    fn result(&self) -> Option<Data> {
+        if self.overflow {
+            return None;
+        }
+        match self.aggr.func {
+            ast::AggrFunc::Count => Some(Data::Int(self.count)),
+            ast::AggrFunc::Avg => match self.value {
+                Some(Data::Int(sum)) => Some(Data::Float(sum as f64 / self.count as f64)),
+                Some(Data::Float(sum)) => Some(Data::Float(sum / self.count as f64)),
+                Some(Data::Numeric(sum)) => { // keep the scale of the column, rounding half away from zero
+                    let (quot, rem) = (sum / self.count, sum % self.count);
+                    Some(Data::Numeric(if 2 * rem.abs() >= self.count {quot + sum.signum()} else {quot}))
+                },
+                _ => None,
+            },
+            _ => self.value.clone(),
+        }
+    }
+}
+
+struct AggregateNode {
+    pub son: Box<dyn QueryNode>,
+    pub group_by: Vec<ast::Column>,
+    pub items: Vec<ast::SelectItem>,
+    pub having: Vec<ast::HavingClause>,
+    pub error: Rc<RefCell<Option<String>>>,
+}
+
+impl AggregateNode {
+    fn get_column_type(item: &ast::SelectItem, ty: &Vec<ColumnType>) -> ColumnType {
+        match item {
+            ast::SelectItem::Column(col) => ty.iter().find(|ct| ct.match_(col)).unwrap().clone(),
+            ast::SelectItem::Aggregate(aggr) => ColumnType::from_aggregate(aggr, aggr.col.as_ref().map(|col| ty.iter().find(|ct| ct.match_(col)).unwrap())),
+            ast::SelectItem::Expr(expr) => ColumnType::from_expr(expr, ty),
+        }
+    }
+
+    fn get_data(item: &ast::SelectItem, first: Option<&Record>, ty: &Vec<ColumnType>, accs: &Vec<Accumulator>) -> Option<Data> {
+        match item {
+            ast::SelectItem::Column(col) => first.and_then(|record| record.get_match_data(col, ty).0),
+            ast::SelectItem::Aggregate(aggr) => accs.iter().find(|acc| &acc.aggr == aggr).unwrap().result(),
+            ast::SelectItem::Expr(expr) => first.and_then(|record| record.eval(expr, ty)),
+        }
+    }
+
+    fn is_valid(&self, first: Option<&Record>, ty: &Vec<ColumnType>, accs: &Vec<Accumulator>) -> bool {
+        self.having.iter().all(|having| {
+            let l_data = AggregateNode::get_data(&having.item, first, ty, accs);
+            let ct = AggregateNode::get_column_type(&having.item, ty);
+            let res = match (l_data, &having.expr) {
+                (None, _) => None,
+                (Some(l_data), ast::Expr::Value(value)) => compare_value(&having.op, l_data, &ct, value),
+                (Some(l_data), expr) => first.and_then(|record| record.eval(expr, ty)).and_then(|r_data| {
+                    compare_typed(&having.op, l_data, &ct, r_data, &ColumnType::from_expr(expr, ty))
+                }),
+            };
+            res == Some(true)
+        })
+    }
+}
+
+impl QueryNode for AggregateNode {
+    fn get_name(&self) -> &str {
+        "aggregate_node"
+    }
+
+    fn query(&self) -> RecordList {
+        let record_list = self.son.query();
+        let ty = &record_list.ty;
+        let mut aggrs: Vec<&ast::Aggregate> = Vec::new();
+        for item in self.items.iter().chain(self.having.iter().map(|having| &having.item)) {
+            if let ast::SelectItem::Aggregate(aggr) = item {
+                if !aggrs.contains(&aggr) {
+                    aggrs.push(aggr);
+                }
+            }
+        }
+
+        let mut groups: HashMap<Vec<Option<Data>>, usize> = HashMap::new();
+        let mut firsts: Vec<Option<&Record>> = Vec::new();
+        let mut accs: Vec<Vec<Accumulator>> = Vec::new();
+        for record in &record_list.record {
+            let key: Vec<Option<Data>> = self.group_by.iter().map(|col| record.get_match_data(col, ty).0).collect();
+            let len = firsts.len();
+            let pos = *groups.entry(key).or_insert(len);
+            if pos == len {
+                firsts.push(Some(record));
+                accs.push(aggrs.iter().map(|aggr| Accumulator::new(aggr)).collect());
+            }
+            for acc in accs[pos].iter_mut() {
+                acc.update(record, ty);
+            }
+        }
+        // without GROUP BY the whole input is a single group, even if it is empty
+        if self.group_by.len() == 0 && firsts.len() == 0 {
+            firsts.push(None);
+            accs.push(aggrs.iter().map(|aggr| Accumulator::new(aggr)).collect());
+        }
+        if let Some(acc) = accs.iter().flatten().find(|acc| acc.overflow) {
+            *self.error.borrow_mut() = Some(format!("{} is out of range", acc.aggr.name()));
+        }
+
+        let mut result = RecordList {
+            ty: self.items.iter().map(|item| AggregateNode::get_column_type(item, ty)).collect(),
+            record: Vec::new(),
+            ptrs: Vec::new(),
+        };
+        for (first, accs) in firsts.iter().zip(accs.iter()) {
+            if !self.is_valid(*first, ty, accs) {
+                continue;
+            }
+            let mut cols = Vec::new();
+            for (i, item) in self.items.iter().enumerate() {
+                cols.push(ColumnData {
+                    index: i as u32,
+                    default: false,
+                    flags: match &result.ty[i].data_type {
+                        &Type::Str(_) => 0,
+                        &Type::Int(_) => 1,
+                        &Type::Float(_) => 2,
+                        &Type::Date(_) => 3,
+                        &Type::Numeric(_) => 4,
+                    },
+                    data: AggregateNode::get_data(item, *first, ty, accs),
+                });
+            }
+            result.record.push(Record { cols: cols });
+            result.ptrs.push(StrPointer::new(0));
+        }
+        result
+    }
+
+    fn get_detail(&self) -> String {
+        let mut detail = vec![self.items.iter().map(|item| item.name()).collect::<Vec<String>>().join(", ")];
+        if self.group_by.len() > 0 {
+            detail.push(format!("group by {}", self.group_by.iter().map(|col| col.name()).collect::<Vec<String>>().join(", ")));
+        }
+        if self.having.len() > 0 {
+            let having: Vec<String> = self.having.iter().map(|having| format!("{} {} {}", having.item.name(), having.op.symbol(), having.expr.name())).collect();
+            detail.push(format!("having {}", having.join(" and ")));
+        }
+        detail.join(", ")
+    }
+
+    fn get_sons(&self) -> Vec<&dyn QueryNode> {
+        vec![self.son.as_ref()]
+    }
+}
+
+// count(*) over a whole table, answered by the sizes of the buckets in the born btree
+struct CountNode {
+    pub root_dir: String,
+    pub database: String,
+    pub rm: Rc<RefCell<RecordManager>>,
+    pub tb_name: ast::Name,
+    pub items: Vec<ast::SelectItem>,
+}
+
+impl QueryNode for CountNode {
+    fn get_name(&self) -> &str {
+        "count_node"
+    }
+
+    fn query(&self) -> RecordList {
+        let mut path: PathBuf = [self.root_dir.clone(), self.database.clone(), self.tb_name.clone()].iter().collect();
+        path.set_extension("rua");
+        let th = self.rm.borrow_mut().open_table(path.to_str().unwrap(), false);
+        let mut count = 0;
+        let mut bucket = th.get_born_btree().first_bucket();
+        while bucket.is_some() {
+            let bucket_ = bucket.unwrap();
+            count += bucket_.data.len() as i64;
+            bucket = if bucket_.next == 0 {None} else {Some(th.get_bucket_(bucket_.next))};
+        }
+        th.close();
+
+        let mut record_list = RecordList::new();
+        let mut cols = Vec::new();
+        for (i, item) in self.items.iter().enumerate() {
+            if let ast::SelectItem::Aggregate(aggr) = item {
+                record_list.ty.push(ColumnType::from_aggregate(aggr, None));
+                cols.push(ColumnData {
+                    index: i as u32,
+                    default: false,
+                    flags: 1,
+                    data: Some(Data::Int(count)),
+                });
+            }
+        }
+        record_list.record.push(Record { cols: cols });
+        record_list.ptrs.push(StrPointer::new(0));
+        record_list
+    }
+
+    fn get_detail(&self) -> String {
+        format!("table {}, counted from the born btree", self.tb_name)
+    }
+}
+
+struct ProductNode {
+    pub son: Vec<Box<dyn QueryNode>>,
+}
+
+impl ProductNode {
+    fn concat(records: &Vec<RecordList>, pos: usize, current_record: &mut Record, result: &mut RecordList) {
+        if pos == records.len() {
+            result.record.push(current_record.clone());
+            result.ptrs.push(StrPointer::new(0));
+            return;
+        }
+        let record_list = &records[pos];
+        for r in &record_list.record {
+            for col in &r.cols {
+                current_record.cols.push(col.clone());
+            }
+            ProductNode::concat(records, pos + 1, current_record, result);
+            for _ in &r.cols {
+                current_record.cols.pop();
+            }
+        }
+    }
+}
+
+impl QueryNode for ProductNode {
+    fn get_name(&self) -> &str {
+        "product_node"
+    }
+
+    fn query(&self) -> RecordList {
+        let mut record_lists: Vec<RecordList> = self.son.iter().map(|node| node.query()).collect();
+        if record_lists.len() == 1 {
+            record_lists.pop().unwrap()
+        }
+        else {
+            let mut ty = Vec::new();
+            for record_list in &record_lists {
+                for t in &record_list.ty {
+                    ty.push(t.clone());
+                }
+            }
+            let mut result = RecordList {
+                ty: ty,
+                record: Vec::new(),
+                ptrs: Vec::new(),
+            };
+            ProductNode::concat(&record_lists, 0, &mut Record{ cols: Vec::new(), }, &mut result);
+            result
+        }
+    }
+
+    fn get_sons(&self) -> Vec<&dyn QueryNode> {
+        self.son.iter().map(|son| son.as_ref()).collect()
+    }
+}
+
+// joins every record of `left` with the records of `right` that satisfy the conditions,
+// an outer join keeps the unmatched records of one side with NULL for the other
+struct JoinNode {
+    pub root_dir: String,
+    pub database: String,
+    pub rm: Rc<RefCell<RecordManager>>,
+    pub left: Box<dyn QueryNode>,
+    pub right: Box<dyn QueryNode>,
+    pub kind: ast::JoinKind,
+    pub conds: Vec<ExprCondition>,
+}
+
+impl QueryNode for JoinNode {
+    fn get_name(&self) -> &str {
+        "join_node"
+    }
+
+    fn query(&self) -> RecordList {
+        let left = self.left.query();
+        let right = self.right.query();
+        let mut result = RecordList {
+            ty: left.ty.iter().chain(right.ty.iter()).cloned().collect(),
+            record: Vec::new(),
+            ptrs: Vec::new(),
+        };
+        let tree = QueryTree::new(&self.root_dir, &self.database, self.rm.clone());
+        let concat = |l: &Record, r: &Record| Record {
+            cols: l.cols.iter().chain(r.cols.iter()).cloned().collect(),
+        };
+        let left_null = Record::from_data(vec![None; left.ty.len()], &left.ty);
+        let right_null = Record::from_data(vec![None; right.ty.len()], &right.ty);
+        let mut right_matched = vec![false; right.record.len()];
+        for l in &left.record {
+            let mut matched = false;
+            for (i, r) in right.record.iter().enumerate() {
+                let record = concat(l, r);
+                if self.conds.iter().all(|cond| cond.match_(&record, &result.ty, &tree)) {
+                    matched = true;
+                    right_matched[i] = true;
+                    result.record.push(record);
+                    result.ptrs.push(StrPointer::new(0));
+                }
+            }
+            if !matched && self.kind == ast::JoinKind::Left {
+                result.record.push(concat(l, &right_null));
+                result.ptrs.push(StrPointer::new(0));
+            }
+        }
+        if self.kind == ast::JoinKind::Right {
+            for (r, matched) in right.record.iter().zip(right_matched.into_iter()) {
+                if !matched {
+                    result.record.push(concat(&left_null, r));
+                    result.ptrs.push(StrPointer::new(0));
+                }
+            }
+        }
+        result
+    }
+
+    fn get_detail(&self) -> String {
+        let kind = match self.kind {
+            ast::JoinKind::Inner => "inner join",
+            ast::JoinKind::Left => "left join",
+            ast::JoinKind::Right => "right join",
+        };
+        match self.conds.len() {
+            0 => kind.to_owned(),
+            _ => format!("{} on {}", kind, ast::WhereClause::And(self.conds.iter().map(|cond| cond.clause.clone()).collect()).name()),
+        }
+    }
+
+    fn get_sons(&self) -> Vec<&dyn QueryNode> {
+        vec![self.left.as_ref(), self.right.as_ref()]
+    }
+}
+
+// wraps every node of EXPLAIN ANALYZE, counting the records the node gives and the time spent in it and its sons
+struct ProfileNode {
+    pub son: Box<dyn QueryNode>,
+    pub rows: Cell<usize>,
+    pub time: Cell<Duration>,
+}
+
+impl QueryNode for ProfileNode {
+    fn get_name(&self) -> &str {
+        self.son.get_name()
+    }
+
+    fn query(&self) -> RecordList {
+        let start = Instant::now();
+        let record_list = self.son.query();
+        self.rows.set(self.rows.get() + record_list.record.len());
+        self.time.set(self.time.get() + start.elapsed());
+        record_list
+    }
+
+    fn get_detail(&self) -> String {
+        let stats = format!("actual rows={} time={:.3}ms", self.rows.get(), self.time.get().as_secs_f64() * 1000.0);
+        match self.son.get_detail() {
+            ref detail if detail.len() == 0 => stats,
+            detail => format!("{}, {}", detail, stats),
+        }
+    }
+
+    fn get_sons(&self) -> Vec<&dyn QueryNode> {
+        self.son.get_sons()
+    }
+}
+
+pub struct QueryTree {
+    root_dir: String,
+    database: String,
+    rm: Rc<RefCell<RecordManager>>,
+    root: Option<Box<dyn QueryNode>>,
+    group_clause: Option<Vec<ast::Column>>,
+    having_clause: Vec<ast::HavingClause>,
+    order_clause: Vec<ast::OrderClause>,
+    limit_clause: Option<ast::LimitClause>,
+    join_list: Vec<ast::Join>,
+    aliases: Vec<ast::TableRef>,
+    item_aliases: Vec<Option<ast::Name>>,
+    distinct: bool,
+    analyze: bool,
+    views: RefCell<HashMap<ast::Name, Rc<RecordList>>>,
+    // what went wrong while the tree ran, its subtrees share it
+    error: Rc<RefCell<Option<String>>>,
+    // the numbers nextval has taken, kept until `store_sequences`, its subtrees share them as well
+    sequences: Rc<RefCell<Sequences>>,
+}
+
+impl QueryTree {
+    pub fn new(root_dir: &String, database_dir: &String, rm: Rc<RefCell<RecordManager>>) -> Self {
+        Self {
+            root_dir: root_dir.clone(),
+            database: database_dir.clone(),
+            rm: rm.clone(),
+            root: None,
+            group_clause: None,
+            having_clause: Vec::new(),
+            order_clause: Vec::new(),
+            limit_clause: None,
+            join_list: Vec::new(),
+            aliases: Vec::new(),
+            item_aliases: Vec::new(),
+            distinct: false,
+            analyze: false,
+            views: RefCell::new(HashMap::new()),
+            error: Rc::new(RefCell::new(None)),
+            sequences: Rc::new(RefCell::new(Sequences::new(root_dir, database_dir))),
+        }
+    }
+
+    pub fn set_group(&mut self, group_clause: &Option<Vec<ast::Column>>, having_clause: &Option<Vec<ast::HavingClause>>) {
+        self.group_clause = group_clause.clone();
+        self.having_clause = having_clause.clone().unwrap_or(Vec::new());
+    }
+
+    fn is_aggregated(&self, selector: &ast::Selector) -> bool {
+        self.group_clause.is_some() || self.having_clause.len() > 0 || selector.has_aggregate()
+    }
+
+    pub fn set_order(&mut self, order_clause: &Option<Vec<ast::OrderClause>>) {
+        self.order_clause = order_clause.clone().unwrap_or(Vec::new());
+    }
+
+    pub fn set_limit(&mut self, limit_clause: &Option<ast::LimitClause>) {
+        self.limit_clause = limit_clause.clone();
+    }
+
+    pub fn set_join(&mut self, join_list: &Vec<ast::Join>) {
+        self.join_list = join_list.clone();
+    }
+
+    pub fn set_distinct(&mut self, distinct: bool) {
+        self.distinct = distinct;
+    }
+
+    // every node records what it produced, for EXPLAIN ANALYZE
+    pub fn set_analyze(&mut self, analyze: bool) {
+        self.analyze = analyze;
+    }
+
+    pub fn set_alias(&mut self, table_list: &Vec<ast::TableRef>) {
+        self.aliases = table_list.clone();
+    }
+
+    // the table behind a name in the from list, which may be an alias
+    fn table_ref(&self, name: &ast::Name) -> ast::TableRef {
+        match self.aliases.iter().find(|table| table.name() == name) {
+            Some(table) => table.clone(),
+            None => ast::TableRef {
+                tb_name: name.clone(),
+                alias: None,
+            },
+        }
+    }
+
+    // how every table is joined to the tables before it in its from item, None starts a new from item;
+    // USING and NATURAL compare the common columns, whose second copy is left out of SELECT *
+    fn join_steps(&self, table_list: &Vec<ast::Name>, name_cols: &Vec<(String, HashMap<String, ColumnType>)>) -> (Vec<Option<(ast::JoinKind, Vec<ast::WhereClause>)>>, Vec<ast::Column>) {
+        let qualify = |tb_name: &String, col_name: &String| ast::Column {
+            tb_name: Some(tb_name.clone()),
+            col_name: col_name.clone(),
+        };
+        let mut steps = Vec::new();
+        let mut hidden = Vec::new();
+        let mut start = 0;
+        for i in 0..table_list.len() {
+            let join = match self.join_list.iter().find(|join| join.tb_name == table_list[i]) {
+                Some(join) => join,
+                None => {
+                    start = i;
+                    steps.push(None);
+                    continue;
+                }
+            };
+            let names: Vec<String> = match join.cond {
+                Some(ast::JoinCondition::Using(ref names)) => names.clone(),
+                Some(ast::JoinCondition::Natural) => {
+                    let mut cts: Vec<&ColumnType> = name_cols[i].1.values()
+                        .filter(|ct| name_cols[start..i].iter().any(|(_, cols)| cols.contains_key(&ct.name)))
+                        .collect();
+                    cts.sort_by_key(|ct| ct.index);
+                    cts.iter().map(|ct| ct.name.clone()).collect()
+                },
+                _ => Vec::new(),
+            };
+            let mut clauses = match join.cond {
+                Some(ast::JoinCondition::On(ref clauses)) => clauses.clone(),
+                _ => Vec::new(),
+            };
+            for name in &names {
+                let (left_tb, _) = name_cols[start..i].iter().find(|(_, cols)| cols.contains_key(name)).unwrap();
+                let (l, r) = (qualify(left_tb, name), qualify(&table_list[i], name));
+                hidden.push(if join.kind == ast::JoinKind::Right {l.clone()} else {r.clone()});
+                clauses.push(ast::WhereClause::Comparison {
+                    col: l,
+                    op: ast::Op::Equal,
+                    expr: ast::Expr::Column(r),
+                });
+            }
+            steps.push(Some((join.kind.clone(), clauses)));
+        }
+        (steps, hidden)
+    }
+
+    // the tables that an outer join may fill with NULL
+    fn padded_tables(steps: &Vec<Option<(ast::JoinKind, Vec<ast::WhereClause>)>>) -> Vec<bool> {
+        let mut padded = vec![false; steps.len()];
+        let mut start = 0;
+        for (i, step) in steps.iter().enumerate() {
+            match step {
+                None => start = i,
+                Some((ast::JoinKind::Left, _)) => padded[i] = true,
+                Some((ast::JoinKind::Right, _)) => for j in start..i {
+                    padded[j] = true;
+                },
+                Some((ast::JoinKind::Inner, _)) => {},
+            }
+        }
+        padded
+    }
+    
+    pub fn get_column_type(&self, name_cols: &Vec<(String, HashMap<String, ColumnType>)>, cond: &ast::Column) -> ColumnType {
+        assert!(cond.tb_name.is_some());
+        let cond_tb_name = cond.tb_name.as_ref().unwrap();
+        for pair in name_cols {
+            let tb_name = &pair.0;
+            let hashmap = &pair.1;
+            if tb_name == cond_tb_name {
+                return hashmap.get(&cond.col_name).unwrap().clone()
+            }
+        }
+        unreachable!()
+    }
+
+    pub fn get_table_name(&self, name_cols: &Vec<(String, HashMap<String, ColumnType>)>, cond: &ast::Column) -> String {
+        if cond.tb_name.is_some() {
+            return cond.tb_name.as_ref().unwrap().clone();
+        }
+        for pair in name_cols {
+            let tb_name = &pair.0;
+            let hashmap = &pair.1;
+            if hashmap.get(&cond.col_name).is_some() {
+                return tb_name.clone();
+            }
+        }
+        unreachable!()
+    }
+
+    fn open_table(&self, tb_name: &ast::Name) -> TableHandler {
+        let mut path: PathBuf = [self.root_dir.clone(), self.database.clone(), tb_name.clone()].iter().collect();
+        path.set_extension("rua");
+        self.rm.borrow_mut().open_table(path.to_str().unwrap(), false)
+    }
+
+    // the rows of a view, its select runs once for every tree that reads it
+    fn view(&self, tb_name: &ast::Name) -> Option<Rc<RecordList>> {
+        if let Some(list) = self.views.borrow().get(tb_name) {
+            return Some(list.clone());
+        }
+        let mut path: PathBuf = [self.root_dir.clone(), self.database.clone(), tb_name.clone()].iter().collect();
+        path.set_extension("view");
+        let query = sql::parse_select(&fs::read_to_string(path).ok()?)?;
+        let list = Rc::new(self.run_select(&query));
+        self.views.borrow_mut().insert(tb_name.clone(), list.clone());
+        Some(list)
+    }
+
+    fn get_table_cols(&self, table_list: &Vec<ast::TableRef>) -> Vec<(String, HashMap<String, ColumnType>)> {
+        table_list.iter()
+            .map(|table| {
+                let mut map = match self.view(&table.tb_name) {
+                    Some(list) => list.ty.iter().enumerate().map(|(i, ct)| (ct.name.clone(), ColumnType { index: i as u32, ..ct.clone() })).collect(),
+                    None => {
+                        let th = self.open_table(&table.tb_name);
+                        let map = th.get_column_types_as_hashmap();
+                        th.close();
+                        map
+                    },
+                };
+                for ct in map.values_mut() {
+                    ct.tb_name = table.name().clone();
+                }
+                (table.name().clone(), map)
+            }).collect()
+    }
+
+    fn get_name_cols(&self, table_list: &Vec<ast::Name>) -> Vec<(String, HashMap<String, ColumnType>)> {
+        self.get_table_cols(&table_list.iter().map(|name| self.table_ref(name)).collect())
+    }
+
+    // find a btree whose leading columns are the ordering columns, so that the records can be read in order
+    fn get_order_index(&self, tb_name: &ast::Name) -> Option<(Vec<u32>, bool)> {
+        if self.order_clause.len() == 0 {
+            return None;
+        }
+        let asc = self.order_clause[0].asc;
+        if self.order_clause.iter().any(|o| o.asc != asc) {
+            return None;
+        }
+        let table = self.table_ref(tb_name);
+        if self.view(&table.tb_name).is_some() {
+            return None;
+        }
+        let th = self.open_table(&table.tb_name);
+        let mut cts = th.get_column_types().cols;
+        let btrees = th.get_btrees();
+        for ct in &mut cts {
+            ct.tb_name = table.name().clone();
+        }
+        th.close();
+        btrees.iter().find(|btree| {
+            btree.index_col.len() >= self.order_clause.len() &&
+            self.order_clause.iter().zip(btree.index_col.iter()).all(|(o, i)| {
+                let ct = &cts[*i as usize];
+                ct.match_(&o.col) && !ct.can_be_null
+            })
+        }).map(|btree| (btree.index_col.clone(), asc))
+    }
+
+    fn create_conds(&self, table_list: &Vec<ast::Name>, where_clause: &Option<Vec<ast::WhereClause>>, range_conds: &mut Vec<RangeCondition>, null_conds: &mut Vec<NullCondition>, pair_conds: &mut Vec<PairCondition>, expr_conds: &mut Vec<ExprCondition>) {
+        let name_cols = self.get_name_cols(table_list);
+        match where_clause {
+            Some(ref conds) => {
+                for cond in conds {
+                    match cond {
+                        ast::WhereClause::IsAssert{col, null} => { // null
+                            let col = ast::Column {
+                                tb_name: if col.tb_name.is_none() {Some(self.get_table_name(&name_cols, col))} else {col.tb_name.clone()},
+                                col_name: col.col_name.clone(),
                             };
                             if *null {
                                 let ct = self.get_column_type(&name_cols, &col);
@@ -602,7 +1785,8 @@ impl QueryTree {
                         },
                         ast::WhereClause::Comparison{col, op, expr} => {
                             match expr {
-                                ast::Expr::Value(ref value) => { // range
+                                // a literal the column cannot hold exactly is compared per record, and so is NULL, which matches nothing
+                                ast::Expr::Value(ref value) if self.fits_value(&name_cols, col, value) => { // range
                                     let mut matched = false;
                                     for cond in range_conds.iter_mut() {
                                         match col.tb_name {
@@ -648,7 +1832,7 @@ impl QueryTree {
                                         range_conds.push(cond);
                                     }
                                 }
-                                ast::Expr::Column(ref r_col) => { // pair
+                                ast::Expr::Column(ref r_col) if self.same_type(&name_cols, col, r_col) => { // pair
                                     pair_conds.push(PairCondition {
                                         l_col: ast::Column {
                                             tb_name: if col.tb_name.is_none() {Some(self.get_table_name(&name_cols, col))} else {col.tb_name.clone()},
@@ -661,8 +1845,61 @@ impl QueryTree {
                                         op: op.clone(),
                                     });
                                 }
+                                _ => {
+                                    expr_conds.push(ExprCondition {
+                                        clause: cond.clone(),
+                                        branches: Vec::new(),
+                                    });
+                                }
                             }
                         }
+                        ast::WhereClause::And(clauses) => {
+                            self.create_conds(table_list, &Some(clauses.clone()), range_conds, null_conds, pair_conds, expr_conds);
+                        }
+                        ast::WhereClause::Like{col, pattern, escape} => {
+                            // a constant prefix bounds the index range, the pattern itself is still checked per record
+                            let prefix = string::like_prefix(pattern, escape.as_ref().and_then(|escape| escape.chars().next()));
+                            if prefix.len() > 0 {
+                                let bound = |op: ast::Op, s: String| ast::WhereClause::Comparison {
+                                    col: col.clone(),
+                                    op: op,
+                                    expr: ast::Expr::Value(ast::Value::Str(s)),
+                                };
+                                let mut bounds = vec![bound(ast::Op::GreaterEqual, prefix.clone())];
+                                if let Some(successor) = string::prefix_successor(&prefix) {
+                                    bounds.push(bound(ast::Op::Less, successor));
+                                }
+                                self.create_conds(table_list, &Some(bounds), range_conds, null_conds, pair_conds, expr_conds);
+                            }
+                            expr_conds.push(ExprCondition {
+                                clause: cond.clone(),
+                                branches: Vec::new(),
+                            });
+                        }
+                        ast::WhereClause::Between{col, low, high} if !low.is_null() && !high.is_null() => {
+                            let bound = |op: ast::Op, value: &ast::Value| ast::WhereClause::Comparison {
+                                col: col.clone(),
+                                op: op,
+                                expr: ast::Expr::Value(value.clone()),
+                            };
+                            self.create_conds(table_list, &Some(vec![bound(ast::Op::GreaterEqual, low), bound(ast::Op::LessEqual, high)]), range_conds, null_conds, pair_conds, expr_conds);
+                        }
+                        // a column compared with a literal of its own type is a range, e.g. after a subquery is replaced by its value
+                        ast::WhereClause::ExprComparison{left: ast::Expr::Column(col), op, right: ast::Expr::Value(value)} if self.fits_value(&name_cols, col, value) => {
+                            let clause = ast::WhereClause::Comparison { col: col.clone(), op: op.clone(), expr: ast::Expr::Value(value.clone()) };
+                            self.create_conds(table_list, &Some(vec![clause]), range_conds, null_conds, pair_conds, expr_conds);
+                        }
+                        ast::WhereClause::ExprComparison{left: ast::Expr::Value(value), op, right: ast::Expr::Column(col)} if self.fits_value(&name_cols, col, value) => {
+                            let clause = ast::WhereClause::Comparison { col: col.clone(), op: op.flip(), expr: ast::Expr::Value(value.clone()) };
+                            self.create_conds(table_list, &Some(vec![clause]), range_conds, null_conds, pair_conds, expr_conds);
+                        }
+                        ast::WhereClause::Between{..} | ast::WhereClause::In{..} | ast::WhereClause::InSubquery{..} | ast::WhereClause::Exists(_) |
+                        ast::WhereClause::ExprComparison{..} | ast::WhereClause::Or(_) | ast::WhereClause::Not(_) => {
+                            expr_conds.push(ExprCondition {
+                                clause: cond.clone(),
+                                branches: Vec::new(),
+                            });
+                        }
                     }
                 }
             }
@@ -670,18 +1907,346 @@ impl QueryTree {
         }
     }
 
+    fn qualified_type(&self, name_cols: &Vec<(String, HashMap<String, ColumnType>)>, col: &ast::Column) -> ColumnType {
+        let col = ast::Column {
+            tb_name: Some(self.get_table_name(name_cols, col)),
+            col_name: col.col_name.clone(),
+        };
+        self.get_column_type(name_cols, &col)
+    }
+
+    fn fits_value(&self, name_cols: &Vec<(String, HashMap<String, ColumnType>)>, col: &ast::Column, value: &ast::Value) -> bool {
+        !value.is_null() && self.qualified_type(name_cols, col).fits_value(value)
+    }
+
+    // a pair condition compares the stored data as it is
+    fn same_type(&self, name_cols: &Vec<(String, HashMap<String, ColumnType>)>, l_col: &ast::Column, r_col: &ast::Column) -> bool {
+        let (l_ct, r_ct) = (self.qualified_type(name_cols, l_col), self.qualified_type(name_cols, r_col));
+        l_ct.data_type.same(&r_ct.data_type) && l_ct.numeric_precision == r_ct.numeric_precision
+    }
+
+    // builds a select or a compound select, `limit_clause` is used when it has none of its own
+    pub fn build_query(&mut self, query: &ast::TableStmt, limit_clause: &Option<ast::LimitClause>) {
+        match query {
+            ast::TableStmt::Select { distinct, table_list, join_list, selector, where_clause, group_clause, having_clause, order_clause, limit_clause: own_limit } => {
+                self.set_distinct(*distinct);
+                self.set_group(group_clause, having_clause);
+                self.set_order(order_clause);
+                self.set_limit(if own_limit.is_some() {own_limit} else {limit_clause});
+                self.set_join(join_list);
+                self.set_alias(table_list);
+                self.build(&table_list.iter().map(|table| table.name().clone()).collect(), selector, where_clause);
+            },
+            ast::TableStmt::Compound { op, all, left, right, order_clause, limit_clause: own_limit } => {
+                self.set_order(order_clause);
+                self.set_limit(if own_limit.is_some() {own_limit} else {limit_clause});
+                self.build_compound(op, *all, left, right);
+            },
+            _ => unreachable!(),
+        }
+    }
+
+    fn subtree(&self, query: &ast::TableStmt, limit_clause: &Option<ast::LimitClause>) -> QueryTree {
+        let mut tree = QueryTree::new(&self.root_dir, &self.database, self.rm.clone());
+        tree.set_analyze(self.analyze);
+        tree.error = self.error.clone();
+        tree.sequences = self.sequences.clone();
+        tree.build_query(query, limit_clause);
+        tree
+    }
+
+    fn run_subquery(&self, query: &ast::TableStmt, limit_clause: &Option<ast::LimitClause>) -> RecordList {
+        self.subtree(query, limit_clause).query()
+    }
+
+    // runs a select that is not part of this tree, such as the source of INSERT ... SELECT
+    pub fn run_select(&self, query: &ast::TableStmt) -> RecordList {
+        self.run_subquery(query, &None)
+    }
+
+    // columns a nested select takes from the enclosing selects, it only has to run once if there are none
+    fn outer_columns(&self, query: &ast::TableStmt) -> Vec<ast::Column> {
+        match query {
+            ast::TableStmt::Select { table_list, where_clause, .. } => {
+                let name_cols = self.get_table_cols(table_list);
+                let mut cols = Vec::new();
+                for clause in where_clause.iter().flatten() {
+                    cols.extend(clause.columns().into_iter().cloned());
+                    for query in clause.subqueries() {
+                        cols.extend(self.outer_columns(query));
+                    }
+                }
+                cols.into_iter().filter(|col| !is_inner(&name_cols, col)).collect()
+            },
+            ast::TableStmt::Compound { left, right, .. } => {
+                let mut cols = self.outer_columns(left);
+                cols.extend(self.outer_columns(right));
+                cols
+            },
+            _ => Vec::new(),
+        }
+    }
+
+    // replaces the columns a nested select takes from the outer record by their values,
+    // `scope` holds the tables of the selects in between
+    fn bind(&self, query: &ast::TableStmt, scope: &Vec<(String, HashMap<String, ColumnType>)>, record: &Record, ty: &Vec<ColumnType>) -> ast::TableStmt {
+        let mut query = query.clone();
+        match query {
+            ast::TableStmt::Select { ref table_list, ref mut where_clause, .. } => {
+                let mut scope = scope.clone();
+                scope.extend(self.get_table_cols(table_list));
+                if let Some(clauses) = where_clause {
+                    *clauses = clauses.iter().map(|clause| self.bind_clause(clause, &scope, record, ty)).collect();
+                }
+            },
+            ast::TableStmt::Compound { ref mut left, ref mut right, .. } => {
+                *left = Box::new(self.bind(left, scope, record, ty));
+                *right = Box::new(self.bind(right, scope, record, ty));
+            },
+            _ => {},
+        }
+        query
+    }
+
+    fn bind_clause(&self, clause: &ast::WhereClause, scope: &Vec<(String, HashMap<String, ColumnType>)>, record: &Record, ty: &Vec<ColumnType>) -> ast::WhereClause {
+        match clause {
+            ast::WhereClause::Comparison{col, op, expr} => {
+                match (self.bind_expr(&ast::Expr::Column(col.clone()), scope, record, ty), self.bind_expr(expr, scope, record, ty)) {
+                    (ast::Expr::Column(col), right) if &right == expr => ast::WhereClause::Comparison { col: col, op: op.clone(), expr: right },
+                    (left, right) => ast::WhereClause::ExprComparison { left: left, op: op.clone(), right: right },
+                }
+            },
+            ast::WhereClause::ExprComparison{left, op, right} => ast::WhereClause::ExprComparison {
+                left: self.bind_expr(left, scope, record, ty),
+                op: op.clone(),
+                right: self.bind_expr(right, scope, record, ty),
+            },
+            ast::WhereClause::InSubquery{col, query} => {
+                let clause = ast::WhereClause::InSubquery { col: col.clone(), query: Box::new(self.bind(query, scope, record, ty)) };
+                if is_inner(scope, col) {clause} else {constant(ExprCondition::eval(&clause, record, ty, self))}
+            },
+            ast::WhereClause::Exists(query) => ast::WhereClause::Exists(Box::new(self.bind(query, scope, record, ty))),
+            ast::WhereClause::IsAssert{col, ..} | ast::WhereClause::Like{col, ..} |
+            ast::WhereClause::In{col, ..} | ast::WhereClause::Between{col, ..} if !is_inner(scope, col) => {
+                constant(ExprCondition::eval(clause, record, ty, self))
+            },
+            ast::WhereClause::And(clauses) => ast::WhereClause::And(clauses.iter().map(|clause| self.bind_clause(clause, scope, record, ty)).collect()),
+            ast::WhereClause::Or(clauses) => ast::WhereClause::Or(clauses.iter().map(|clause| self.bind_clause(clause, scope, record, ty)).collect()),
+            ast::WhereClause::Not(clause) => ast::WhereClause::Not(Box::new(self.bind_clause(clause, scope, record, ty))),
+            clause => clause.clone(),
+        }
+    }
+
+    fn bind_expr(&self, expr: &ast::Expr, scope: &Vec<(String, HashMap<String, ColumnType>)>, record: &Record, ty: &Vec<ColumnType>) -> ast::Expr {
+        match expr {
+            ast::Expr::Column(col) if !is_inner(scope, col) => match record.get_match_data(col, ty) {
+                (data, Some(ct)) => ast::Expr::Value(Data::to_value(&data, &ct)),
+                (_, None) => expr.clone(),
+            },
+            ast::Expr::Binary{op, left, right} => ast::Expr::Binary {
+                op: op.clone(),
+                left: Box::new(self.bind_expr(left, scope, record, ty)),
+                right: Box::new(self.bind_expr(right, scope, record, ty)),
+            },
+            ast::Expr::Neg(expr) => ast::Expr::Neg(Box::new(self.bind_expr(expr, scope, record, ty))),
+            ast::Expr::Subquery(query) => ast::Expr::Subquery(Box::new(self.bind(query, scope, record, ty))),
+            ast::Expr::Func{func, args} => ast::Expr::Func {
+                func: func.clone(),
+                args: args.iter().map(|arg| self.bind_expr(arg, scope, record, ty)).collect(),
+            },
+            ast::Expr::Case{whens, else_} => ast::Expr::Case {
+                whens: whens.iter().map(|(cond, result)| (self.bind_clause(cond, scope, record, ty), self.bind_expr(result, scope, record, ty))).collect(),
+                else_: else_.as_ref().map(|result| Box::new(self.bind_expr(result, scope, record, ty))),
+            },
+            ast::Expr::Cast{expr, ty: cast_ty} => ast::Expr::Cast {
+                expr: Box::new(self.bind_expr(expr, scope, record, ty)),
+                ty: cast_ty.clone(),
+            },
+            expr => expr.clone(),
+        }
+    }
+
+    // replaces scalar subqueries by the value of their first row, NULL if there is none;
+    // correlated ones are only replaced when the outer record is given
+    fn resolve_expr(&self, expr: &ast::Expr, outer: Option<(&Record, &Vec<ColumnType>)>) -> ast::Expr {
+        match expr {
+            ast::Expr::Subquery(query) => {
+                let list = match outer {
+                    Some((record, ty)) => self.run_subquery(&self.bind(query, &Vec::new(), record, ty), &Some(ast::LimitClause { limit: 1, offset: 0 })),
+                    None if self.outer_columns(query).len() == 0 => self.run_subquery(query, &Some(ast::LimitClause { limit: 1, offset: 0 })),
+                    None => return expr.clone(),
+                };
+                ast::Expr::Value(match list.record.first() {
+                    Some(record) => Data::to_value(&record.cols[0].data, &list.ty[0]),
+                    None => ast::Value::Null,
+                })
+            },
+            ast::Expr::Binary{op, left, right} => ast::Expr::Binary {
+                op: op.clone(),
+                left: Box::new(self.resolve_expr(left, outer)),
+                right: Box::new(self.resolve_expr(right, outer)),
+            },
+            ast::Expr::Neg(expr) => ast::Expr::Neg(Box::new(self.resolve_expr(expr, outer))),
+            ast::Expr::Func{func, args} => ast::Expr::Func {
+                func: func.clone(),
+                args: args.iter().map(|arg| self.resolve_expr(arg, outer)).collect(),
+            },
+            ast::Expr::Case{whens, else_} => ast::Expr::Case {
+                whens: whens.iter().map(|(cond, result)| (cond.clone(), self.resolve_expr(result, outer))).collect(),
+                else_: else_.as_ref().map(|result| Box::new(self.resolve_expr(result, outer))),
+            },
+            ast::Expr::Cast{expr, ty} => ast::Expr::Cast {
+                expr: Box::new(self.resolve_expr(expr, outer)),
+                ty: ty.clone(),
+            },
+            expr => expr.clone(),
+        }
+    }
+
+    // EXISTS (SELECT .. WHERE inner = outer AND ..) is the same as outer IN (SELECT inner FROM .. WHERE ..),
+    // which does not depend on the outer record any more
+    fn semi_join(&self, query: &ast::TableStmt) -> Option<ast::WhereClause> {
+        match query {
+            ast::TableStmt::Select { distinct: _, table_list, join_list, selector, where_clause: Some(clauses), group_clause: None, having_clause: None, order_clause: _, limit_clause: None } if !selector.has_aggregate() => {
+                let name_cols = self.get_table_cols(table_list);
+                let is_correlated = |clause: &ast::WhereClause| {
+                    clause.columns().into_iter().any(|col| !is_inner(&name_cols, col)) ||
+                    clause.subqueries().into_iter().any(|query| self.outer_columns(query).iter().any(|col| !is_inner(&name_cols, col)))
+                };
+                let mut conjuncts = Vec::new();
+                let mut stack: Vec<&ast::WhereClause> = clauses.iter().rev().collect();
+                while let Some(clause) = stack.pop() {
+                    match clause {
+                        ast::WhereClause::And(clauses) => stack.extend(clauses.iter().rev()),
+                        clause => conjuncts.push(clause.clone()),
+                    }
+                }
+                let (correlated, rest): (Vec<ast::WhereClause>, Vec<ast::WhereClause>) = conjuncts.into_iter().partition(|clause| is_correlated(clause));
+                if correlated.len() != 1 {
+                    return None;
+                }
+                let (inner, outer) = match correlated[0] {
+                    ast::WhereClause::Comparison{ref col, op: ast::Op::Equal, expr: ast::Expr::Column(ref r_col)} => {
+                        match (is_inner(&name_cols, col), is_inner(&name_cols, r_col)) {
+                            (true, false) => (col.clone(), r_col.clone()),
+                            (false, true) => (r_col.clone(), col.clone()),
+                            _ => return None,
+                        }
+                    },
+                    _ => return None,
+                };
+                Some(ast::WhereClause::InSubquery {
+                    col: outer,
+                    query: Box::new(ast::TableStmt::Select {
+                        distinct: false,
+                        table_list: table_list.clone(),
+                        join_list: join_list.clone(),
+                        selector: ast::Selector::Columns(vec![ast::SelectItem::Column(inner)], vec![None]),
+                        where_clause: if rest.len() > 0 {Some(rest)} else {None},
+                        group_clause: None,
+                        having_clause: None,
+                        order_clause: None,
+                        limit_clause: None,
+                    }),
+                })
+            },
+            _ => None,
+        }
+    }
+
+    // runs the subqueries that do not depend on the outer record once, before the tree is built;
+    // under a NOT the semi join would turn a false EXISTS into NULL, so a correlated one is left to each outer record
+    fn resolve(&self, name_cols: &Vec<(String, HashMap<String, ColumnType>)>, clause: &ast::WhereClause, negated: bool) -> ast::WhereClause {
+        match clause {
+            ast::WhereClause::InSubquery{col, query} if self.outer_columns(query).len() == 0 => {
+                let list = self.run_subquery(query, &None);
+                let mut seen = HashSet::new();
+                let values = list.record.iter()
+                    .filter(|record| seen.insert(record.cols[0].data.clone()))
+                    .map(|record| Data::to_value(&record.cols[0].data, &list.ty[0]))
+                    .collect();
+                ast::WhereClause::In { col: col.clone(), values: values }
+            },
+            ast::WhereClause::Exists(query) => match self.semi_join(query).filter(|_| !negated) {
+                Some(clause) => self.resolve(name_cols, &clause, negated),
+                None if self.outer_columns(query).len() == 0 => {
+                    constant(Some(self.run_subquery(query, &Some(ast::LimitClause { limit: 1, offset: 0 })).record.len() > 0))
+                },
+                None => clause.clone(),
+            },
+            ast::WhereClause::Comparison{col, op, expr} if expr.has_subquery() => match self.resolve_expr(expr, None) {
+                expr if expr.has_subquery() => ast::WhereClause::Comparison { col: col.clone(), op: op.clone(), expr: expr },
+                expr => ast::WhereClause::ExprComparison { left: ast::Expr::Column(col.clone()), op: op.clone(), right: expr },
+            },
+            ast::WhereClause::ExprComparison{left, op, right} => ast::WhereClause::ExprComparison {
+                left: self.resolve_expr(left, None),
+                op: op.clone(),
+                right: self.resolve_expr(right, None),
+            },
+            ast::WhereClause::And(clauses) => ast::WhereClause::And(clauses.iter().map(|clause| self.resolve(name_cols, clause, negated)).collect()),
+            ast::WhereClause::Or(clauses) => ast::WhereClause::Or(clauses.iter().map(|clause| self.resolve(name_cols, clause, negated)).collect()),
+            ast::WhereClause::Not(clause) => ast::WhereClause::Not(Box::new(self.resolve(name_cols, clause, !negated))),
+            clause => clause.clone(),
+        }
+    }
+
     pub fn build(&mut self, table_list: &Vec<ast::Name>, selector: &ast::Selector, where_clause: &Option<Vec<ast::WhereClause>>) {
+        let name_cols = self.get_name_cols(table_list);
+        let mut where_clause: Option<Vec<ast::WhereClause>> = where_clause.as_ref().map(|clauses| clauses.iter().map(|clause| self.resolve(&name_cols, clause, false)).collect());
+
+        let (mut steps, hidden) = self.join_steps(table_list, &name_cols);
+        let padded = QueryTree::padded_tables(&steps);
+        // inner joins only filter the product, outer joins keep their conditions to themselves
+        // and the where clause can only be pushed down to the tables they never fill with NULL
+        let mut padded_clauses = Vec::new();
+        if padded.contains(&true) {
+            let (clauses, rest): (Vec<ast::WhereClause>, Vec<ast::WhereClause>) = where_clause.unwrap_or(Vec::new()).into_iter().partition(|clause| {
+                clause.columns().iter().any(|col| padded[name_cols.iter().position(|(tb_name, _)| tb_name == &self.get_table_name(&name_cols, col)).unwrap()])
+            });
+            padded_clauses = clauses;
+            where_clause = if rest.len() > 0 {Some(rest)} else {None};
+        } else {
+            for step in steps.iter_mut() {
+                if let Some((_, clauses)) = step.take() {
+                    where_clause.get_or_insert(Vec::new()).extend(clauses);
+                }
+            }
+        }
+        let selector = &match selector {
+            ast::Selector::All if hidden.len() > 0 => {
+                let items: Vec<ast::SelectItem> = name_cols.iter().flat_map(|(tb_name, cols)| {
+                    let mut cts: Vec<&ColumnType> = cols.values().collect();
+                    cts.sort_by_key(|ct| ct.index);
+                    cts.into_iter().map(move |ct| ast::Column {
+                        tb_name: Some(tb_name.clone()),
+                        col_name: ct.name.clone(),
+                    })
+                }).filter(|col| !hidden.contains(col)).map(ast::SelectItem::Column).collect();
+                let aliases = vec![None; items.len()];
+                ast::Selector::Columns(items, aliases)
+            },
+            selector => selector.clone(),
+        };
+        self.item_aliases = match selector {
+            ast::Selector::Columns(_, aliases) => aliases.clone(),
+            ast::Selector::All => Vec::new(),
+        };
+        let where_clause = &where_clause;
+
         let mut range_conds: Vec<RangeCondition> = Vec::new();
         let mut null_conds: Vec<NullCondition> = Vec::new();
         let mut pair_conds: Vec<PairCondition> = Vec::new();
-        self.create_conds(table_list, where_clause, &mut range_conds, &mut null_conds, &mut pair_conds);
+        let mut expr_conds: Vec<ExprCondition> = Vec::new();
+        self.create_conds(table_list, where_clause, &mut range_conds, &mut null_conds, &mut pair_conds, &mut expr_conds);
 
         let mut table_range_conds: Vec<Vec<RangeCondition>> = Vec::new();
         let mut table_null_conds: Vec<Vec<NullCondition>> = Vec::new();
+        let mut table_expr_conds: Vec<Vec<ExprCondition>> = Vec::new();
 
         for _ in 0..table_list.len() {
             table_range_conds.push(Vec::new());
             table_null_conds.push(Vec::new());
+            table_expr_conds.push(Vec::new());
         }
         for i in 0..table_list.len() {
             for cond in &range_conds {
@@ -695,71 +2260,290 @@ impl QueryTree {
                 }
             }
         }
-        self.root = Some(self.project_layer(table_list, selector, table_range_conds, table_null_conds, pair_conds))
+        let mut pair_expr_conds = Vec::new();
+        for cond in expr_conds {
+            let mut tables: Vec<String> = cond.clause.columns().iter().map(|col| self.get_table_name(&name_cols, col)).collect();
+            tables.sort();
+            tables.dedup();
+            match table_list.iter().position(|tb_name| tables.len() == 1 && tb_name == &tables[0]) {
+                // a correlated subquery may use the columns of any table
+                Some(i) if !cond.clause.has_subquery() => table_expr_conds[i].push(self.create_branches(table_list, cond)),
+                _ => pair_expr_conds.push(cond),
+            }
+        }
+        for clause in padded_clauses {
+            pair_expr_conds.push(ExprCondition {
+                clause: clause,
+                branches: Vec::new(),
+            });
+        }
+        if let Some(node) = self.count_layer(table_list, selector, where_clause) {
+            self.root = Some(self.limit_layer(self.profile(node)));
+            return;
+        }
+        let order_index = if table_list.len() == 1 && !self.is_aggregated(selector) {self.get_order_index(&table_list[0])} else {None};
+        // the scan may stop early only if the rows it produces are final and already in order
+        let scan_limit = match self.limit_clause {
+            Some(ref limit) if table_list.len() == 1 && !self.is_aggregated(selector) && !self.distinct && pair_conds.len() == 0 && pair_expr_conds.len() == 0
+                && (self.order_clause.len() == 0 || order_index.is_some()) => Some(limit.offset.saturating_add(limit.limit)),
+            _ => None,
+        };
+        let root = self.project_layer(table_list, selector, steps, table_range_conds, table_null_conds, table_expr_conds, pair_conds, pair_expr_conds, order_index, scan_limit);
+        self.root = Some(self.limit_layer(self.distinct_layer(root)));
+    }
+
+    pub fn build_compound(&mut self, op: &ast::SetOp, all: bool, left: &ast::TableStmt, right: &ast::TableStmt) {
+        let node = self.profile(Box::new(SetOpNode {
+            op: op.clone(),
+            all: all,
+            left: self.subtree(left, &None),
+            right: self.subtree(right, &None),
+        }));
+        self.root = Some(self.limit_layer(self.sort_layer(node, false)));
+    }
+
+    fn profile(&self, node: Box<dyn QueryNode>) -> Box<dyn QueryNode> {
+        if self.analyze {
+            Box::new(ProfileNode {
+                son: node,
+                rows: Cell::new(0),
+                time: Cell::new(Duration::new(0, 0)),
+            })
+        }
+        else {
+            node
+        }
+    }
+
+    fn distinct_layer(&self, son: Box<dyn QueryNode>) -> Box<dyn QueryNode> {
+        if self.distinct {
+            self.profile(Box::new(DistinctNode {
+                son: son,
+            }))
+        }
+        else {
+            son
+        }
+    }
+
+    fn limit_layer(&self, son: Box<dyn QueryNode>) -> Box<dyn QueryNode> {
+        match self.limit_clause {
+            Some(ref limit) => self.profile(Box::new(LimitNode {
+                son: son,
+                limit: limit.limit,
+                offset: limit.offset,
+            })),
+            None => son,
+        }
+    }
+
+    fn create_branches(&self, table_list: &Vec<ast::Name>, cond: ExprCondition) -> ExprCondition {
+        let mut cond = cond;
+        let branches: Vec<Vec<ast::WhereClause>> = match cond.clause {
+            ast::WhereClause::Or(ref clauses) => clauses.iter().map(|clause| match clause {
+                ast::WhereClause::And(conjuncts) => conjuncts.clone(),
+                _ => vec![clause.clone()],
+            }).collect(),
+            // every value of an IN list is a point lookup, a NULL never matches
+            ast::WhereClause::In{ref col, ref values} => values.iter().filter(|value| !value.is_null()).map(|value| vec![ast::WhereClause::Comparison {
+                col: col.clone(),
+                op: ast::Op::Equal,
+                expr: ast::Expr::Value(value.clone()),
+            }]).collect(),
+            _ => Vec::new(),
+        };
+        for conjuncts in branches {
+            let mut range_conds = Vec::new();
+            self.create_conds(table_list, &Some(conjuncts), &mut range_conds, &mut Vec::new(), &mut Vec::new(), &mut Vec::new());
+            cond.branches.push(range_conds);
+        }
+        cond
+    }
+
+    fn count_layer(&self, table_list: &Vec<ast::Name>, selector: &ast::Selector, where_clause: &Option<Vec<ast::WhereClause>>) -> Option<Box<dyn QueryNode>> {
+        let count_only = match selector {
+            ast::Selector::Columns(items, _) => items.iter().all(|item| match item {
+                ast::SelectItem::Aggregate(ast::Aggregate { func: ast::AggrFunc::Count, col: None }) => true,
+                _ => false,
+            }),
+            ast::Selector::All => false,
+        };
+        if count_only && table_list.len() == 1 && where_clause.is_none() && self.group_clause.is_none() && self.having_clause.len() == 0
+            && self.view(&self.table_ref(&table_list[0]).tb_name).is_none() {
+            Some(Box::new(CountNode {
+                root_dir: self.root_dir.clone(),
+                database: self.database.clone(),
+                rm: self.rm.clone(),
+                tb_name: self.table_ref(&table_list[0]).tb_name,
+                items: match selector {
+                    ast::Selector::Columns(items, _) => items.clone(),
+                    ast::Selector::All => unreachable!(),
+                },
+            }))
+        }
+        else {
+            None
+        }
     }
 
-    fn project_layer(&self, table_list: &Vec<ast::Name>, selector: &ast::Selector, table_range_conds: Vec<Vec<RangeCondition>>, table_null_conds: Vec<Vec<NullCondition>>, pair_conds: Vec<PairCondition>) -> Box<dyn QueryNode> {
+    fn project_layer(&self, table_list: &Vec<ast::Name>, selector: &ast::Selector, steps: Vec<Option<(ast::JoinKind, Vec<ast::WhereClause>)>>, table_range_conds: Vec<Vec<RangeCondition>>, table_null_conds: Vec<Vec<NullCondition>>, table_expr_conds: Vec<Vec<ExprCondition>>, pair_conds: Vec<PairCondition>, expr_conds: Vec<ExprCondition>, order_index: Option<(Vec<u32>, bool)>, scan_limit: Option<usize>) -> Box<dyn QueryNode> {
+        let sorted = order_index.is_some();
+        let son = self.select_pair_layer(table_list, steps, table_range_conds, table_null_conds, table_expr_conds, pair_conds, expr_conds, order_index, scan_limit);
         match selector {
             ast::Selector::All => {
-                self.select_pair_layer(table_list, table_range_conds, table_null_conds, pair_conds)
+                self.sort_layer(son, sorted)
             },
-            ast::Selector::Columns(cols) => {
-                Box::new(ProjectNode {
-                    son: self.select_pair_layer(table_list, table_range_conds, table_null_conds, pair_conds),
-                    cols: cols.clone(),
-                })
+            ast::Selector::Columns(items, _) if self.is_aggregated(selector) => {
+                self.sort_layer(self.aggregate_layer(son, items), false)
+            },
+            ast::Selector::Columns(items, _) => {
+                self.profile(Box::new(ProjectNode {
+                    son: self.sort_layer(son, sorted),
+                    items: items.clone(),
+                    sequences: self.sequences.clone(),
+                    error: self.error.clone(),
+                }))
             },
         }
     }
 
-    fn select_pair_layer(&self, table_list: &Vec<ast::Name>, table_range_conds: Vec<Vec<RangeCondition>>, table_null_conds: Vec<Vec<NullCondition>>, pair_conds: Vec<PairCondition>) -> Box<dyn QueryNode> {
-        Box::new(
+    fn aggregate_layer(&self, son: Box<dyn QueryNode>, items: &Vec<ast::SelectItem>) -> Box<dyn QueryNode> {
+        self.profile(Box::new(AggregateNode {
+            son: son,
+            group_by: self.group_clause.clone().unwrap_or(Vec::new()),
+            items: items.clone(),
+            having: self.having_clause.clone(),
+            error: self.error.clone(),
+        }))
+    }
+
+    fn sort_layer(&self, son: Box<dyn QueryNode>, sorted: bool) -> Box<dyn QueryNode> {
+        if self.order_clause.len() == 0 || sorted {
+            son
+        }
+        else {
+            self.profile(Box::new(SortNode {
+                son: son,
+                order: self.order_clause.clone(),
+            }))
+        }
+    }
+
+    fn select_pair_layer(&self, table_list: &Vec<ast::Name>, steps: Vec<Option<(ast::JoinKind, Vec<ast::WhereClause>)>>, table_range_conds: Vec<Vec<RangeCondition>>, table_null_conds: Vec<Vec<NullCondition>>, table_expr_conds: Vec<Vec<ExprCondition>>, pair_conds: Vec<PairCondition>, expr_conds: Vec<ExprCondition>, order_index: Option<(Vec<u32>, bool)>, scan_limit: Option<usize>) -> Box<dyn QueryNode> {
+        self.profile(Box::new(
             SelectNode {
                 root_dir: self.root_dir.clone(),
                 database: self.database.clone(),
                 rm: self.rm.clone(),
-                son: Some(self.product_layer(table_list, table_range_conds, table_null_conds)),
+                son: Some(self.product_layer(table_list, steps, table_range_conds, table_null_conds, table_expr_conds, order_index, scan_limit)),
                 table_list: None,
+                alias: None,
+                view: None,
                 range_conds: Vec::new(),
                 null_conds: Vec::new(),
                 pair_conds: pair_conds,
+                expr_conds: expr_conds,
+                order_index: None,
+                limit: None,
             }
-        )
+        ))
     }
 
-    fn product_layer(&self, table_list: &Vec<ast::Name>, table_range_conds: Vec<Vec<RangeCondition>>, table_null_conds: Vec<Vec<NullCondition>>) -> Box<dyn QueryNode> {
+    // a from item with outer joins is a chain of join nodes, the from items form a product
+    fn product_layer(&self, table_list: &Vec<ast::Name>, steps: Vec<Option<(ast::JoinKind, Vec<ast::WhereClause>)>>, table_range_conds: Vec<Vec<RangeCondition>>, table_null_conds: Vec<Vec<NullCondition>>, table_expr_conds: Vec<Vec<ExprCondition>>, order_index: Option<(Vec<u32>, bool)>, scan_limit: Option<usize>) -> Box<dyn QueryNode> {
         let mut son: Vec<Box<dyn QueryNode>> = Vec::new();
-        let mut table_range_conds = table_range_conds;
-        let mut table_null_conds = table_null_conds;
-        for i in 0..table_list.len() {
-            son.push(self.select_single_layer(table_list[i].clone(), table_range_conds[i].clone(), table_null_conds[i].clone()));
+        for (i, step) in steps.into_iter().enumerate() {
+            let node = self.select_single_layer(table_list[i].clone(), table_range_conds[i].clone(), table_null_conds[i].clone(), table_expr_conds[i].clone(), order_index.clone(), scan_limit);
+            match step {
+                Some((kind, clauses)) => {
+                    let left = son.pop().unwrap();
+                    son.push(self.profile(Box::new(JoinNode {
+                        root_dir: self.root_dir.clone(),
+                        database: self.database.clone(),
+                        rm: self.rm.clone(),
+                        left: left,
+                        right: node,
+                        kind: kind,
+                        conds: clauses.into_iter().map(|clause| ExprCondition {
+                            clause: clause,
+                            branches: Vec::new(),
+                        }).collect(),
+                    })));
+                },
+                None => son.push(node),
+            }
         }
-        Box::new(
+        self.profile(Box::new(
             ProductNode {
                 son: son,
             }
-        )
+        ))
     }
 
-    fn select_single_layer(&self, tb_name: ast::Name, range_conds: Vec<RangeCondition>, null_conds: Vec<NullCondition>) -> Box<dyn QueryNode> {
-        Box::new(
+    fn select_single_layer(&self, tb_name: ast::Name, range_conds: Vec<RangeCondition>, null_conds: Vec<NullCondition>, expr_conds: Vec<ExprCondition>, order_index: Option<(Vec<u32>, bool)>, scan_limit: Option<usize>) -> Box<dyn QueryNode> {
+        let table = self.table_ref(&tb_name);
+        self.profile(Box::new(
             SelectNode {
                 root_dir: self.root_dir.clone(),
                 database: self.database.clone(),
                 rm: self.rm.clone(),
                 son: None,
-                table_list: Some(tb_name),
+                view: self.view(&table.tb_name),
+                table_list: Some(table.tb_name),
+                alias: table.alias,
                 range_conds: range_conds,
                 null_conds: null_conds,
                 pair_conds: Vec::new(),
+                expr_conds: expr_conds,
+                order_index: order_index,
+                limit: scan_limit,
             }
-        )
+        ))
     }
 
     pub fn query(&self) -> RecordList {
         match self.root {
-            Some(ref root) => root.query(),
+            Some(ref root) => {
+                let mut record_list = root.query();
+                for (ct, alias) in record_list.ty.iter_mut().zip(self.item_aliases.iter()) {
+                    if let Some(alias) = alias {
+                        ct.name = alias.clone();
+                    }
+                }
+                record_list
+            },
             None => RecordList::new(),
         }
     }
-}
\ No newline at end of file
+
+    // set by `query` when the rows could not be produced
+    pub fn error(&self) -> Option<String> {
+        self.error.borrow().clone()
+    }
+
+    // writes back the sequences the query has moved on, once it has run for real
+    pub fn store_sequences(&self) {
+        self.sequences.borrow().store();
+    }
+
+    // one line per node, the sons are indented under their parent
+    pub fn explain(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        if let Some(ref root) = self.root {
+            explain_node(root.as_ref(), 0, &mut lines);
+        }
+        lines
+    }
+}
+
+fn explain_node(node: &dyn QueryNode, depth: usize, lines: &mut Vec<String>) {
+    let detail = node.get_detail();
+    let line = match detail.len() {
+        0 => node.get_name().to_owned(),
+        _ => format!("{}: {}", node.get_name(), detail),
+    };
+    lines.push(format!("{}{}", "  ".repeat(depth), line));
+    for son in node.get_sons() {
+        explain_node(son, depth + 1, lines);
+    }
+}
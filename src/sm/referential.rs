@@ -0,0 +1,260 @@
+use crate::rm::record::*;
+use crate::parser::ast::ReferentialAction;
+use crate::index::btree::*;
+
+use crate::defer;
+
+use super::system_manager::SystemManager;
+use super::check;
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+// what a delete or an update comes to once the foreign keys referencing its rows have taken their actions
+// the rows are only read while it is worked out, `apply` writes them all at the end
+pub struct Propagation<'a> {
+    sm: &'a SystemManager,
+    // table -> row -> (the row as stored, what it becomes or None when deleted)
+    changes: HashMap<String, HashMap<u64, (Record, Option<Record>)>>,
+    // rows which NO ACTION lets keep a key only if the statement changes or deletes them, (table, row, columns, key)
+    held: Vec<(String, u64, Vec<u32>, Vec<Data>)>,
+    // keys SET DEFAULT points rows at, (table, key)
+    defaults: Vec<(String, Vec<Data>)>,
+    restricted: bool,
+    // table -> the tables with a foreign key referencing it, itself included
+    referencing: HashMap<String, Vec<String>>,
+}
+
+fn key(record: &Record, cols: &Vec<u32>) -> Vec<Option<Data>> {
+    cols.iter().map(|i| record.cols[*i as usize].data.clone()).collect()
+}
+
+impl<'a> Propagation<'a> {
+    pub fn new(sm: &'a SystemManager) -> Self {
+        Self {
+            sm: sm,
+            changes: HashMap::new(),
+            held: Vec::new(),
+            defaults: Vec::new(),
+            restricted: false,
+            referencing: HashMap::new(),
+        }
+    }
+
+    pub fn delete(&mut self, tb_name: &String, ptr: u64, record: Record) {
+        let rows = self.changes.entry(tb_name.clone()).or_insert_with(HashMap::new);
+        let old = match rows.get_mut(&ptr) {
+            Some((_, None)) => return,
+            Some((old, new)) => {
+                *new = None;
+                old.clone()
+            },
+            None => {
+                rows.insert(ptr, (record.clone(), None));
+                record
+            },
+        };
+        self.release(tb_name, &old, None);
+    }
+
+    pub fn update(&mut self, tb_name: &String, ptr: u64, record: Record, new: Record) {
+        let rows = self.changes.entry(tb_name.clone()).or_insert_with(HashMap::new);
+        let (old, before) = match rows.get_mut(&ptr) {
+            Some((_, None)) => return,
+            Some((old, Some(planned))) => (old.clone(), std::mem::replace(planned, new.clone())),
+            None => {
+                rows.insert(ptr, (record.clone(), Some(new.clone())));
+                (record.clone(), record)
+            },
+        };
+        if let Some(primary_cols) = self.primary_cols(tb_name) {
+            if key(&before, &primary_cols) != key(&new, &primary_cols) {
+                self.release(tb_name, &old, Some(&new));
+            }
+        }
+    }
+
+    // sets some columns of a row on top of what the statement already does to it
+    fn set(&mut self, tb_name: &String, ptr: u64, record: Record, cols: &Vec<u32>, data: Vec<Option<Data>>) {
+        let mut new = match self.changes.get(tb_name).and_then(|rows| rows.get(&ptr)) {
+            Some((_, None)) => return,
+            Some((_, Some(planned))) => planned.clone(),
+            None => record.clone(),
+        };
+        for (i, d) in cols.iter().zip(data.into_iter()) {
+            new.cols[*i as usize].data = d;
+        }
+        self.update(tb_name, ptr, record, new);
+    }
+
+    fn primary_cols(&self, tb_name: &String) -> Option<Vec<u32>> {
+        let th = self.sm.open_table(tb_name, false).unwrap();
+        defer!(th.close());
+        th.get_primary_column_index()
+    }
+
+    fn referencing_tables(&mut self, tb_name: &String) -> Vec<String> {
+        let sm = self.sm;
+        self.referencing.entry(tb_name.clone()).or_insert_with(|| {
+            sm.get_tables().into_iter().filter(|name| {
+                let th = sm.open_table(name, false).unwrap();
+                defer!(th.close());
+                th.get_column_types().cols.iter().any(|ct| ct.is_foreign && &ct.foreign_table_name == tb_name)
+            }).collect()
+        }).clone()
+    }
+
+    // the row `old` of `tb_name` is deleted, or its primary key becomes that of `new`
+    fn release(&mut self, tb_name: &String, old: &Record, new: Option<&Record>) {
+        let primary_cols = match self.primary_cols(tb_name) {
+            Some(cols) => cols,
+            None => return,
+        };
+        let old_key: Vec<Data> = key(old, &primary_cols).into_iter().map(Option::unwrap).collect();
+
+        for name in self.referencing_tables(tb_name) {
+            // (columns, action, rows) of every foreign key of `name` referencing the row
+            let mut references = Vec::new();
+            let ty = {
+                let fth = self.sm.open_table(&name, false).unwrap();
+                defer!(fth.close());
+                for btree in fth.get_btrees().into_iter().filter(|t| t.is_foreign() && t.get_foreign_table_name() == tb_name) {
+                    let (on_delete, on_update) = btree.get_foreign_actions();
+                    let ptrs = btree.search_record(&RawIndex::from_record(old, &primary_cols)).map_or(Vec::new(), |bucket| bucket.data);
+                    // a NULL is kept in the btree as a zero, so only rows holding the very key count
+                    let rows: Vec<(u64, Record)> = ptrs.into_iter().map(|ptr| (ptr, fth.get_record_(ptr).0))
+                        .filter(|(_, record)| key(record, &btree.index_col).into_iter().zip(old_key.iter()).all(|(d, k)| d.as_ref() == Some(k)))
+                        .collect();
+                    references.push((btree.index_col.clone(), if new.is_some() {on_update} else {on_delete}, rows));
+                }
+                fth.get_column_types().cols
+            };
+
+            for (cols, action, rows) in references {
+                for (ptr, record) in rows {
+                    match action {
+                        ReferentialAction::Cascade => match new {
+                            Some(new) => self.set(&name, ptr, record, &cols, key(new, &primary_cols)),
+                            None => self.delete(&name, ptr, record),
+                        },
+                        ReferentialAction::SetNull => self.set(&name, ptr, record, &cols, vec![None; cols.len()]),
+                        ReferentialAction::SetDefault => {
+                            let data: Vec<Option<Data>> = cols.iter().map(|i| {
+                                let ct = &ty[*i as usize];
+                                Data::from_value(&ct.default_value(), ct)
+                            }).collect();
+                            if data.iter().all(Option::is_some) {
+                                self.defaults.push((tb_name.clone(), data.iter().cloned().map(Option::unwrap).collect()));
+                            }
+                            self.set(&name, ptr, record, &cols, data);
+                        },
+                        ReferentialAction::Restrict => self.restricted = true,
+                        ReferentialAction::NoAction => self.held.push((name.clone(), ptr, cols.clone(), old_key.clone())),
+                    }
+                }
+            }
+        }
+    }
+
+    // whether a row of `tb_name` still has the primary key `key` once the statement is done
+    fn keeps_key(&self, tb_name: &String, key_data: &Vec<Data>) -> bool {
+        let th = self.sm.open_table(tb_name, false).unwrap();
+        defer!(th.close());
+        let primary_cols = th.get_primary_column_index().unwrap();
+        let wanted: Vec<Option<Data>> = key_data.iter().cloned().map(Some).collect();
+        let no_rows = HashMap::new();
+        let rows = self.changes.get(tb_name).unwrap_or(&no_rows);
+
+        let stored = th.get_primary_btree().unwrap().search_record(&RawIndex { index: key_data.clone() }).map_or(false, |bucket| {
+            bucket.data.iter().any(|ptr| match rows.get(ptr) {
+                Some((_, Some(new))) => key(new, &primary_cols) == wanted,
+                Some((_, None)) => false,
+                None => true,
+            })
+        });
+        stored || rows.values().any(|(_, new)| new.as_ref().map_or(false, |new| key(new, &primary_cols) == wanted))
+    }
+
+    // whether the rows may end up as worked out, the same checks an insert or an update of them would make
+    pub fn finish(&self) -> bool {
+        if self.restricted {
+            return false;
+        }
+
+        for (tb_name, ptr, cols, old_key) in &self.held {
+            let released = match self.changes.get(tb_name).and_then(|rows| rows.get(ptr)) {
+                Some((_, Some(new))) => key(new, cols).into_iter().zip(old_key.iter()).any(|(d, k)| d.as_ref() != Some(k)),
+                Some((_, None)) => true,
+                None => false,
+            };
+            if !released {
+                return false;
+            }
+        }
+
+        if !self.defaults.iter().all(|(tb_name, key_data)| self.keeps_key(tb_name, key_data)) {
+            return false;
+        }
+
+        for (tb_name, rows) in &self.changes {
+            let th = self.sm.open_table(tb_name, false).unwrap();
+            defer!(th.close());
+            let ty = th.get_column_types().cols;
+            let records: Vec<Record> = rows.values().filter_map(|(_, new)| new.clone()).collect();
+            let replaced: HashSet<u64> = rows.keys().cloned().collect();
+            let keys: Vec<BTree> = th.get_btrees().into_iter().filter(|t| t.is_primary() || t.is_unique()).collect();
+
+            let not_null = records.iter().all(|record| ty.iter().all(|ct| ct.can_be_null || record.cols[ct.index as usize].data.is_some()));
+            if !not_null || !check::check_unique(&th, &keys, &records, &replaced) || !check::check_constraints(&th, &records, &ty) {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    pub fn apply(self) {
+        let sm = self.sm;
+        for (tb_name, rows) in self.changes {
+            let th = sm.open_table(&tb_name, false).unwrap();
+            // a value set past the counter of an auto-increment column moves the counter on
+            if let Some(i) = th.get_column_types().cols.iter().position(|ct| ct.auto_increment) {
+                let top = rows.values().filter_map(|(_, new)| match new.as_ref().and_then(|new| new.cols[i].data.as_ref()) {
+                    Some(Data::Int(d)) => Some(*d),
+                    _ => None,
+                }).max();
+                if let Some(top) = top.filter(|top| *top > th.get_auto_increment()) {
+                    th.set_auto_increment(top);
+                }
+            }
+            let mut born_btree = th.get_born_btree();
+            let mut btrees = th.get_btrees_with_ptrs();
+            for (ptr, (old, new)) in rows {
+                match new {
+                    Some(new) => {
+                        for (_, btree) in &mut btrees {
+                            let (from, to) = (RawIndex::from_record(&old, &btree.index_col), RawIndex::from_record(&new, &btree.index_col));
+                            if from.index != to.index {
+                                btree.delete_record(&from, ptr);
+                                btree.insert_record(&to, ptr, true);
+                            }
+                        }
+                        th.update_record_(ptr, &new);
+                    },
+                    None => {
+                        born_btree.delete_record(&RawIndex::from_u64(ptr), ptr);
+                        for (_, btree) in &mut btrees {
+                            btree.delete_record(&RawIndex::from_record(&old, &btree.index_col), ptr);
+                        }
+                        th.delete_(ptr);
+                    },
+                }
+            }
+            th.update_born_btree(&born_btree);
+            for (p, btree) in &btrees {
+                th.update_btree(p, btree);
+            }
+            th.close();
+        }
+    }
+}
@@ -0,0 +1,131 @@
+use std::str::FromStr;
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::parser::ast::*;
+
+// a sequence as its file keeps it, `increment next [current]`
+pub struct Sequence {
+    pub increment: i64,
+    pub next: i64,
+    // what the last nextval gave, currval has nothing to give before it
+    pub current: Option<i64>,
+}
+
+impl Sequence {
+    // an ascending sequence starts from 1 and a descending one from -1 unless told otherwise
+    pub fn new(increment: i64, start: Option<i64>) -> Self {
+        Self {
+            increment: increment,
+            next: start.unwrap_or(if increment > 0 {1} else {-1}),
+            current: None,
+        }
+    }
+
+    pub fn parse(text: &str) -> Option<Self> {
+        let numbers: Vec<i64> = text.split_whitespace().map(i64::from_str).collect::<Result<_, _>>().ok()?;
+        match numbers.as_slice() {
+            [increment, next] => Some(Self { increment: *increment, next: *next, current: None }),
+            [increment, next, current] => Some(Self { increment: *increment, next: *next, current: Some(*current) }),
+            _ => None,
+        }
+    }
+
+    pub fn to_string(&self) -> String {
+        match self.current {
+            Some(current) => format!("{} {} {}", self.increment, self.next, current),
+            None => format!("{} {}", self.increment, self.next),
+        }
+    }
+
+    // None once the values run out of i64
+    pub fn nextval(&mut self) -> Option<i64> {
+        let value = self.next;
+        self.next = value.checked_add(self.increment)?;
+        self.current = Some(value);
+        Some(value)
+    }
+}
+
+// a sequence is a file next to the tables, see `Sequence` for what it holds
+pub fn path(root_dir: &str, database: &str, seq_name: &str) -> PathBuf {
+    let mut path: PathBuf = [root_dir, database, seq_name].iter().collect();
+    path.set_extension("seq");
+    path
+}
+
+// the sequences one statement has read, taking numbers moves them on in memory until `store`
+pub struct Sequences {
+    root_dir: String,
+    database: String,
+    taken: HashMap<String, Sequence>,
+}
+
+impl Sequences {
+    pub fn new(root_dir: &str, database: &str) -> Self {
+        Self {
+            root_dir: root_dir.to_string(),
+            database: database.to_string(),
+            taken: HashMap::new(),
+        }
+    }
+
+    // what nextval gives when `next` is set and what currval gives otherwise
+    pub fn take(&mut self, seq_name: &str, next: bool) -> Result<i64, String> {
+        if !self.taken.contains_key(seq_name) {
+            let sequence = fs::read_to_string(path(&self.root_dir, &self.database, seq_name)).ok()
+                .and_then(|text| Sequence::parse(&text))
+                .ok_or(format!("sequence {} doesn't exist", seq_name))?;
+            self.taken.insert(seq_name.to_string(), sequence);
+        }
+        let sequence = self.taken.get_mut(seq_name).unwrap();
+        match next {
+            true => sequence.nextval().ok_or(format!("sequence {} has run out of values", seq_name)),
+            false => sequence.current.ok_or(format!("currval of sequence {} is not yet defined", seq_name)),
+        }
+    }
+
+    // puts the numbers of nextval and currval in their place, left to right,
+    // every branch of a CASE takes its numbers whichever one is chosen
+    pub fn resolve(&mut self, expr: &Expr) -> Result<Expr, String> {
+        Ok(match expr {
+            Expr::Func { func: Func::NextVal, args } | Expr::Func { func: Func::CurrVal, args } => {
+                let seq_name = match args.as_slice() {
+                    [Expr::Value(Value::Str(seq_name))] => seq_name,
+                    _ => return Err("invalid sequence function".to_string()),
+                };
+                let next = match expr {
+                    Expr::Func { func: Func::NextVal, .. } => true,
+                    _ => false,
+                };
+                Expr::Value(Value::Int(self.take(seq_name, next)?.to_string()))
+            },
+            Expr::Binary { op, left, right } => Expr::Binary {
+                op: op.clone(),
+                left: Box::new(self.resolve(left)?),
+                right: Box::new(self.resolve(right)?),
+            },
+            Expr::Neg(expr) => Expr::Neg(Box::new(self.resolve(expr)?)),
+            Expr::Func { func, args } => Expr::Func {
+                func: func.clone(),
+                args: args.iter().map(|arg| self.resolve(arg)).collect::<Result<_, _>>()?,
+            },
+            Expr::Case { whens, else_ } => Expr::Case {
+                whens: whens.iter().map(|(cond, expr)| Ok((cond.clone(), self.resolve(expr)?))).collect::<Result<_, String>>()?,
+                else_: match else_ {
+                    Some(expr) => Some(Box::new(self.resolve(expr)?)),
+                    None => None,
+                },
+            },
+            Expr::Cast { expr, ty } => Expr::Cast { expr: Box::new(self.resolve(expr)?), ty: ty.clone() },
+            _ => expr.clone(),
+        })
+    }
+
+    pub fn store(&self) {
+        for (seq_name, sequence) in &self.taken {
+            assert!(fs::write(path(&self.root_dir, &self.database, seq_name), sequence.to_string()).is_ok());
+        }
+    }
+}
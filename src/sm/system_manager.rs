@@ -6,20 +6,28 @@ use crate::rm::record_manager::*;
 use crate::rm::table_handler::TableHandler;
 use crate::settings;
 use crate::parser::ast::*;
+use crate::parser::sql;
 use crate::index::btree::*;
+use crate::defer;
 
 use super::query_tree::*;
 use super::check;
+use super::sequence;
+use super::sequence::{Sequence, Sequences};
+use super::referential::Propagation;
 
 use std::path::PathBuf;
 use std::fs;
 use std::rc::Rc;
 use std::cell::RefCell;
+use std::collections::HashMap;
 
 
 pub struct SystemManager {
     pub root_dir: String,
     pub check: bool,
+    // checking a prepared statement, the checks that read rows wait for its values
+    pub prepare: bool,
     pub current_database: Option<String>,
     pub rm: Rc<RefCell<RecordManager>>,
 }
@@ -39,6 +47,7 @@ impl SystemManager {
             rm: rm,
             root_dir: rd,
             check: false,
+            prepare: false,
             current_database: None,
         }
     }
@@ -47,6 +56,10 @@ impl SystemManager {
         self.check = check;
     }
 
+    pub fn set_prepare(&mut self, prepare: bool) {
+        self.prepare = prepare;
+    }
+
     pub fn get_database_path(&self, db_name: &String) -> PathBuf {
         let path: PathBuf = [self.root_dir.clone(), db_name.clone()].iter().collect();
         path
@@ -58,6 +71,49 @@ impl SystemManager {
         path
     }
 
+    // a view is kept next to the tables as a file holding its select
+    pub fn get_view_path(&self, database: &str, view_name: &str) -> PathBuf {
+        let mut path: PathBuf = [self.root_dir.clone(), database.to_string(), view_name.to_string()].iter().collect();
+        path.set_extension("view");
+        path
+    }
+
+    pub fn get_views(&self) -> Vec<String> {
+        assert!(self.current_database.is_some());
+        let dir: PathBuf = [self.root_dir.clone(), self.current_database.as_ref().unwrap().clone()].iter().collect();
+        fs::read_dir(dir).unwrap().filter_map(
+            |e| {
+                let p = e.unwrap().path();
+                if p.extension().unwrap() == "view" {
+                    Some(p.file_stem().unwrap().to_str().unwrap().to_string())
+                } else {
+                    None
+                }
+            }
+        ).collect()
+    }
+
+    pub fn get_view(&self, view_name: &str) -> Option<TableStmt> {
+        let path = self.get_view_path(self.current_database.as_ref()?, view_name);
+        let definition = fs::read_to_string(path).ok()?;
+        sql::parse_select(&definition)
+    }
+
+    // a sequence is a file next to the tables as well, see `Sequence` for what it holds
+    pub fn get_sequence_path(&self, database: &str, seq_name: &str) -> PathBuf {
+        sequence::path(&self.root_dir, database, seq_name)
+    }
+
+    fn put_sequence(&self, seq_name: &str, sequence: &Sequence) {
+        let path = self.get_sequence_path(self.current_database.as_ref().unwrap(), seq_name);
+        assert!(fs::write(path, sequence.to_string()).is_ok());
+    }
+
+    // the numbers one statement takes from the sequences of the current database
+    fn sequences(&self) -> Sequences {
+        Sequences::new(&self.root_dir, self.current_database.as_ref().unwrap())
+    }
+
     pub fn get_tables(&self) -> Vec<String> {
         assert!(self.current_database.is_some());
         let dir: PathBuf = [self.root_dir.clone(), self.current_database.as_ref().unwrap().clone()].iter().collect();
@@ -103,12 +159,16 @@ impl SystemManager {
         if let Some(ref cdb) = self.current_database {
             let mut path: PathBuf = [self.root_dir.clone(), cdb.clone(), tb_name.clone()].iter().collect();
             path.set_extension("rua");
+            let is_view = self.get_view_path(cdb, tb_name).is_file();
             if !path.is_file() && should_exist {
-                RuaResult::err("table doesn't exist".to_string())
+                RuaResult::err(if is_view {format!("{} is a view", tb_name)} else {"table doesn't exist".to_string()})
             }
             else if path.is_file() && !should_exist {
                 RuaResult::err("table exists".to_string())
             }
+            else if is_view && !should_exist {
+                RuaResult::err("view exists".to_string())
+            }
             else {
                 RuaResult::default()
             }
@@ -118,6 +178,25 @@ impl SystemManager {
         }
     }
 
+    fn check_view_existence(&self, view_name: &String) -> RuaResult {
+        match self.current_database {
+            Some(ref cdb) if self.get_view_path(cdb, view_name).is_file() => RuaResult::default(),
+            Some(_) => RuaResult::err("view doesn't exist".to_string()),
+            None => RuaResult::err("not use any database".to_string()),
+        }
+    }
+
+    // what a select may read from
+    fn check_relation_existence(&self, tb_name: &String) -> RuaResult {
+        let res = self.check_table_existence(tb_name, true);
+        if res.is_err() && self.check_view_existence(tb_name).is_ok() {
+            RuaResult::default()
+        }
+        else {
+            res
+        }
+    }
+
     pub fn create_database(&self, db_name: &String) -> RuaResult {
         if self.check {
             self.check_database_existence(db_name, false)
@@ -179,10 +258,12 @@ impl SystemManager {
         else {
             let database = self.current_database.as_ref().unwrap();
             let path = self.get_database_path(database);
-            let mut res = vec![vec![format!("Tables_in_{}", database)]];
+            let mut res = vec![vec![format!("Tables_in_{}", database), "Table_type".to_string()]];
             let tables: Vec<String> = self.get_tables();
-            let count = tables.len();
-            res.push(vec![tables.join("\n")]);
+            let views: Vec<String> = self.get_views();
+            let count = tables.len() + views.len();
+            let types: Vec<&str> = tables.iter().map(|_| "BASE TABLE").chain(views.iter().map(|_| "VIEW")).collect();
+            res.push(vec![tables.iter().chain(views.iter()).cloned().collect::<Vec<String>>().join("\n"), types.join("\n")]);
             RuaResult::ok(Some(res), format!("{} row(s) in set", count))
         }
     }
@@ -201,21 +282,130 @@ impl SystemManager {
             }
         }
         else {
-            let (columns, primary_cols, foreign_indexes) = ColumnTypeVec::from_fields(field_list, tb_name);
-            let th = self.open_table(tb_name, true).unwrap();
-            th.insert_column_types(&columns);
-            th.init_btrees();
-            th.insert_born_btree(&BTree::new(&th, vec![], "", 0));
-            if primary_cols.len() > 0 {
-                th.insert_btree(&BTree::new(&th, primary_cols, "", BTree::primary_ty()));
+            let (columns, primary_cols, foreign_indexes, unique_indexes) = ColumnTypeVec::from_fields(field_list, tb_name);
+            let checks = CheckConstraint::from_fields(field_list, tb_name);
+            self.create_table_file(tb_name, &columns, primary_cols, foreign_indexes, unique_indexes, checks);
+            RuaResult::ok(None, "table created".to_string())
+        }
+    }
+
+    fn create_table_file(&self, tb_name: &String, columns: &ColumnTypeVec, primary_cols: Vec<u32>, foreign_indexes: Vec<(&String, Vec<u32>, ReferentialAction, ReferentialAction)>, unique_indexes: Vec<Vec<u32>>, checks: Vec<CheckConstraint>) {
+        let th = self.open_table(tb_name, true).unwrap();
+        th.insert_column_types(columns);
+        th.init_btrees();
+        th.insert_born_btree(&BTree::new(&th, vec![], "", 0));
+        if primary_cols.len() > 0 {
+            th.insert_btree(&BTree::new(&th, primary_cols, "", BTree::primary_ty()));
+        }
+
+        for (ft_name, foreign_index, on_delete, on_update) in foreign_indexes {
+            th.insert_btree(&BTree::new(&th, foreign_index, BTree::foreign_index_name("foreign", ft_name, on_delete, on_update).as_str(), BTree::foreign_ty()));
+        }
+
+        for unique_index in unique_indexes {
+            let name = unique_index.iter().map(|i| columns.cols[*i as usize].name.clone()).collect::<Vec<String>>().join(",");
+            th.insert_btree(&BTree::new(&th, unique_index, format!("unique {}", name).as_str(), BTree::unique_ty()));
+        }
+
+        for check in &checks {
+            th.insert_check(check);
+        }
+
+        th.close();
+    }
+
+    pub fn create_table_as(&self, tb_name: &String, query: &TableStmt) -> RuaResult {
+        if self.check {
+            let res = self.check_table_existence(tb_name, false);
+            if res.is_err() {
+                return res;
+            }
+            match check::check_subquery(query, &HashMap::new(), self) {
+                Some(cts) => if check::check_no_repeat(&cts.iter().map(|ct| ct.name.clone()).collect()) {
+                    RuaResult::default()
+                } else {
+                    RuaResult::err("duplicate column name, use an alias".to_string())
+                },
+                None => RuaResult::err("invalid select".to_string()),
+            }
+        }
+        else {
+            let database = self.current_database.as_ref().unwrap();
+            let tree = QueryTree::new(&self.root_dir, database, self.rm.clone());
+            let record_list = tree.run_select(query);
+            if let Some(e) = tree.error() {
+                return RuaResult::err(e);
+            }
+            let columns = ColumnTypeVec::from_record_types(&record_list.ty, tb_name);
+            self.create_table_file(tb_name, &columns, Vec::new(), Vec::new(), Vec::new(), Vec::new());
+            let records: Vec<Record> = record_list.record.into_iter().map(|record| {
+                Record::from_data(record.cols.into_iter().map(|col| col.data).collect(), &columns.cols)
+            }).collect();
+            self.insert_records(tb_name, &records);
+            RuaResult::ok(None, format!("table created, {} rows affected", records.len()))
+        }
+    }
+
+    pub fn create_view(&self, view_name: &String, query: &TableStmt, definition: &String) -> RuaResult {
+        if self.check {
+            let res = self.check_table_existence(view_name, false);
+            if res.is_err() {
+                return res;
             }
+            match check::check_subquery(query, &HashMap::new(), self) {
+                Some(cts) => if check::check_no_repeat(&cts.iter().map(|ct| ct.name.clone()).collect()) {
+                    RuaResult::default()
+                } else {
+                    RuaResult::err("duplicate column name, use an alias".to_string())
+                },
+                None => RuaResult::err("invalid select".to_string()),
+            }
+        }
+        else {
+            let path = self.get_view_path(self.current_database.as_ref().unwrap(), view_name);
+            assert!(fs::write(path, definition).is_ok());
+            RuaResult::ok(None, "view created".to_string())
+        }
+    }
 
-            for (ft_name, foreign_index) in foreign_indexes {
-                th.insert_btree(&BTree::new(&th, foreign_index, format!("foreign {}", ft_name).as_str(), BTree::foreign_ty()));
+    pub fn drop_view(&self, view_name: &String) -> RuaResult {
+        if self.check {
+            self.check_view_existence(view_name)
+        }
+        else {
+            let path = self.get_view_path(self.current_database.as_ref().unwrap(), view_name);
+            assert!(fs::remove_file(path).is_ok());
+            RuaResult::ok(None, "view dropped".to_string())
+        }
+    }
+
+    pub fn create_sequence(&self, seq_name: &String, increment: &Option<i64>, start: &Option<i64>) -> RuaResult {
+        if self.check {
+            match self.current_database {
+                Some(ref cdb) if self.get_sequence_path(cdb, seq_name).is_file() => RuaResult::err("sequence exists".to_string()),
+                Some(_) if *increment == Some(0) => RuaResult::err("the increment of a sequence cannot be 0".to_string()),
+                Some(_) => RuaResult::default(),
+                None => RuaResult::err("not use any database".to_string()),
             }
+        }
+        else {
+            self.put_sequence(seq_name, &Sequence::new(increment.unwrap_or(1), *start));
+            RuaResult::ok(None, "sequence created".to_string())
+        }
+    }
 
-            th.close();
-            RuaResult::ok(None, "table created".to_string())
+    pub fn drop_sequence(&self, seq_name: &String) -> RuaResult {
+        if self.check {
+            match self.current_database {
+                Some(ref cdb) if self.get_sequence_path(cdb, seq_name).is_file() => RuaResult::default(),
+                Some(_) => RuaResult::err("sequence doesn't exist".to_string()),
+                None => RuaResult::err("not use any database".to_string()),
+            }
+        }
+        else {
+            let path = self.get_sequence_path(self.current_database.as_ref().unwrap(), seq_name);
+            assert!(fs::remove_file(path).is_ok());
+            RuaResult::ok(None, "sequence dropped".to_string())
         }
     }
 
@@ -247,70 +437,246 @@ impl SystemManager {
         else {
             let th = self.open_table(tb_name, false).unwrap();
             let cts = th.get_column_types();
+            let checks = th.get_checks();
             th.close();
             let title = vec!["Field", "Type", "Null", "Key", "Default", "Foreign"].iter().map(|x| x.to_string()).collect();
-            let print_content = cts.print();
-            RuaResult::ok(Some(vec![title, print_content]), format!("{} row(s) in set", cts.cols.len()))
+            let mut print_content = cts.print();
+            // the checks follow the columns under their names
+            for check in &checks {
+                let content = vec![check.name.clone(), format!("CHECK ({})", check.definition), String::new(), String::new(), String::new(), String::new()];
+                for (cell, value) in print_content.iter_mut().zip(content) {
+                    cell.push('\n');
+                    cell.push_str(&value);
+                }
+            }
+            RuaResult::ok(Some(vec![title, print_content]), format!("{} row(s) in set", cts.cols.len() + checks.len()))
         }
     }
 
-    pub fn insert(&self, tb_name: &String, value_lists: &Vec<Vec<Value>>) -> RuaResult {
+    pub fn insert(&self, tb_name: &String, column_list: &Option<Vec<Name>>, value_lists: &Vec<Vec<Value>>) -> RuaResult {
         if self.check {
             let res = self.check_table_existence(tb_name, true);
             if res.is_err() {
-                res
-            } else {
-                if check::check_insert_value(tb_name, value_lists, &self) {
-                    RuaResult::default()
-                } else {
-                    RuaResult::err("invalid insert values".to_string())
-                }
+                return res;
+            }
+            let mut value_lists = value_lists.clone();
+            if let Err(message) = self.take_sequence_values(&mut value_lists, false) {
+                return RuaResult::err(message);
+            }
+            match self.complete_values(tb_name, column_list, &value_lists) {
+                Some(mut value_lists) => {
+                    if let Err(message) = self.fill_auto_increment(tb_name, &mut value_lists, false) {
+                        return RuaResult::err(message);
+                    }
+                    if check::check_insert_value(tb_name, &value_lists, &self) {
+                        RuaResult::default()
+                    } else {
+                        RuaResult::err("invalid insert values".to_string())
+                    }
+                },
+                None => RuaResult::err("invalid insert columns".to_string()),
             }
         }
         else {
-            let th = self.open_table(tb_name, false).unwrap();
-            let cts = th.get_column_types();
-            let records: Vec<Record> = value_lists.iter().map(|v| Record::from_value_lists(v, &cts.cols)).collect();
-            let ptrs: Vec<(StrPointer, RecordInFile)> = records.iter().map(|record| th.insert_record_get_record_in_file(record)).collect();
+            let mut value_lists = value_lists.clone();
+            self.take_sequence_values(&mut value_lists, true).unwrap();
+            let mut value_lists = self.complete_values(tb_name, column_list, &value_lists).unwrap();
+            self.fill_auto_increment(tb_name, &mut value_lists, true).unwrap();
+            self.insert_values(tb_name, &value_lists)
+        }
+    }
 
-            let mut born_btree = th.get_born_btree();
-            let mut btrees = th.get_btrees_with_ptrs();
-            for (ptr, rif) in ptrs {
-                born_btree.insert_record(&RawIndex::from_u64(ptr.to_u64()), ptr.to_u64(), true);
-                // TODO: take advantage of cache
-                for (_, btree) in &mut btrees {
-                    btree.insert_record(&RawIndex::from(&rif.get_index(&th, &btree.index_col)), ptr.to_u64(), true);
+    // puts the numbers nextval and currval give in their place, in the order they are written,
+    // the check only looks ahead and `advance` moves the sequences on
+    fn take_sequence_values(&self, value_lists: &mut Vec<Vec<Value>>, advance: bool) -> Result<(), String> {
+        let mut sequences = self.sequences();
+        for value in value_lists.iter_mut().flatten() {
+            let number = match value {
+                Value::NextVal(seq_name) => sequences.take(seq_name, true)?,
+                Value::CurrVal(seq_name) => sequences.take(seq_name, false)?,
+                _ => continue,
+            };
+            *value = Value::Int(number.to_string());
+        }
+        if advance {
+            sequences.store();
+        }
+        Ok(())
+    }
+
+    // a NULL in the auto-increment column becomes the next value of the table's counter, a larger value
+    // given for it moves the counter on, the check only looks ahead and `advance` stores the counter
+    fn fill_auto_increment(&self, tb_name: &String, value_lists: &mut Vec<Vec<Value>>, advance: bool) -> Result<(), String> {
+        let th = self.open_table(tb_name, false).unwrap();
+        defer!(th.close());
+        let cols = th.get_column_types().cols;
+        if let Some(i) = cols.iter().position(|ct| ct.auto_increment) {
+            let mut counter = th.get_auto_increment();
+            for values in value_lists.iter_mut().filter(|values| values.len() > i) {
+                match &values[i] {
+                    Value::Null => {
+                        counter = counter.checked_add(1).ok_or(format!("column {} has run out of values", cols[i].name))?;
+                        values[i] = Value::Int(counter.to_string());
+                    },
+                    Value::Int(s) => counter = counter.max(s.parse().unwrap_or(counter)),
+                    _ => {},
                 }
             }
+            if advance {
+                th.set_auto_increment(counter);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn insert_select(&self, tb_name: &String, column_list: &Option<Vec<Name>>, query: &TableStmt) -> RuaResult {
+        if self.check {
+            let res = self.check_table_existence(tb_name, true);
+            if res.is_err() {
+                return res;
+            }
+            let targets = match self.target_columns(tb_name, column_list) {
+                Some(targets) => targets,
+                None => return RuaResult::err("invalid insert columns".to_string()),
+            };
+            let valid = match check::check_subquery(query, &HashMap::new(), self) {
+                Some(cts) => cts.len() == targets.len() && targets.iter().zip(cts.iter()).all(|(target, ct)| target.accepts(ct)),
+                None => false,
+            };
+            if !valid {
+                return RuaResult::err("invalid select".to_string());
+            }
+            if self.prepare {
+                return RuaResult::default();
+            }
+            let value_lists = match self.select_values(query, &targets, false) {
+                Ok(value_lists) => value_lists,
+                Err(e) => return RuaResult::err(e),
+            };
+            match self.complete_values(tb_name, column_list, &value_lists) {
+                Some(mut value_lists) => {
+                    if let Err(message) = self.fill_auto_increment(tb_name, &mut value_lists, false) {
+                        return RuaResult::err(message);
+                    }
+                    if check::check_insert_value(tb_name, &value_lists, &self) {
+                        RuaResult::default()
+                    } else {
+                        RuaResult::err("invalid insert values".to_string())
+                    }
+                },
+                None => RuaResult::err("invalid insert values".to_string()),
+            }
+        }
+        else {
+            let targets = self.target_columns(tb_name, column_list).unwrap();
+            let value_lists = self.select_values(query, &targets, true).unwrap();
+            let mut value_lists = self.complete_values(tb_name, column_list, &value_lists).unwrap();
+            self.fill_auto_increment(tb_name, &mut value_lists, true).unwrap();
+            self.insert_values(tb_name, &value_lists)
+        }
+    }
 
-            th.update_born_btree(&born_btree);
-            for (p, btree) in &btrees {
-                th.update_btree(p, btree);
+    // the columns an insert gives values for, in the order of its column list
+    fn target_columns(&self, tb_name: &String, column_list: &Option<Vec<Name>>) -> Option<Vec<ColumnType>> {
+        let th = self.open_table(tb_name, false).unwrap();
+        let cols = th.get_column_types().cols;
+        th.close();
+        match column_list {
+            Some(column_list) if check::check_no_repeat(column_list) => column_list.iter().map(|name| cols.iter().find(|ct| &ct.name == name).cloned()).collect(),
+            Some(_) => None,
+            None => Some(cols),
+        }
+    }
+
+    // puts the values in table order, the columns left out get their default or NULL
+    fn complete_values(&self, tb_name: &String, column_list: &Option<Vec<Name>>, value_lists: &Vec<Vec<Value>>) -> Option<Vec<Vec<Value>>> {
+        let column_list = match column_list {
+            Some(column_list) => column_list,
+            None => return Some(value_lists.clone()),
+        };
+        let th = self.open_table(tb_name, false).unwrap();
+        let cols = th.get_column_types().cols;
+        th.close();
+        if !check::check_no_repeat(column_list) || !column_list.iter().all(|name| cols.iter().any(|ct| &ct.name == name)) {
+            return None;
+        }
+        value_lists.iter().map(|values| {
+            if values.len() != column_list.len() {
+                return None;
             }
+            Some(cols.iter().map(|ct| match column_list.iter().position(|name| name == &ct.name) {
+                Some(i) => values[i].clone(),
+                None => ct.default_value(),
+            }).collect())
+        }).collect()
+    }
 
-            th.close();
-            RuaResult::ok(None, format!("{} rows affected", records.len()))
+    // the rows of a select as literals of the columns they are inserted into
+    // the check only looks ahead and `advance` stores the sequences the select has moved on
+    fn select_values(&self, query: &TableStmt, targets: &Vec<ColumnType>, advance: bool) -> Result<Vec<Vec<Value>>, String> {
+        let database = self.current_database.as_ref().unwrap();
+        let tree = QueryTree::new(&self.root_dir, database, self.rm.clone());
+        let record_list = tree.run_select(query);
+        if let Some(e) = tree.error() {
+            return Err(e);
+        }
+        if advance {
+            tree.store_sequences();
         }
+        Ok(record_list.record.iter().map(|record| {
+            record.cols.iter().zip(record_list.ty.iter()).zip(targets.iter()).map(|((col, ct), target)| {
+                Data::to_value(&col.data.clone().and_then(|data| data.cast(ct, target)), target)
+            }).collect()
+        }).collect())
     }
 
-    pub fn select(&self, table_list: &Vec<Name>, selector: &Selector, where_clause: &Option<Vec<WhereClause>>) -> RuaResult {
+    fn insert_values(&self, tb_name: &String, value_lists: &Vec<Vec<Value>>) -> RuaResult {
+        let th = self.open_table(tb_name, false).unwrap();
+        let cts = th.get_column_types();
+        th.close();
+        let records: Vec<Record> = value_lists.iter().map(|v| Record::from_value_lists(v, &cts.cols)).collect();
+        self.insert_records(tb_name, &records);
+        RuaResult::ok(None, format!("{} rows affected", records.len()))
+    }
+
+    fn insert_records(&self, tb_name: &String, records: &Vec<Record>) {
+        let th = self.open_table(tb_name, false).unwrap();
+        let ptrs: Vec<(StrPointer, RecordInFile)> = records.iter().map(|record| th.insert_record_get_record_in_file(record)).collect();
+
+        let mut born_btree = th.get_born_btree();
+        let mut btrees = th.get_btrees_with_ptrs();
+        for (ptr, rif) in ptrs {
+            born_btree.insert_record(&RawIndex::from_u64(ptr.to_u64()), ptr.to_u64(), true);
+            // TODO: take advantage of cache
+            for (_, btree) in &mut btrees {
+                btree.insert_record(&RawIndex::from(&rif.get_index(&th, &btree.index_col)), ptr.to_u64(), true);
+            }
+        }
+
+        th.update_born_btree(&born_btree);
+        for (p, btree) in &btrees {
+            th.update_btree(p, btree);
+        }
+
+        th.close();
+    }
+
+    pub fn select(&self, distinct: &bool, table_list: &Vec<TableRef>, join_list: &Vec<Join>, selector: &Selector, where_clause: &Option<Vec<WhereClause>>, group_clause: &Option<Vec<Column>>, having_clause: &Option<Vec<HavingClause>>, order_clause: &Option<Vec<OrderClause>>, limit_clause: &Option<LimitClause>) -> RuaResult {
+        let names: Vec<Name> = table_list.iter().map(|table| table.name().clone()).collect();
         if self.check {
-            let repeat = !check::check_no_repeat(table_list);
+            let repeat = !check::check_no_repeat(&names);
             if repeat {
-                return RuaResult::err("a single table cannot be selected twice".to_string())
+                return RuaResult::err("a table name or alias cannot appear twice in the from list".to_string())
             }
-            let res = table_list.iter().map(|tb_name| self.check_table_existence(tb_name, true)).fold(RuaResult::default(), |s, v| s & v);
+            let res = table_list.iter().map(|table| self.check_relation_existence(&table.tb_name)).fold(RuaResult::default(), |s, v| s & v);
             if res.is_err() {
                 res
             } else {
-                let name_cols = table_list.iter()
-                                .map(|tb_name| {
-                                    let th = self.open_table(tb_name, false).unwrap();
-                                    let map = th.get_column_types_as_hashmap();
-                                    th.close();
-                                    (tb_name, map)
-                                }).collect();
-                let valid = check::check_select(&name_cols, selector, where_clause);
+                let name_cols = match check::get_table_cols(table_list, self) {
+                    Some(name_cols) => name_cols,
+                    None => return RuaResult::err("invalid view".to_string()),
+                };
+                let valid = check::check_join(&names, &name_cols, join_list) && check::check_select(&name_cols, &HashMap::new(), selector, where_clause, group_clause, having_clause, order_clause, self);
                 if !valid {
                     RuaResult::err("invalid select".to_string())
                 } else {
@@ -321,22 +687,103 @@ impl SystemManager {
         else {
             let database = self.current_database.as_ref().unwrap();
             let mut tree = QueryTree::new(&self.root_dir, database, self.rm.clone());
-            tree.build(table_list, selector, where_clause);
-            let record_list = tree.query();
+            tree.set_distinct(*distinct);
+            tree.set_group(group_clause, having_clause);
+            tree.set_order(order_clause);
+            tree.set_limit(limit_clause);
+            tree.set_join(join_list);
+            tree.set_alias(table_list);
+            tree.build(&names, selector, where_clause);
+            SystemManager::rows_in_set(&tree)
+        }
+    }
 
-            let record_num = record_list.record.len();
-            if record_num == 0 {
-                RuaResult::ok(None, format!("Empty set"))
-            }
-            else if record_num == 1 {
-                let print_content = record_list.print();
-                RuaResult::ok(Some(print_content), format!("{} row in set", record_num))
+    pub fn compound(&self, op: &SetOp, all: &bool, left: &TableStmt, right: &TableStmt, order_clause: &Option<Vec<OrderClause>>, limit_clause: &Option<LimitClause>) -> RuaResult {
+        if self.check {
+            let query = TableStmt::Compound {
+                op: op.clone(),
+                all: *all,
+                left: Box::new(left.clone()),
+                right: Box::new(right.clone()),
+                order_clause: order_clause.clone(),
+                limit_clause: limit_clause.clone(),
+            };
+            match check::check_subquery(&query, &HashMap::new(), self) {
+                Some(_) => RuaResult::default(),
+                None => RuaResult::err("invalid select".to_string()),
             }
-            else {
-                let print_content = record_list.print();
-                RuaResult::ok(Some(print_content), format!("{} rows in set", record_num))
+        }
+        else {
+            let database = self.current_database.as_ref().unwrap();
+            let mut tree = QueryTree::new(&self.root_dir, database, self.rm.clone());
+            tree.set_order(order_clause);
+            tree.set_limit(limit_clause);
+            tree.build_compound(op, *all, left, right);
+            SystemManager::rows_in_set(&tree)
+        }
+    }
+
+    // checks the statement as if it were run, ANALYZE runs it but only for selects
+    pub fn explain(&self, analyze: &bool, stmt: &TableStmt) -> RuaResult {
+        if self.check {
+            match stmt {
+                TableStmt::Select { distinct, table_list, join_list, selector, where_clause, group_clause, having_clause, order_clause, limit_clause } => self.select(distinct, table_list, join_list, selector, where_clause, group_clause, having_clause, order_clause, limit_clause),
+                TableStmt::Compound { op, all, left, right, order_clause, limit_clause } => self.compound(op, all, left, right, order_clause, limit_clause),
+                TableStmt::Update { .. } | TableStmt::Delete { .. } if *analyze => RuaResult::err("explain analyze only runs select".to_string()),
+                TableStmt::Update { tb_name, set_clause, where_clause } => self.update(tb_name, set_clause, where_clause),
+                TableStmt::Delete { tb_name, where_clause } => self.delete(tb_name, where_clause),
+                _ => RuaResult::err("only select, update and delete can be explained".to_string()),
             }
         }
+        else {
+            let database = self.current_database.as_ref().unwrap();
+            let mut tree = QueryTree::new(&self.root_dir, database, self.rm.clone());
+            tree.set_analyze(*analyze);
+            // update and delete read their records with a select over the whole table
+            let header = match stmt {
+                TableStmt::Update { tb_name, set_clause: _, where_clause } => {
+                    tree.build(&vec![tb_name.clone()], &Selector::All, where_clause);
+                    Some(format!("update {}", tb_name))
+                },
+                TableStmt::Delete { tb_name, where_clause } => {
+                    tree.build(&vec![tb_name.clone()], &Selector::All, where_clause);
+                    Some(format!("delete from {}", tb_name))
+                },
+                query => {
+                    tree.build_query(query, &None);
+                    None
+                },
+            };
+            let rows = if *analyze {Some(tree.query().record.len())} else {None};
+            let mut lines: Vec<String> = header.iter().cloned().collect();
+            let indent = if header.is_some() {"  "} else {""};
+            lines.extend(tree.explain().into_iter().map(|line| format!("{}{}", indent, line)));
+            let msg = match rows {
+                Some(rows) => format!("query plan, {} rows produced", rows),
+                None => "query plan".to_string(),
+            };
+            RuaResult::ok(Some(vec![vec!["QUERY PLAN".to_string()], vec![lines.join("\n")]]), msg)
+        }
+    }
+
+    fn rows_in_set(tree: &QueryTree) -> RuaResult {
+        let record_list = tree.query();
+        if let Some(e) = tree.error() {
+            return RuaResult::err(e);
+        }
+        tree.store_sequences();
+        let record_num = record_list.record.len();
+        if record_num == 0 {
+            RuaResult::ok(None, format!("Empty set"))
+        }
+        else if record_num == 1 {
+            let print_content = record_list.print();
+            RuaResult::ok(Some(print_content), format!("{} row in set", record_num))
+        }
+        else {
+            let print_content = record_list.print();
+            RuaResult::ok(Some(print_content), format!("{} rows in set", record_num))
+        }
     }
 
     pub fn delete(&self, tb_name: &String, where_clause: &Option<Vec<WhereClause>>) -> RuaResult {
@@ -363,23 +810,16 @@ impl SystemManager {
             tree.build(&vec![tb_name.clone()], &Selector::All, where_clause);
             let record_list = tree.query();
 
-            let th = self.open_table(tb_name, false).unwrap();
-            let mut born_btree = th.get_born_btree();
-            let mut btrees = th.get_btrees_with_ptrs();
-            for (ptr, record) in record_list.ptrs.iter().zip(record_list.record.iter()) {
-                born_btree.delete_record(&RawIndex::from_u64(ptr.to_u64()), ptr.to_u64());
-                for (_, btree) in &mut btrees {
-                    btree.delete_record(&RawIndex::from_record(record, &btree.index_col), ptr.to_u64());
-                }
-                th.delete(&ptr);
-            }
-            th.update_born_btree(&born_btree);
-            for (p, btree) in &btrees {
-                th.update_btree(p, btree);
+            let l = record_list.ptrs.len();
+
+            // the rows referencing them go as their foreign keys say
+            let mut propagation = Propagation::new(self);
+            for (ptr, record) in record_list.ptrs.into_iter().zip(record_list.record.into_iter()) {
+                propagation.delete(tb_name, ptr.to_u64(), record);
             }
+            propagation.apply();
 
-            th.close();
-            RuaResult::ok(None, format!("{} rows affected", record_list.ptrs.len()))
+            RuaResult::ok(None, format!("{} rows affected", l))
         }
     }
 
@@ -407,32 +847,40 @@ impl SystemManager {
             tree.build(&vec![tb_name.clone()], &Selector::All, where_clause);
             let record_list = tree.query();
 
-            let th = self.open_table(tb_name, false).unwrap();
-            let mut affected_btrees = th.get_affected_btrees_with_ptrs(&set_clause.iter().map(|s| &s.col_name).collect());
-
             let l = record_list.ptrs.len();
 
-            for (ptr, mut record) in record_list.ptrs.into_iter().zip(record_list.record.into_iter()) {
-                let origin_record = record.clone();
-                record.set_(set_clause, &record_list.ty);
-
-                for (_, btree) in &mut affected_btrees {
-                    btree.delete_record(&RawIndex::from_record(&origin_record, &btree.index_col), ptr.to_u64());
-                    btree.insert_record(&RawIndex::from_record(&record, &btree.index_col), ptr.to_u64(), true);
-                }
-                th.update_record(&ptr, &record);
+            // a new primary key is passed on to the rows referencing the old one as their foreign keys say
+            let new_records = self.updated_records(&record_list, set_clause, true).unwrap();
+            let mut propagation = Propagation::new(self);
+            for ((ptr, record), new_record) in record_list.ptrs.into_iter().zip(record_list.record.into_iter()).zip(new_records) {
+                propagation.update(tb_name, ptr.to_u64(), record, new_record);
             }
+            propagation.apply();
 
-            for (p, btree) in &affected_btrees {
-                th.update_btree(p, btree);
-            }
-
-            th.close();
             RuaResult::ok(None, format!("{} rows affected", l))
         }
     }
 
-    pub fn create_index(&self, idx_name: &String, tb_name: &String, column_list: &Vec<String>) -> RuaResult {
+    // the records as the set clauses leave them, nextval moves on once for every record,
+    // the check only looks ahead and `advance` stores the sequences
+    pub fn updated_records(&self, record_list: &RecordList, set_clause: &Vec<SetClause>, advance: bool) -> Result<Vec<Record>, String> {
+        let mut sequences = self.sequences();
+        let new_records = record_list.record.iter().map(|record| {
+            let set_clause = set_clause.iter().map(|sub_set_clause| Ok(SetClause {
+                col_name: sub_set_clause.col_name.clone(),
+                expr: sequences.resolve(&sub_set_clause.expr)?,
+            })).collect::<Result<_, String>>()?;
+            let mut new_record = record.clone();
+            new_record.set_(&set_clause, &record_list.ty);
+            Ok(new_record)
+        }).collect::<Result<_, String>>()?;
+        if advance {
+            sequences.store();
+        }
+        Ok(new_records)
+    }
+
+    pub fn create_index(&self, idx_name: &String, tb_name: &String, column_list: &Vec<String>, unique: bool) -> RuaResult {
         if self.check {
             let exist = self.check_table_existence(tb_name, true);
             if exist.is_err() {
@@ -446,6 +894,8 @@ impl SystemManager {
                 let valid = check::check_create_index(idx_name, &map, column_list, &btrees);
                 if !valid {
                     RuaResult::err("invalid create index".to_string())
+                } else if unique && !check::check_unique_data(tb_name, column_list, self) {
+                    RuaResult::err("duplicate values for unique index".to_string())
                 } else {
                     RuaResult::default()
                 }
@@ -460,7 +910,7 @@ impl SystemManager {
             let th = self.open_table(tb_name, false).unwrap();
             let map = th.get_column_types_as_hashmap();
             let index_col: Vec<u32> = column_list.iter().map(|column_name| map.get(column_name).unwrap().index).collect();
-            let mut btree = BTree::new(&th, index_col.clone(), idx_name, BTree::index_ty());
+            let mut btree = BTree::new(&th, index_col.clone(), idx_name, if unique {BTree::unique_ty()} else {BTree::index_ty()});
 
             for ptr in &record_list.ptrs {
                 let (_, record_in_file) = th.get_record(ptr);
@@ -511,7 +961,7 @@ impl SystemManager {
                 let map = th.get_column_types_as_hashmap();
                 th.close();
 
-                let valid = check::check_add_column(&map, field);
+                let valid = check::check_add_column(tb_name, &map, field, self);
                 if !valid {
                     RuaResult::err("invalid add column".to_string())
                 } else {
@@ -533,6 +983,20 @@ impl SystemManager {
                 th.insert_record_data_column(ptr, &new_column);
             }
 
+            if let Field::ColumnField { unique: true, .. } = field {
+                let mut btree = BTree::new(&th, vec![index], format!("unique {}", new_column.name).as_str(), BTree::unique_ty());
+                for ptr in &record_list.ptrs {
+                    let (_, record_in_file) = th.get_record(ptr);
+                    btree.insert_record(&RawIndex::from(&record_in_file.get_index(&th, &vec![index])), ptr.to_u64(), true);
+                }
+                th.insert_btree(&btree);
+            }
+
+            if let Field::ColumnField { check: Some(check), .. } = field {
+                let taken = th.get_checks().into_iter().map(|c| c.name).collect();
+                th.insert_check(&CheckConstraint { name: CheckConstraint::fresh_name(tb_name, &taken), definition: check.definition.clone() });
+            }
+
             th.close();
             RuaResult::ok(None, "column added".to_string())
         }
@@ -601,8 +1065,14 @@ impl SystemManager {
                 exist
             } else {
                 let valid = check::check_change_column(tb_name, col_name, field, self);
+                let unique = match field {
+                    Field::ColumnField { unique, .. } => *unique,
+                    _ => false,
+                };
                 if !valid {
                     RuaResult::err("invalid change column".to_string())
+                } else if unique && !check::check_unique_data(tb_name, &vec![col_name.clone()], self) {
+                    RuaResult::err("duplicate values for unique index".to_string())
                 } else {
                     RuaResult::default()
                 }
@@ -615,6 +1085,9 @@ impl SystemManager {
             
             th.update_column_type_from_index(origin_col.index as usize, &new_col);
             th.close();
+            if let Field::ColumnField { unique: true, .. } = field {
+                self.create_index(&format!("unique {}", new_col.name), tb_name, &vec![new_col.name.clone()], true);
+            }
             RuaResult::default()
         }
     }
@@ -664,6 +1137,11 @@ impl SystemManager {
         }
     }
 
+    // named like the unique constraints of a create table, the rows already there have to be unique
+    pub fn add_unique(&self, tb_name: &String, column_list: &Vec<String>) -> RuaResult {
+        self.create_index(&format!("unique {}", column_list.join(",")), tb_name, column_list, true)
+    }
+
     pub fn add_primary_key(&self, tb_name: &String, column_list: &Vec<String>) -> RuaResult {
         if self.check {
             let exist = self.check_table_existence(tb_name, true);
@@ -816,13 +1294,13 @@ impl SystemManager {
             RuaResult::default()
         }
     }
-    pub fn add_constraint_foreign_key(&self, tb_name: &String, fk_name: &String, column_list: &Vec<String>, foreign_tb_name: &String, foreign_column_list: &Vec<String>)  -> RuaResult {
+    pub fn add_constraint_foreign_key(&self, tb_name: &String, fk_name: &String, column_list: &Vec<String>, foreign_tb_name: &String, foreign_column_list: &Vec<String>, on_delete: ReferentialAction, on_update: ReferentialAction)  -> RuaResult {
         if self.check {
             let exist = self.check_table_existence(tb_name, true);
             if exist.is_err() {
                 exist
             } else {
-                let valid = check::check_add_constraint_foreign_key(tb_name, fk_name, column_list, foreign_tb_name, foreign_column_list, self);
+                let valid = check::check_add_constraint_foreign_key(tb_name, fk_name, column_list, foreign_tb_name, foreign_column_list, [on_delete, on_update], self);
                 if !valid {
                     RuaResult::err("invalid add foreign key".to_string())
                 } else {
@@ -839,7 +1317,7 @@ impl SystemManager {
             let map = th.get_column_types_as_hashmap();
             let index_cols: Vec<u32> = column_list.iter().map(|name| map.get(name).unwrap().index).collect();
 
-            let mut btree = BTree::new(&th, index_cols.clone(), format!("{} {}", fk_name, foreign_tb_name).as_str(), BTree::foreign_ty());
+            let mut btree = BTree::new(&th, index_cols.clone(), BTree::foreign_index_name(fk_name, foreign_tb_name, on_delete, on_update).as_str(), BTree::foreign_ty());
 
             for (ptr, record) in record_list.ptrs.iter().zip(record_list.record.iter()) {
                 let ri = RawIndex::from_record(record, &index_cols);
@@ -895,6 +1373,70 @@ impl SystemManager {
         }
     }
 
+    pub fn add_constraint_check(&self, tb_name: &String, ck_name: &String, check: &Check) -> RuaResult {
+        if self.check {
+            let exist = self.check_table_existence(tb_name, true);
+            if exist.is_err() {
+                exist
+            } else {
+                let valid = check::check_add_constraint_check(tb_name, ck_name, check, self);
+                if !valid {
+                    RuaResult::err("invalid add check".to_string())
+                } else {
+                    RuaResult::default()
+                }
+            }
+        } else {
+            let th = self.open_table(tb_name, false).unwrap();
+            th.insert_check(&CheckConstraint { name: ck_name.clone(), definition: check.definition.clone() });
+            th.close();
+            RuaResult::ok(None, "check added".to_string())
+        }
+    }
+
+    // a check or a foreign key, whichever of the table has the name
+    pub fn drop_constraint(&self, tb_name: &String, name: &String) -> RuaResult {
+        if self.check {
+            let exist = self.check_table_existence(tb_name, true);
+            if exist.is_err() {
+                return exist;
+            }
+        }
+        let th = self.open_table(tb_name, false).unwrap();
+        let is_check = th.get_checks().iter().any(|c| &c.name == name);
+        let is_foreign = th.get_btrees().iter().any(|t| t.is_foreign() && t.get_foreign_constraint_name() == name);
+        th.close();
+        if is_check {
+            self.drop_constraint_check(tb_name, name)
+        } else if is_foreign {
+            self.drop_constraint_foreign_key(tb_name, name)
+        } else {
+            RuaResult::err("invalid drop constraint".to_string())
+        }
+    }
+
+    pub fn drop_constraint_check(&self, tb_name: &String, ck_name: &String) -> RuaResult {
+        if self.check {
+            let exist = self.check_table_existence(tb_name, true);
+            if exist.is_err() {
+                exist
+            } else {
+                let valid = check::check_drop_constraint_check(tb_name, ck_name, self);
+                if !valid {
+                    RuaResult::err("invalid drop check".to_string())
+                } else {
+                    RuaResult::default()
+                }
+            }
+        } else {
+            let th = self.open_table(tb_name, false).unwrap();
+            let i = th.get_checks().iter().position(|c| &c.name == ck_name).unwrap();
+            th.delete_check_from_index(i);
+            th.close();
+            RuaResult::ok(None, "check dropped".to_string())
+        }
+    }
+
     pub fn copy_read(&self, tb_name: &String, path_name: &String) -> Vec<Vec<Value>> {
         use std::error::Error;
         use std::fs::File;
@@ -963,7 +1505,7 @@ impl SystemManager {
         }
         else {
             let value_lists = self.copy_read(tb_name, &path_name);
-            self.insert(tb_name, &value_lists);
+            self.insert(tb_name, &None, &value_lists);
             RuaResult::default()
         }
     }
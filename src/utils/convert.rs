@@ -118,6 +118,26 @@ pub fn str_to_date(s: &str) -> u64 {
     }
 }
 
+// the current date in UTC, encoded like `str_to_date`
+pub fn today() -> u64 {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    days_to_date(SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs() / 86400) as i64)
+}
+
+// from days since 1970-01-01 to the civil date, with years starting in March so that leap days come last
+fn days_to_date(days: i64) -> u64 {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 {mp + 3} else {mp - 9};
+    let year = yoe + era * 400 + if month <= 2 {1} else {0};
+    ((year as u64) << 9) | ((month as u64) << 5) | day as u64
+}
+
 pub fn date_to_str(date: u64) -> String {
     format!("{}-{:02}-{:02}", date >> 9, date >> 5 & 0b1111, date & 0b11111)
 }
@@ -168,11 +188,13 @@ pub fn numeric_to_str(num: i64, precision: u8) -> String {
         tmp_d /= 10;
         len += 1;
     }
+    // the integer part of -0.5 is 0, so the sign has to be written explicitly
+    let sign = if num < 0 && integer == 0 { "-" } else { "" };
     if precision == 0 {
         format!("{}", integer)
     }
     else {
-        format!("{}.{}{}", integer, String::from_utf8(vec![48; precision as usize - len]).unwrap(), decimal)
+        format!("{}{}.{}{}", sign, integer, String::from_utf8(vec![48; precision as usize - len]).unwrap(), decimal)
     }
 }
 
@@ -184,4 +206,18 @@ fn convert() {
         assert_eq!(string_to_vec_u32(&vec_u32_to_string(&vec![1, 2, 3])), vec![1, 2, 3]);
         assert_eq!(string_to_vec_u64(&vec_u64_to_string(&vec![1, 2, 3])), vec![1, 2, 3]);
     }
-}
\ No newline at end of file
+    assert_eq!(numeric_to_str(str_to_numeric("-0.5", 3), 3), "-0.50");
+    assert_eq!(numeric_to_str(str_to_numeric("12.05", 3), 3), "12.05");
+}
+
+#[test]
+fn days_to_date_test() {
+    assert_eq!(days_to_date(0), str_to_date("1970-01-01"));
+    assert_eq!(days_to_date(-1), str_to_date("1969-12-31"));
+    assert_eq!(days_to_date(11016), str_to_date("2000-02-29"));
+    assert_eq!(days_to_date(11017), str_to_date("2000-03-01"));
+    assert_eq!(days_to_date(-25509), str_to_date("1900-02-28"));
+    assert_eq!(days_to_date(-25508), str_to_date("1900-03-01"));
+    assert_eq!(days_to_date(20088), str_to_date("2024-12-31"));
+    assert_eq!(days_to_date(47541), str_to_date("2100-03-01"));
+}
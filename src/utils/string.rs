@@ -23,4 +23,99 @@ pub fn copy_bytes_u8_offset(to: &mut [u8], from: &[u8], offset: usize) {
 
 pub fn from_bytes(from: &[u8], len: usize) -> String {
     std::str::from_utf8(&from[..len]).unwrap().to_owned()
-}
\ No newline at end of file
+}
+
+enum LikeToken {
+    Char(char),
+    One, // _
+    Any, // %
+}
+
+fn like_tokens(pattern: &str, escape: Option<char>) -> Vec<LikeToken> {
+    let mut tokens = Vec::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        tokens.push(match c {
+            c if Some(c) == escape => LikeToken::Char(chars.next().unwrap_or(c)),
+            '%' => LikeToken::Any,
+            '_' => LikeToken::One,
+            c => LikeToken::Char(c),
+        });
+    }
+    tokens
+}
+
+// SQL LIKE, backtracks to the last % on a mismatch
+pub fn like(text: &str, pattern: &str, escape: Option<char>) -> bool {
+    let tokens = like_tokens(pattern, escape);
+    let text: Vec<char> = text.chars().collect();
+    let (mut t, mut p) = (0, 0);
+    let mut last_any: Option<(usize, usize)> = None; // (token after the %, text position it resumes from)
+    while t < text.len() {
+        match tokens.get(p) {
+            Some(LikeToken::Any) => {
+                p += 1;
+                last_any = Some((p, t));
+            },
+            Some(LikeToken::One) => {
+                t += 1;
+                p += 1;
+            },
+            Some(LikeToken::Char(c)) if *c == text[t] => {
+                t += 1;
+                p += 1;
+            },
+            _ => match last_any {
+                Some((any_p, any_t)) => {
+                    p = any_p;
+                    t = any_t + 1;
+                    last_any = Some((any_p, t));
+                },
+                None => return false,
+            },
+        }
+    }
+    tokens[p..].iter().all(|token| match token {
+        LikeToken::Any => true,
+        _ => false,
+    })
+}
+
+// the constant part of a pattern before its first wildcard
+pub fn like_prefix(pattern: &str, escape: Option<char>) -> String {
+    let mut prefix = String::new();
+    for token in like_tokens(pattern, escape) {
+        match token {
+            LikeToken::Char(c) => prefix.push(c),
+            _ => break,
+        }
+    }
+    prefix
+}
+
+// the smallest string greater than every string starting with `prefix`
+pub fn prefix_successor(prefix: &str) -> Option<String> {
+    let mut chars: Vec<char> = prefix.chars().collect();
+    while let Some(c) = chars.pop() {
+        if let Some(next) = std::char::from_u32(c as u32 + 1) {
+            chars.push(next);
+            return Some(chars.into_iter().collect());
+        }
+    }
+    None
+}
+
+#[test]
+fn like_test() {
+    assert!(like("abcde", "abc%", None));
+    assert!(like("abc", "abc%", None));
+    assert!(!like("xabcx", "%b_d%", None));
+    assert!(like("xabcdx", "%b_d%", None));
+    assert!(like("aaab", "%a%ab", None));
+    assert!(!like("ab", "a_b", None));
+    assert!(like("50%", "50!%", Some('!')));
+    assert!(!like("500", "50!%", Some('!')));
+    assert_eq!(like_prefix("ab!%c_d%", Some('!')), "ab%c");
+    assert_eq!(prefix_successor("abc"), Some("abd".to_owned()));
+    assert_eq!(prefix_successor(""), None);
+}